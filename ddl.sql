@@ -39,5 +39,25 @@ CREATE TABLE trade_chromosomes (
   winning_trades integer,
   losing_trades integer,
   percentage_winners numeric,
-  rank integer
+  rank integer,
+  long_num_of_trades integer,
+  long_cum_pnl numeric,
+  long_mean_return numeric,
+  long_variance numeric,
+  long_kelly numeric,
+  long_winning_trades integer,
+  long_losing_trades integer,
+  long_percentage_winners numeric,
+  short_num_of_trades integer,
+  short_cum_pnl numeric,
+  short_mean_return numeric,
+  short_variance numeric,
+  short_kelly numeric,
+  short_winning_trades integer,
+  short_losing_trades integer,
+  short_percentage_winners numeric,
+  max_drawdown numeric,
+  front integer,
+  crowding_distance double precision,
+  island integer
 );
@@ -0,0 +1,198 @@
+//! Adaptive mutation and crossover rates
+//!
+//! Rates that work well on one dataset stall on another. With adaptive rates
+//! each island scales its mutation rates between generations: up when the
+//! best fitness stops improving or the chromosomes get too close to each
+//! other, and back down while the search makes progress. The crossover
+//! probability moves the other way, so a stalled island breeds more mutated
+//! copies of its parents.
+//!
+//! Settings parse from the string below. Settings that are left out keep
+//! their default and `on` uses the defaults for all of them.
+//!
+//! ```text
+//! factor=1.5,min=0.25,max=4,diversity=0.1
+//!
+//! factor     scale applied to the rates after each generation
+//! min        smallest scale of the rates
+//! max        largest scale of the rates
+//! diversity  mean distance between chromosomes below which the rates go up
+//! ```
+use serde::{Deserialize, Serialize};
+use std::f32;
+use std::fmt;
+use std::str::FromStr;
+use {Config, Mutation};
+
+/// How rates adapt to the progress of the search
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Adaptation {
+  /// Scale applied to the rates after each generation
+  pub factor: f32,
+  /// Smallest scale of the rates
+  pub min: f32,
+  /// Largest scale of the rates
+  pub max: f32,
+  /// Mean distance between chromosomes below which the rates go up
+  pub diversity: f32,
+}
+
+impl Default for Adaptation {
+  fn default() -> Adaptation {
+    Adaptation {
+      factor: 1.5,
+      min: 0.25,
+      max: 4.0,
+      diversity: 0.1,
+    }
+  }
+}
+
+impl fmt::Display for Adaptation {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "factor={:?},min={:?},max={:?},diversity={:?}",
+      self.factor, self.min, self.max, self.diversity
+    )
+  }
+}
+
+impl FromStr for Adaptation {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Adaptation, String> {
+    let mut adaptation = Adaptation::default();
+    for part in s.split(',').filter(|p| !p.is_empty() && *p != "on") {
+      let mut kv = part.splitn(2, '=');
+      let key = kv.next().unwrap_or("");
+      let value: f32 = match kv.next().map(|v| v.parse()) {
+        Some(Ok(value)) => value,
+        _ => return Err(format!("invalid adaptive setting `{}`", part)),
+      };
+      match key {
+        "factor" => adaptation.factor = value,
+        "min" => adaptation.min = value,
+        "max" => adaptation.max = value,
+        "diversity" => adaptation.diversity = value,
+        _ => return Err(format!("unknown adaptive setting `{}`", key)),
+      }
+    }
+    if adaptation.factor < 1.0 {
+      return Err("adaptive factor should be at least 1".to_string());
+    }
+    if !(adaptation.min > 0.0 && adaptation.min <= adaptation.max) {
+      return Err("adaptive min should be above 0 and no greater than max".to_string());
+    }
+    Ok(adaptation)
+  }
+}
+
+/// Mutation and crossover rates of an island as they adapt
+///
+/// The rates the island started with are kept so that they can be scaled
+/// again every generation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdaptiveRates {
+  mutation: Mutation,
+  crossover_prob: f32,
+  /// Scale of the rates in effect
+  pub scale: f32,
+  best_fitness: f32,
+}
+
+impl AdaptiveRates {
+  /// Starts from the rates in the config
+  pub fn new(config: &Config) -> AdaptiveRates {
+    AdaptiveRates {
+      mutation: config.mutation.clone(),
+      crossover_prob: config.crossover_prob,
+      scale: 1.0,
+      best_fitness: f32::NEG_INFINITY,
+    }
+  }
+
+  /// Scales the rates after a generation
+  ///
+  /// The rates go up when the best fitness didn't improve by more than
+  /// `epsilon` or the diversity is below the setting, and go down otherwise.
+  pub fn update(&mut self, best_fitness: f32, diversity: f32, adaptation: &Adaptation, epsilon: f32) {
+    let improved = best_fitness > self.best_fitness + epsilon;
+    if improved {
+      self.best_fitness = best_fitness;
+    }
+    if !improved || diversity < adaptation.diversity {
+      self.scale *= adaptation.factor;
+    } else {
+      self.scale /= adaptation.factor;
+    }
+    self.scale = self.scale.max(adaptation.min).min(adaptation.max);
+  }
+
+  /// Mutation rates in effect. The standard deviation of gaussian steps
+  /// doesn't change
+  pub fn mutation(&self) -> Mutation {
+    let rate = |r: f32| (r * self.scale).min(1.0);
+    Mutation {
+      creep: rate(self.mutation.creep),
+      reset: rate(self.mutation.reset),
+      gaussian: rate(self.mutation.gaussian),
+      sigma: self.mutation.sigma,
+      insertion: rate(self.mutation.insertion),
+      deletion: rate(self.mutation.deletion),
+    }
+  }
+
+  /// Crossover probability in effect
+  pub fn crossover_prob(&self) -> f32 {
+    (self.crossover_prob / self.scale).min(1.0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_adaptation() {
+    assert_eq!(Ok(Adaptation::default()), "on".parse());
+    let adaptation: Adaptation = "factor=2,max=8".parse().unwrap();
+    assert_eq!(2.0, adaptation.factor);
+    assert_eq!(8.0, adaptation.max);
+    assert_eq!(0.25, adaptation.min);
+    assert_eq!(Ok(adaptation.clone()), adaptation.to_string().parse());
+    assert!("factor=0.5".parse::<Adaptation>().is_err());
+    assert!("min=2,max=1".parse::<Adaptation>().is_err());
+    assert!("speed=2".parse::<Adaptation>().is_err());
+  }
+
+  #[test]
+  fn test_rates_follow_progress() {
+    let config = Config {
+      crossover_prob: 0.9,
+      ..Config::default()
+    };
+    let adaptation: Adaptation = "factor=2,min=0.5,max=4,diversity=0.1".parse().unwrap();
+    let mut rates = AdaptiveRates::new(&config);
+    // Improving and diverse
+    rates.update(1.0, 0.5, &adaptation, 0.0);
+    assert_eq!(0.5, rates.scale);
+    assert_eq!(0.1, rates.mutation().creep);
+    assert_eq!(1.0, rates.crossover_prob());
+    rates.update(2.0, 0.5, &adaptation, 0.0);
+    assert_eq!(0.5, rates.scale);
+    // Stalled
+    rates.update(2.0, 0.5, &adaptation, 0.0);
+    assert_eq!(1.0, rates.scale);
+    assert_eq!(0.2, rates.mutation().creep);
+    assert_eq!(0.9, rates.crossover_prob());
+    // Improving but converged
+    rates.update(3.0, 0.05, &adaptation, 0.0);
+    rates.update(4.0, 0.05, &adaptation, 0.0);
+    rates.update(5.0, 0.05, &adaptation, 0.0);
+    assert_eq!(4.0, rates.scale);
+    assert_eq!(0.8, rates.mutation().creep);
+    assert_eq!(8.0, rates.mutation().sigma);
+    assert_eq!(0.225, rates.crossover_prob());
+  }
+}
@@ -1,28 +1,181 @@
-// When buildi
-pub static STATEMENTS: [&str; 2] = ["<stmnt>", "<stmnt>::<code>"];
-
-pub static STRATEGIES: [&str; 10] = [
-  "hhv:<ticker>:<param>",
-  "llv:<ticker>:<param>",
-  "conupdays:<ticker>:<param>",
-  "condowndays:<ticker>:<param>",
-  "gapup:<ticker>:<param>",
-  "gapdown:<ticker>:<param>",
-  "stdeva:<ticker>:<param>",
-  "stdevb:<ticker>:<param>",
-  "stdevd:<ticker>:<param>",
-  "stdevf:<ticker>:<param>",
-];
-
-pub fn strategies_length() -> i32 {
-  STRATEGIES.len() as i32
-}
+use adaptive::Adaptation;
+use crossover;
+use diversity::Niching;
+use init::Initialization;
+use island::Topology;
+use mutation::Mutation;
+use pareto::{Objective, Ranking};
+use selection;
+use serde::{Deserialize, Serialize};
 
-pub fn statements_length() -> i32 {
-  STATEMENTS.len() as i32
-}
+// Default grammar used when no grammar file is given
+pub static GRAMMAR: &str = include_str!("../grammar.bnf");
+
+// Codons are drawn from 1 up to, but not including, CODON_SIZE
+pub static CODON_SIZE: i32 = 256;
 
-// Mutate probability of dna applied after crossover
+// Probability that a codon is nudged up or down by one after crossover
 pub static MUTATE_PROB: f32 = 0.20;
 
+// Number of times decoding may wrap around to the start of the dna
+pub static MAX_WRAPS: usize = 2;
+
+// Length of the dna in the first generation
+pub static DNA_LENGTH: usize = 12;
+
+// Bounds on the length of dna as it evolves
+pub static MIN_DNA_LENGTH: usize = 4;
+pub static MAX_DNA_LENGTH: usize = 64;
+
+// Number of chromosomes in each generation
+pub static POPULATION_SIZE: i32 = 10000;
+
+// Probability that two parents are recombined rather than copied
+pub static CROSSOVER_PROB: f32 = 1.0;
+
+// Number of the fittest chromosomes copied unchanged into the next generation
+pub static ELITISM: usize = 0;
+
+// Number of islands the population is split into
+pub static ISLANDS: usize = 1;
+
+// Number of generations between migrations
+pub static MIGRATION_INTERVAL: usize = 1;
+
+// Number of the fittest chromosomes each island sends to its neighbours
+pub static MIGRANTS: usize = 10;
 
+// Weight of the genotype in the distance between chromosomes. The behaviour
+// gets the rest
+pub static GENOTYPE_WEIGHT: f32 = 0.5;
+
+// Number of generations in a run
+pub static MAX_GENERATIONS: i32 = 3;
+
+// Smallest gain in the best fitness that counts as an improvement
+pub static EPSILON: f32 = 0.0001;
+
+// Fewest genes a rule may have
+pub static MIN_CHROMOSOME_LENGTH: usize = 1;
+
+// Number of the best chromosomes ever evaluated kept in the hall of fame
+pub static HALL_OF_FAME: usize = 100;
+
+// Chromosomes need more than this many trades to be ranked
+pub static MIN_TRADES: i32 = 100;
+
+// Fitness given to chromosomes that can't be decoded or evaluated
+pub static WORST_FITNESS: f32 = f32::MIN;
+
+/// Settings for a run that can change without rebuilding forge
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+  /// Number of times decoding may wrap around to the start of the dna before
+  /// the chromosome is marked invalid
+  pub max_wraps: usize,
+  /// Number of chromosomes in each generation
+  pub population_size: i32,
+  /// Operator that picks the parents of the next generation
+  pub selection: selection::Method,
+  /// Operator that recombines the dna of two parents
+  pub crossover: crossover::Method,
+  /// Probability that two parents are recombined rather than copied
+  pub crossover_prob: f32,
+  /// Number of the fittest chromosomes copied unchanged into the next
+  /// generation
+  pub elitism: usize,
+  /// Rates of the mutation operators
+  pub mutation: Mutation,
+  /// How the dna of the first generation is made
+  pub initialization: Initialization,
+  /// Length of the dna in the first generation when it is random
+  pub dna_length: usize,
+  /// Dna shorter than this is padded with random codons
+  pub min_dna_length: usize,
+  /// Dna longer than this is cut off
+  pub max_dna_length: usize,
+  /// Rules with fewer genes are invalid
+  pub min_chromosome_length: usize,
+  /// Rules with more genes are invalid. 0 doesn't limit the number of genes
+  pub max_chromosome_length: usize,
+  /// Fitness taken off kelly for each gene in a rule
+  pub parsimony: f32,
+  /// Whether the shorter rule is fitter when two chromosomes are equally fit
+  pub parsimony_ties: bool,
+  /// How mutation and crossover rates adapt to the progress of the search.
+  /// None keeps them fixed
+  pub adaptation: Option<Adaptation>,
+  /// Whether chromosomes are ranked by kelly or by Pareto front
+  pub ranking: Ranking,
+  /// Objectives of the Pareto fronts
+  pub objectives: Vec<Objective>,
+  /// Number of islands the population is split into. Each island has
+  /// `population_size` chromosomes
+  pub islands: usize,
+  /// Number of generations between migrations. 0 turns migration off
+  pub migration_interval: usize,
+  /// Number of the fittest chromosomes each island sends to its neighbours
+  pub migrants: usize,
+  /// Which islands exchange migrants
+  pub topology: Topology,
+  /// How the distance between chromosomes keeps the population diverse
+  pub niching: Niching,
+  /// Weight of the genotype in the distance between chromosomes. The
+  /// behaviour gets the rest
+  pub genotype_weight: f32,
+  /// Number of generations in a run
+  pub max_generations: i32,
+  /// Seconds a run may take. Checked between generations
+  pub time_budget: Option<u64>,
+  /// Number of generations without improvement in the best fitness before
+  /// a run stops. 0 never stops a run for stagnation
+  pub stagnation: usize,
+  /// Smallest gain in the best fitness that counts as an improvement
+  pub epsilon: f32,
+  /// Number of the best chromosomes ever evaluated kept in the hall of fame
+  pub hall_of_fame: usize,
+  /// Number of hall of fame members put back into each island every
+  /// generation. 0 never puts them back
+  pub hall_of_fame_inject: usize,
+  /// Chromosomes need more than this many trades to be ranked. Fewer trades
+  /// say too little about a rule to trust its kelly
+  pub min_trades: i32,
+}
+
+impl Default for Config {
+  fn default() -> Config {
+    Config {
+      max_wraps: MAX_WRAPS,
+      population_size: POPULATION_SIZE,
+      selection: selection::Method::LinearRank(2.0),
+      crossover: crossover::Method::OnePoint,
+      crossover_prob: CROSSOVER_PROB,
+      elitism: ELITISM,
+      mutation: Mutation::default(),
+      initialization: Initialization::Random,
+      dna_length: DNA_LENGTH,
+      min_dna_length: MIN_DNA_LENGTH,
+      max_dna_length: MAX_DNA_LENGTH,
+      min_chromosome_length: MIN_CHROMOSOME_LENGTH,
+      max_chromosome_length: 0,
+      parsimony: 0.0,
+      parsimony_ties: false,
+      adaptation: None,
+      ranking: Ranking::Kelly,
+      objectives: Objective::all(),
+      islands: ISLANDS,
+      migration_interval: MIGRATION_INTERVAL,
+      migrants: MIGRANTS,
+      topology: Topology::Ring,
+      niching: Niching::Off,
+      genotype_weight: GENOTYPE_WEIGHT,
+      max_generations: MAX_GENERATIONS,
+      time_budget: None,
+      stagnation: 0,
+      epsilon: EPSILON,
+      hall_of_fame: HALL_OF_FAME,
+      hall_of_fame_inject: 0,
+      min_trades: MIN_TRADES,
+    }
+  }
+}
@@ -0,0 +1,303 @@
+//! Crossover operators
+//!
+//! A crossover operator recombines the dna of two parents into two children.
+//! Parents can have dna of different lengths and each parent gets its own
+//! crossover points, so children can be longer or shorter than their
+//! parents.
+//!
+//! Homologous and effective-region crossover are aware of the grammar. They
+//! only pick crossover points among the codons that were consumed while
+//! decoding, and homologous crossover only joins codons that expand the same
+//! symbol.
+//!
+//! The operator used for a run is picked with a `Method`, which parses from
+//! the strings below.
+//!
+//! ```text
+//! onepoint    swap the tails after one point in each parent
+//! twopoint    swap the segments between two points in each parent
+//! uniform     swap each codon the parents share with a chance of one half
+//! homologous  swap the tails after points that expand the same symbol
+//! effective   swap the tails after points in the effective region
+//! ```
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use Dna;
+
+/// Dna of a parent with the symbols its codons expanded while decoding
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parent<'a> {
+  pub dna: &'a Dna,
+  /// See `derivation_history`
+  pub history: Vec<String>,
+}
+
+impl<'a> Parent<'a> {
+  /// Number of codons at the start of the dna that were consumed while
+  /// decoding
+  pub fn effective_length(&self) -> usize {
+    self.history.len().min(self.dna.len())
+  }
+}
+
+/// Recombines the dna of two parents
+pub trait Crossover: fmt::Debug {
+  /// Whether the operator needs the derivation history of the parents
+  fn needs_history(&self) -> bool {
+    false
+  }
+
+  /// Returns the dna of two children
+  fn crossover(&self, x: &Parent, y: &Parent, rng: &mut dyn RngCore) -> (Dna, Dna);
+}
+
+/// Swaps the tails after one point in each parent
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnePoint;
+
+impl Crossover for OnePoint {
+  fn crossover(&self, x: &Parent, y: &Parent, rng: &mut dyn RngCore) -> (Dna, Dna) {
+    let i = rng.gen_range(0, x.dna.len() + 1);
+    let j = rng.gen_range(0, y.dna.len() + 1);
+    swap_tails(x.dna, y.dna, i, j)
+  }
+}
+
+/// Swaps the segments between two points in each parent
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwoPoint;
+
+impl Crossover for TwoPoint {
+  fn crossover(&self, x: &Parent, y: &Parent, rng: &mut dyn RngCore) -> (Dna, Dna) {
+    let (x1, x2) = two_points(x.dna.len(), rng);
+    let (y1, y2) = two_points(y.dna.len(), rng);
+    let mut a: Dna = x.dna[..x1].to_vec();
+    a.extend(&y.dna[y1..y2]);
+    a.extend(&x.dna[x2..]);
+    let mut b: Dna = y.dna[..y1].to_vec();
+    b.extend(&x.dna[x1..x2]);
+    b.extend(&y.dna[y2..]);
+    (a, b)
+  }
+}
+
+/// Swaps each codon the parents share with a chance of one half
+///
+/// Codons past the end of the shorter parent stay with their parent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uniform;
+
+impl Crossover for Uniform {
+  fn crossover(&self, x: &Parent, y: &Parent, rng: &mut dyn RngCore) -> (Dna, Dna) {
+    let mut a = x.dna.clone();
+    let mut b = y.dna.clone();
+    for i in 0..a.len().min(b.len()) {
+      if rng.gen::<bool>() {
+        ::std::mem::swap(&mut a[i], &mut b[i]);
+      }
+    }
+    (a, b)
+  }
+}
+
+/// Swaps the tails after points that expand the same symbol
+///
+/// Parents that decode the same way start with the same history. The first
+/// point is picked after this shared region when possible so the children
+/// differ from their parents. The second point is picked among the codons of
+/// the other parent that expand the same symbol, so the tail that is swapped
+/// in continues the derivation where the head left off.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Homologous;
+
+impl Crossover for Homologous {
+  fn needs_history(&self) -> bool {
+    true
+  }
+
+  fn crossover(&self, x: &Parent, y: &Parent, rng: &mut dyn RngCore) -> (Dna, Dna) {
+    let (x_len, y_len) = (x.effective_length(), y.effective_length());
+    if x_len == 0 || y_len == 0 {
+      return (x.dna.clone(), y.dna.clone());
+    }
+    let shared = x.history[..x_len]
+      .iter()
+      .zip(&y.history[..y_len])
+      .zip(x.dna.iter().zip(y.dna.iter()))
+      .take_while(|((hx, hy), (bx, by))| hx == hy && bx == by)
+      .count();
+    let start = if shared < x_len { shared } else { 0 };
+    let i = rng.gen_range(start, x_len);
+    let matches: Vec<usize> = (0..y_len).filter(|j| y.history[*j] == x.history[i]).collect();
+    if matches.is_empty() {
+      return EffectiveRegion.crossover(x, y, rng);
+    }
+    let j = matches[rng.gen_range(0, matches.len())];
+    swap_tails(x.dna, y.dna, i, j)
+  }
+}
+
+/// Swaps the tails after one point in the effective region of each parent
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectiveRegion;
+
+impl Crossover for EffectiveRegion {
+  fn needs_history(&self) -> bool {
+    true
+  }
+
+  fn crossover(&self, x: &Parent, y: &Parent, rng: &mut dyn RngCore) -> (Dna, Dna) {
+    let i = rng.gen_range(0, x.effective_length() + 1);
+    let j = rng.gen_range(0, y.effective_length() + 1);
+    swap_tails(x.dna, y.dna, i, j)
+  }
+}
+
+fn swap_tails(x: &Dna, y: &Dna, i: usize, j: usize) -> (Dna, Dna) {
+  let mut a: Dna = x[..i].to_vec();
+  a.extend(&y[j..]);
+  let mut b: Dna = y[..j].to_vec();
+  b.extend(&x[i..]);
+  (a, b)
+}
+
+fn two_points(len: usize, rng: &mut dyn RngCore) -> (usize, usize) {
+  let p = rng.gen_range(0, len + 1);
+  let q = rng.gen_range(0, len + 1);
+  (p.min(q), p.max(q))
+}
+
+/// Crossover operator picked in the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Method {
+  OnePoint,
+  TwoPoint,
+  Uniform,
+  Homologous,
+  EffectiveRegion,
+}
+
+impl Method {
+  /// Builds the crossover operator
+  pub fn crossover(&self) -> Box<dyn Crossover> {
+    match self {
+      Method::OnePoint => Box::new(OnePoint),
+      Method::TwoPoint => Box::new(TwoPoint),
+      Method::Uniform => Box::new(Uniform),
+      Method::Homologous => Box::new(Homologous),
+      Method::EffectiveRegion => Box::new(EffectiveRegion),
+    }
+  }
+}
+
+impl fmt::Display for Method {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      Method::OnePoint => "onepoint",
+      Method::TwoPoint => "twopoint",
+      Method::Uniform => "uniform",
+      Method::Homologous => "homologous",
+      Method::EffectiveRegion => "effective",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+impl FromStr for Method {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Method, String> {
+    match s {
+      "onepoint" => Ok(Method::OnePoint),
+      "twopoint" => Ok(Method::TwoPoint),
+      "uniform" => Ok(Method::Uniform),
+      "homologous" => Ok(Method::Homologous),
+      "effective" => Ok(Method::EffectiveRegion),
+      _ => Err(format!("unknown crossover `{}`", s)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::prng::ChaChaRng;
+  use rand::SeedableRng;
+
+  fn parent<'a>(dna: &'a Dna, history: &[&str]) -> Parent<'a> {
+    Parent {
+      dna,
+      history: history.iter().map(|h| h.to_string()).collect(),
+    }
+  }
+
+  // Every codon of the children comes from one of the parents, in order
+  fn assert_recombined(x: &Dna, y: &Dna, (a, b): (Dna, Dna)) {
+    assert_eq!(x.len() + y.len(), a.len() + b.len());
+    let mut codons: Vec<i32> = a.iter().chain(b.iter()).cloned().collect();
+    let mut expected: Vec<i32> = x.iter().chain(y.iter()).cloned().collect();
+    codons.sort();
+    expected.sort();
+    assert_eq!(expected, codons);
+  }
+
+  #[test]
+  fn test_operators_keep_codons() {
+    let x = vec![1, 2, 3, 4, 5, 6];
+    let y = vec![11, 12, 13, 14];
+    let history = ["<a>", "<b>", "<a>", "<b>"];
+    let mut rng = ChaChaRng::seed_from_u64(1);
+    for method in &["onepoint", "twopoint", "uniform", "homologous", "effective"] {
+      let operator = method.parse::<Method>().unwrap().crossover();
+      for _ in 0..50 {
+        let children = operator.crossover(&parent(&x, &history), &parent(&y, &history), &mut rng);
+        assert_recombined(&x, &y, children);
+      }
+    }
+  }
+
+  #[test]
+  fn test_effective_region() {
+    let x = vec![1, 2, 3, 4, 5, 6];
+    let y = vec![11, 12, 13, 14, 15, 16];
+    let mut rng = ChaChaRng::seed_from_u64(1);
+    for _ in 0..50 {
+      let (a, _) = EffectiveRegion.crossover(&parent(&x, &["<a>", "<b>"]), &parent(&y, &["<a>"]), &mut rng);
+      // Only the first two codons of x and the first codon of y were used, so
+      // the tail of x is swapped at most at the second codon
+      assert!(a.len() >= 5 && a.len() <= 8, "{:?}", a);
+      assert!(a.ends_with(&y[1..]), "{:?}", a);
+    }
+  }
+
+  #[test]
+  fn test_homologous() {
+    let x = vec![1, 2, 3, 4];
+    let y = vec![1, 2, 13, 14, 15];
+    let x_history = ["<code>", "<stmnt>", "<ticker>", "<int>"];
+    let y_history = ["<code>", "<stmnt>", "<int>", "<ticker>", "<int>"];
+    let mut rng = ChaChaRng::seed_from_u64(1);
+    for _ in 0..50 {
+      let (a, b) = Homologous.crossover(&parent(&x, &x_history), &parent(&y, &y_history), &mut rng);
+      // The shared region of two codons is never split, and the tails are
+      // joined where both parents expand the same symbol
+      assert!(a.starts_with(&[1, 2]), "{:?}", a);
+      assert!(
+        a == vec![1, 2, 14, 15] || a == vec![1, 2, 3, 13, 14, 15] || a == vec![1, 2, 3, 15],
+        "{:?} {:?}",
+        a,
+        b
+      );
+    }
+  }
+
+  #[test]
+  fn test_parse_method() {
+    for method in &[Method::OnePoint, Method::TwoPoint, Method::Uniform, Method::Homologous, Method::EffectiveRegion] {
+      assert_eq!(Ok(method.clone()), method.to_string().parse());
+    }
+    assert!("threepoint".parse::<Method>().is_err());
+  }
+}
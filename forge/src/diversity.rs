@@ -0,0 +1,376 @@
+//! Distance between chromosomes and diversity preservation
+//!
+//! Two chromosomes are compared on their genotype, the codons of their dna,
+//! and on their behaviour, the days they take a position on and which way.
+//! Both distances are between 0 and 1 and are blended with the genotype
+//! weight of the config.
+//!
+//! The distance keeps near-identical rules from taking over the population
+//! with one of the niching methods below, which parse from these strings.
+//!
+//! ```text
+//! off           no niching
+//! sharing:0.3   fitness sharing with a niche radius of 0.3
+//! crowding      deterministic crowding
+//! ```
+//!
+//! Fitness sharing divides the fitness of a chromosome by the number of
+//! chromosomes within the niche radius and orders the population by it
+//! before parents are selected, so it needs a selection that goes by that
+//! order rather than by fitness.
+//! Deterministic crowding makes each child compete with the closer of its
+//! two parents for a place in the next generation.
+use pareto;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+use {Chromosome, Config, Dna, Ranking};
+
+/// Number of chromosomes compared when measuring the diversity of a
+/// population
+pub static DIVERSITY_SAMPLE: usize = 500;
+
+/// How the distance between chromosomes is used to keep the population
+/// diverse
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Niching {
+  Off,
+  /// Fitness sharing with a niche radius
+  Sharing(f32),
+  DeterministicCrowding,
+}
+
+impl fmt::Display for Niching {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Niching::Off => write!(f, "off"),
+      Niching::Sharing(radius) => write!(f, "sharing:{:?}", radius),
+      Niching::DeterministicCrowding => write!(f, "crowding"),
+    }
+  }
+}
+
+impl FromStr for Niching {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Niching, String> {
+    let mut parts = s.splitn(2, ':');
+    match (parts.next().unwrap_or(""), parts.next()) {
+      ("off", None) => Ok(Niching::Off),
+      ("crowding", None) => Ok(Niching::DeterministicCrowding),
+      ("sharing", Some(radius)) => match radius.parse::<f32>() {
+        Ok(radius) if radius > 0.0 && radius <= 1.0 => Ok(Niching::Sharing(radius)),
+        _ => Err(format!("niche radius in `{}` should be between 0 and 1", s)),
+      },
+      _ => Err(format!("unknown niching `{}`", s)),
+    }
+  }
+}
+
+/// Share of the codons that differ between two dnas
+///
+/// Codons past the end of the shorter dna count as different.
+pub fn genotype_distance(a: &Dna, b: &Dna) -> f32 {
+  let len = a.len().max(b.len());
+  if len == 0 {
+    return 0.0;
+  }
+  let same = a.iter().zip(b.iter()).filter(|(x, y)| x == y).count();
+  (len - same) as f32 / len as f32
+}
+
+/// Share of the days either chromosome is in the market on that they don't
+/// take the same position
+///
+/// Chromosomes that never take a position have no distance between them.
+pub fn behaviour_distance(a: &Chromosome, b: &Chromosome) -> f32 {
+  let (x, y) = (&a.behaviour, &b.behaviour);
+  let (mut i, mut j) = (0, 0);
+  let (mut union, mut same) = (0, 0);
+  while i < x.len() || j < y.len() {
+    union += 1;
+    if j == y.len() || (i < x.len() && x[i].0 < y[j].0) {
+      i += 1;
+    } else if i == x.len() || y[j].0 < x[i].0 {
+      j += 1;
+    } else {
+      if x[i].1 == y[j].1 {
+        same += 1;
+      }
+      i += 1;
+      j += 1;
+    }
+  }
+  if union == 0 {
+    return 0.0;
+  }
+  (union - same) as f32 / union as f32
+}
+
+/// Distance between two chromosomes
+///
+/// `genotype_weight` of the distance comes from the genotype and the rest
+/// from the behaviour.
+pub fn distance(a: &Chromosome, b: &Chromosome, genotype_weight: f32) -> f32 {
+  genotype_weight * genotype_distance(&a.dna, &b.dna) + (1.0 - genotype_weight) * behaviour_distance(a, b)
+}
+
+/// Mean distance between the chromosomes of a population
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Diversity {
+  pub genotype: f32,
+  pub behaviour: f32,
+}
+
+impl Diversity {
+  /// Mean distance with the genotype weighted like in `distance`
+  pub fn blend(&self, genotype_weight: f32) -> f32 {
+    genotype_weight * self.genotype + (1.0 - genotype_weight) * self.behaviour
+  }
+}
+
+impl fmt::Display for Diversity {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "genotype {:.4}, behaviour {:.4}", self.genotype, self.behaviour)
+  }
+}
+
+/// Measures the diversity of a population
+///
+/// Large populations are measured on `DIVERSITY_SAMPLE` chromosomes spread
+/// evenly through the population.
+pub fn diversity(population: &[Chromosome]) -> Diversity {
+  let step = (population.len() / DIVERSITY_SAMPLE).max(1);
+  let sample: Vec<&Chromosome> = population.iter().step_by(step).take(DIVERSITY_SAMPLE).collect();
+  let (mut genotype, mut behaviour, mut pairs) = (0.0, 0.0, 0);
+  for (i, a) in sample.iter().enumerate() {
+    for b in &sample[i + 1..] {
+      genotype += genotype_distance(&a.dna, &b.dna);
+      behaviour += behaviour_distance(a, b);
+      pairs += 1;
+    }
+  }
+  if pairs == 0 {
+    return Diversity { genotype: 0.0, behaviour: 0.0 };
+  }
+  Diversity {
+    genotype: genotype / pairs as f32,
+    behaviour: behaviour / pairs as f32,
+  }
+}
+
+/// Orders a population by shared fitness with the fittest last
+///
+/// Fitness is shifted to make the least fit chromosome zero and divided by
+/// the niche count, the sum of `1 - d / radius` over the chromosomes within
+/// `radius` of the chromosome, itself included. Large populations count the
+/// niche on `DIVERSITY_SAMPLE` chromosomes spread evenly through the
+/// population and scale it up to the whole population, so the cost grows
+/// with the size of the population rather than its square.
+pub fn share(population: &mut Vec<Chromosome>, radius: f32, genotype_weight: f32) {
+  let min = population.iter().map(|c| c.fitness()).fold(f32::MAX, f32::min);
+  let step = (population.len() / DIVERSITY_SAMPLE).max(1);
+  let sample: Vec<&Chromosome> = population.iter().step_by(step).take(DIVERSITY_SAMPLE).collect();
+  let scale = population.len() as f32 / sample.len().max(1) as f32;
+  let shared: Vec<f32> = population
+    .iter()
+    .map(|a| {
+      let niche_count: f32 = sample
+        .iter()
+        .map(|b| distance(a, b, genotype_weight))
+        .filter(|d| *d < radius)
+        .map(|d| 1.0 - d / radius)
+        .sum();
+      (a.fitness() - min) / (niche_count * scale).max(1.0)
+    })
+    .collect();
+  let mut order: Vec<usize> = (0..population.len()).collect();
+  order.sort_by(|a, b| shared[*a].partial_cmp(&shared[*b]).unwrap_or(::std::cmp::Ordering::Equal));
+  let mut chromosomes: Vec<Option<Chromosome>> = population.drain(..).map(Some).collect();
+  population.extend(order.into_iter().filter_map(|i| chromosomes[i].take()));
+}
+
+/// Picks the survivors of deterministic crowding
+///
+/// Children are grouped by the pair of parents in `previous` they were bred
+/// from. Each child is matched with one of its parents so that the total
+/// distance between them is smallest, and the better of the two survives.
+/// Chromosomes of `previous` without a child to compete with survive, as do
+/// children whose parents aren't in `previous`.
+pub fn crowd(previous: Vec<Chromosome>, children: Vec<Chromosome>, config: &Config) -> Vec<Chromosome> {
+  let parents: HashMap<Uuid, &Chromosome> = previous.iter().map(|c| (c.id, c)).collect();
+  let mut competed: HashSet<Uuid> = HashSet::new();
+  let mut families: Vec<(Vec<&Chromosome>, Vec<Chromosome>)> = vec![];
+  let mut family_of: HashMap<Vec<Uuid>, usize> = HashMap::new();
+  let mut survivors: Vec<Chromosome> = vec![];
+  for child in children {
+    let family_parents: Vec<&Chromosome> = child.parents.iter().filter_map(|id| parents.get(id).cloned()).collect();
+    if family_parents.is_empty() {
+      survivors.push(child);
+      continue;
+    }
+    let idx = *family_of.entry(child.parents.clone()).or_insert_with(|| {
+      families.push((family_parents, vec![]));
+      families.len() - 1
+    });
+    families[idx].1.push(child);
+  }
+  let d = |a: &Chromosome, b: &Chromosome| distance(a, b, config.genotype_weight);
+  for (family_parents, family_children) in families {
+    let mut pairs: Vec<(Option<Chromosome>, &Chromosome)> = vec![];
+    let mut children = family_children.into_iter();
+    match (family_parents.len(), children.next(), children.next()) {
+      (2, Some(a), Some(b)) => {
+        let (p, q) = (family_parents[0], family_parents[1]);
+        if d(&a, p) + d(&b, q) <= d(&a, q) + d(&b, p) {
+          pairs.push((Some(a), p));
+          pairs.push((Some(b), q));
+        } else {
+          pairs.push((Some(b), p));
+          pairs.push((Some(a), q));
+        }
+      }
+      (2, Some(a), None) => {
+        let (p, q) = (family_parents[0], family_parents[1]);
+        let (closer, further) = if d(&a, p) <= d(&a, q) { (p, q) } else { (q, p) };
+        pairs.push((Some(a), closer));
+        pairs.push((None, further));
+      }
+      (_, a, b) => {
+        // A single parent was found, so every child competes with it
+        for child in a.into_iter().chain(b) {
+          pairs.push((Some(child), family_parents[0]));
+        }
+      }
+    }
+    survivors.extend(children);
+    for (child, parent) in pairs {
+      competed.insert(parent.id);
+      match child {
+        Some(ref child) if child_wins(child, parent, config) => survivors.push(child.clone()),
+        _ => survivors.push(parent.clone()),
+      }
+    }
+  }
+  survivors.extend(previous.iter().filter(|c| !competed.contains(&c.id)).cloned());
+  let mut seen: HashSet<Uuid> = HashSet::new();
+  survivors.retain(|c| seen.insert(c.id));
+  survivors
+}
+
+// Whether a child takes the place of its parent
+fn child_wins(child: &Chromosome, parent: &Chromosome, config: &Config) -> bool {
+  match config.ranking {
+    Ranking::Kelly => child.fitness() >= parent.fitness(),
+    Ranking::Pareto => !pareto::dominates(parent, child, &config.objectives),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use testing::chromosomes as population;
+
+  #[test]
+  fn test_genotype_distance() {
+    assert_eq!(0.0, genotype_distance(&vec![1, 2, 3], &vec![1, 2, 3]));
+    assert_eq!(0.5, genotype_distance(&vec![1, 2, 3, 4], &vec![1, 9, 3, 8]));
+    assert_eq!(0.5, genotype_distance(&vec![1, 2], &vec![1, 2, 3, 4]));
+    assert_eq!(0.0, genotype_distance(&vec![], &vec![]));
+  }
+
+  #[test]
+  fn test_behaviour_distance() {
+    let mut p = population(3);
+    p[0].behaviour = vec![(1, 1), (2, 1), (3, -1)];
+    p[1].behaviour = vec![(2, 1), (3, 1), (4, 1)];
+    // Days 1 to 4 are traded and only day 2 agrees
+    assert_eq!(0.75, behaviour_distance(&p[0], &p[1]));
+    assert_eq!(0.0, behaviour_distance(&p[0], &p[0]));
+    assert_eq!(1.0, behaviour_distance(&p[0], &p[2]));
+    assert_eq!(0.0, behaviour_distance(&p[2], &p[2]));
+    assert_eq!(0.5 * 0.25 + 0.5 * 0.75, distance(&p[0], &p[1], 0.5));
+  }
+
+  #[test]
+  fn test_share() {
+    let mut p = population(4);
+    for (i, c) in p.iter_mut().enumerate() {
+      c.kelly = [1.0, 5.0, 5.0, 4.0][i];
+      c.behaviour = if i == 3 { vec![(1, -1)] } else { vec![(1, 1)] };
+    }
+    // The two chromosomes with kelly 5 share a niche and fall behind the one
+    // with kelly 4 that has a niche to itself
+    share(&mut p, 0.5, 0.0);
+    let kellies: Vec<f32> = p.iter().map(|c| c.kelly).collect();
+    assert_eq!(vec![1.0, 5.0, 5.0, 4.0], kellies);
+    share(&mut p, 0.5, 1.0);
+    let kellies: Vec<f32> = p.iter().map(|c| c.kelly).collect();
+    assert_eq!(vec![1.0, 4.0, 5.0, 5.0], kellies);
+  }
+
+  #[test]
+  fn test_share_samples_large_populations() {
+    let mut p = population(DIVERSITY_SAMPLE * 2 + 1);
+    let small_niche = p.len() / 4;
+    for (i, c) in p.iter_mut().enumerate() {
+      c.kelly = if i == 0 { 0.0 } else { 1.0 };
+      c.behaviour = vec![(1, if i < small_niche { -1 } else { 1 })];
+    }
+    // Equally fit chromosomes in the small niche share less and go last
+    share(&mut p, 0.5, 0.0);
+    assert_eq!(0.0, p[0].kelly);
+    let sides: Vec<i32> = p[1..].iter().map(|c| c.behaviour[0].1).collect();
+    assert!(sides.windows(2).all(|w| w[0] >= w[1]));
+    assert_eq!(-1, sides[sides.len() - 1]);
+  }
+
+  #[test]
+  fn test_crowd() {
+    let mut p = population(6);
+    for (i, c) in p.iter_mut().enumerate() {
+      c.kelly = [1.0, 2.0, 3.0, 1.5, 2.5, 0.5][i];
+      c.behaviour = vec![(1, [1, -1, 1, -1, 1, 1][i])];
+    }
+    let previous = p[..3].to_vec();
+    let mut children = p[3..].to_vec();
+    for child in &mut children[..2] {
+      child.parents = vec![previous[0].id, previous[1].id];
+    }
+    let config = Config {
+      genotype_weight: 0.0,
+      ..Config::default()
+    };
+    // Child 1.5 is closer to parent 2.0 and loses, child 2.5 is closer to
+    // parent 1.0 and wins. Parent 3.0 has no children and child 0.5 has no
+    // parents, so both survive.
+    let survivors = crowd(previous, children, &config);
+    let mut kellies: Vec<f32> = survivors.iter().map(|c| c.kelly).collect();
+    kellies.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(vec![0.5, 2.0, 2.5, 3.0], kellies);
+  }
+
+  #[test]
+  fn test_diversity() {
+    let mut p = population(3);
+    assert_eq!(0.0, diversity(&p[..1]).genotype);
+    p[0].behaviour = vec![(1, 1)];
+    // Two of the three pairs differ in their last codon and their behaviour
+    let diversity = diversity(&p);
+    assert!((diversity.genotype - 0.25).abs() < 1e-6);
+    assert!((diversity.behaviour - 2.0 / 3.0).abs() < 1e-6);
+  }
+
+  #[test]
+  fn test_parse_niching() {
+    assert_eq!(Ok(Niching::Sharing(0.3)), "sharing:0.3".parse());
+    for niching in &[Niching::Off, Niching::Sharing(0.5), Niching::DeterministicCrowding] {
+      assert_eq!(Ok(*niching), niching.to_string().parse());
+    }
+    for niching in &["sharing", "sharing:0", "sharing:2", "crowding:1", "niche"] {
+      assert!(niching.parse::<Niching>().is_err(), "{}", niching);
+    }
+  }
+}
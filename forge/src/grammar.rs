@@ -0,0 +1,547 @@
+//! BNF grammar used to decode dna into chromosomes
+//!
+//! A grammar is a list of rules. Each rule maps a non-terminal to one or more
+//! productions separated by `|`. The first rule in the file is the start symbol.
+//!
+//! ```text
+//! # comments start with a hash
+//! <code>  ::= <stmnt> | <stmnt>::<code>
+//! <stmnt> ::= hhv:<ticker>:<param>
+//!           | llv:<ticker>:<param>
+//! ```
+//!
+//! Symbols are written next to each other without separators. Whitespace
+//! between symbols is ignored, so terminals that need spaces should be quoted,
+//! i.e. `<expr> " AND " <expr>`. A line that starts with `|` continues the
+//! productions of the previous rule.
+//!
+//! `<ticker>` and `<param>` are built in non-terminals. They are filled in at
+//! decode time from the ticker pool and the codon value unless the grammar
+//! defines them itself.
+//!
+//! Parameters can also be drawn from a domain that fits the strategy
+//!
+//! ```text
+//! <int:2:250>        integers from 2 to 250
+//! <set:5,10,20>      one of 5, 10 or 20
+//! <float:0.5:3.0>    floats from 0.5 to 3.0, rounded to 2 decimals
+//! ```
+//!
+//! Integer ranges that fit in a codon are picked with the codon modulo the
+//! size of the range. Wider ranges and float ranges are scaled so every part
+//! of the range can be reached.
+use config::CODON_SIZE;
+use rule::Param;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+
+/// Non-terminals that are expanded by the decoder when the grammar does not
+/// define them
+pub static BUILTINS: [&str; 2] = ["ticker", "param"];
+
+/// Non-terminal whose productions are the strategies a gene can use
+pub static STRATEGIES: &str = "stmnt";
+
+/// A terminal or non-terminal in a production
+#[derive(Debug, Clone, PartialEq)]
+pub enum Symbol {
+  Terminal(String),
+  NonTerminal(String),
+  /// A parameter drawn from a domain, i.e. `<int:2:250>`
+  Domain(Domain),
+}
+
+impl Symbol {
+  /// True for symbols that still need to be expanded
+  pub fn is_non_terminal(&self) -> bool {
+    match self {
+      Symbol::NonTerminal(_) | Symbol::Domain(_) => true,
+      Symbol::Terminal(_) => false,
+    }
+  }
+}
+
+impl fmt::Display for Symbol {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Symbol::Terminal(t) => write!(f, "{}", t),
+      Symbol::NonTerminal(n) => write!(f, "<{}>", n),
+      Symbol::Domain(d) => write!(f, "<{}>", d),
+    }
+  }
+}
+
+/// Values a strategy parameter can take
+#[derive(Debug, Clone, PartialEq)]
+pub enum Domain {
+  /// Integers from low to high inclusive
+  Int(i32, i32),
+  /// One of a discrete set of values
+  Set(Vec<Param>),
+  /// Floats from low to high inclusive
+  Float(f32, f32),
+}
+
+impl Domain {
+  /// Parses a domain from the inside of `<...>`
+  ///
+  /// Returns `None` when the name isn't a domain
+  pub fn parse(spec: &str) -> Option<Result<Domain, String>> {
+    let parts: Vec<&str> = spec.splitn(2, ':').collect();
+    if parts.len() != 2 {
+      return None;
+    }
+    let domain = match parts[0] {
+      "int" => parse_bounds(parts[1]).map(|(low, high)| Domain::Int(low, high)),
+      "float" => parse_bounds(parts[1]).map(|(low, high)| Domain::Float(low, high)),
+      "set" => parts[1]
+        .split(',')
+        .map(|v| parse_param(v.trim()))
+        .collect::<Result<Vec<Param>, String>>()
+        .map(Domain::Set),
+      _ => return None,
+    };
+    Some(domain.and_then(|d| d.validate()).map_err(|e| format!("invalid domain <{}>: {}", spec, e)))
+  }
+
+  /// Maps a codon into the domain
+  ///
+  /// Codons run from 1 to `CODON_SIZE - 1`, so scaled ranges map the lowest
+  /// codon to the low bound and the highest to the high bound.
+  pub fn pick(&self, base: i32) -> Param {
+    let codons = CODON_SIZE as i64 - 1;
+    // Position of the codon from 0 to `codons - 1`
+    let position = (base as i64 - 1).rem_euclid(codons);
+    match self {
+      Domain::Int(low, high) => {
+        // Spans of extreme bounds overflow an i32
+        let span = *high as i64 - *low as i64 + 1;
+        let offset = if span <= codons {
+          (base as i64).rem_euclid(span)
+        } else {
+          let step = (span - 1) as f64 / (codons - 1) as f64;
+          (position as f64 * step).round() as i64
+        };
+        Param::Int((*low as i64 + offset) as i32)
+      }
+      Domain::Set(values) => values[base.rem_euclid(values.len() as i32) as usize],
+      Domain::Float(low, high) => {
+        let fraction = position as f32 / (codons - 1) as f32;
+        Param::Float(((low + fraction * (high - low)) * 100.0).round() / 100.0)
+      }
+    }
+  }
+
+  fn validate(self) -> Result<Domain, String> {
+    let valid = match &self {
+      Domain::Int(low, high) => low <= high,
+      Domain::Float(low, high) => low <= high,
+      Domain::Set(values) => !values.is_empty(),
+    };
+    if valid {
+      Ok(self)
+    } else {
+      Err("empty range".to_string())
+    }
+  }
+}
+
+impl fmt::Display for Domain {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Domain::Int(low, high) => write!(f, "int:{}:{}", low, high),
+      Domain::Float(low, high) => write!(f, "float:{:?}:{:?}", low, high),
+      Domain::Set(values) => {
+        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        write!(f, "set:{}", values.join(","))
+      }
+    }
+  }
+}
+
+fn parse_bounds<T: ::std::str::FromStr>(bounds: &str) -> Result<(T, T), String> {
+  let parts: Vec<&str> = bounds.split(':').collect();
+  if parts.len() != 2 {
+    return Err("expected `low:high`".to_string());
+  }
+  match (parts[0].trim().parse::<T>(), parts[1].trim().parse::<T>()) {
+    (Ok(low), Ok(high)) => Ok((low, high)),
+    _ => Err(format!("invalid bounds `{}`", bounds)),
+  }
+}
+
+fn parse_param(value: &str) -> Result<Param, String> {
+  match (value.parse::<i32>(), value.parse::<f32>()) {
+    (Ok(i), _) => Ok(Param::Int(i)),
+    (_, Ok(f)) => Ok(Param::Float(f)),
+    _ => Err(format!("invalid value `{}`", value)),
+  }
+}
+
+/// A production is the sequence of symbols that replaces a non-terminal
+pub type Production = Vec<Symbol>;
+
+/// Errors raised while loading a grammar
+#[derive(Debug, Clone, PartialEq)]
+pub enum GrammarError {
+  /// The grammar file could not be read
+  Io(String),
+  /// A line could not be parsed
+  Syntax { line: usize, message: String },
+  /// A non-terminal is used but never defined
+  Undefined(String),
+  /// The grammar has no rules
+  Empty,
+  /// A strategy production uses a strategy that doesn't exist
+  UnknownStrategy(String),
+}
+
+impl fmt::Display for GrammarError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      GrammarError::Io(msg) => write!(f, "could not read grammar: {}", msg),
+      GrammarError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+      GrammarError::Undefined(name) => write!(f, "non-terminal <{}> is never defined", name),
+      GrammarError::Empty => write!(f, "grammar has no rules"),
+      GrammarError::UnknownStrategy(production) => write!(f, "<{}> uses an unknown strategy: {}", STRATEGIES, production),
+    }
+  }
+}
+
+/// A parsed grammar
+#[derive(Debug, Clone)]
+pub struct Grammar {
+  start: String,
+  rules: HashMap<String, Vec<Production>>,
+}
+
+impl Grammar {
+  /// Parses a grammar from BNF source
+  pub fn parse(source: &str) -> Result<Grammar, GrammarError> {
+    Grammar::parse_partial(source, &[])
+  }
+
+  /// Parses a grammar that may use the non-terminals in `undefined` without
+  /// defining them, so they can be filled in with `define`
+  pub fn parse_partial(source: &str, undefined: &[&str]) -> Result<Grammar, GrammarError> {
+    let mut start: Option<String> = None;
+    let mut rules: HashMap<String, Vec<Production>> = HashMap::new();
+    let mut current: Option<String> = None;
+
+    for (i, raw) in source.lines().enumerate() {
+      let line_no = i + 1;
+      let line = raw.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+
+      let (name, body) = if let Some(rest) = line.strip_prefix('|') {
+        match current {
+          Some(ref name) => (name.clone(), rest),
+          None => return Err(syntax(line_no, "continuation before the first rule")),
+        }
+      } else {
+        let parts: Vec<&str> = line.splitn(2, "::=").collect();
+        if parts.len() != 2 {
+          return Err(syntax(line_no, "expected `<name> ::= ...`"));
+        }
+        let name = parse_lhs(parts[0].trim()).map_err(|m| syntax(line_no, &m))?;
+        if rules.contains_key(&name) {
+          return Err(syntax(line_no, &format!("<{}> is defined twice", name)));
+        }
+        rules.insert(name.clone(), vec![]);
+        if start.is_none() {
+          start = Some(name.clone());
+        }
+        current = Some(name.clone());
+        (name, parts[1])
+      };
+
+      let productions = parse_productions(body).map_err(|m| syntax(line_no, &m))?;
+      rules.get_mut(&name).unwrap().extend(productions);
+    }
+
+    let start = match start {
+      Some(s) => s,
+      None => return Err(GrammarError::Empty),
+    };
+    let grammar = Grammar { start, rules };
+    grammar.validate(undefined)?;
+    Ok(grammar)
+  }
+
+  /// Reads and parses a grammar file
+  pub fn from_file(path: &str) -> Result<Grammar, GrammarError> {
+    let source = fs::read_to_string(path).map_err(|e| GrammarError::Io(format!("{}: {}", path, e)))?;
+    Grammar::parse(&source)
+  }
+
+  /// Name of the start symbol
+  pub fn start(&self) -> &str {
+    &self.start
+  }
+
+  /// Productions for a non-terminal
+  ///
+  /// Returns `None` for built in non-terminals that the grammar doesn't define
+  pub fn productions(&self, non_terminal: &str) -> Option<&[Production]> {
+    self.rules.get(non_terminal).map(|p| p.as_slice())
+  }
+
+  /// Replaces the productions of a non-terminal, or adds the non-terminal
+  /// when the grammar doesn't define it yet
+  ///
+  /// The grammar is left unchanged when the new productions use a
+  /// non-terminal that is never defined.
+  pub fn define(&mut self, non_terminal: &str, productions: Vec<Production>) -> Result<(), GrammarError> {
+    if productions.is_empty() {
+      return Err(GrammarError::Undefined(non_terminal.to_string()));
+    }
+    let previous = self.rules.insert(non_terminal.to_string(), productions);
+    if let Err(e) = self.validate(&[]) {
+      match previous {
+        Some(productions) => self.rules.insert(non_terminal.to_string(), productions),
+        None => self.rules.remove(non_terminal),
+      };
+      return Err(e);
+    }
+    Ok(())
+  }
+
+  /// Names of the non-terminals the grammar defines
+  pub fn non_terminals(&self) -> Vec<&str> {
+    self.rules.keys().map(|n| n.as_str()).collect()
+  }
+
+  // Every non-terminal must have productions, be a builtin or be allowed to
+  // be undefined
+  fn validate(&self, undefined: &[&str]) -> Result<(), GrammarError> {
+    for (name, productions) in &self.rules {
+      if productions.is_empty() {
+        return Err(GrammarError::Undefined(name.clone()));
+      }
+      for production in productions {
+        for symbol in production {
+          if let Symbol::NonTerminal(n) = symbol {
+            if !self.rules.contains_key(n) && !BUILTINS.contains(&n.as_str()) && !undefined.contains(&n.as_str()) {
+              return Err(GrammarError::Undefined(n.clone()));
+            }
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Default for Grammar {
+  /// The grammar shipped with forge
+  fn default() -> Grammar {
+    Grammar::parse(::config::GRAMMAR).expect("default grammar is invalid")
+  }
+}
+
+/// Renders a sentential form as a string
+pub fn render(symbols: &[Symbol]) -> String {
+  symbols.iter().map(|s| s.to_string()).collect()
+}
+
+fn syntax(line: usize, message: &str) -> GrammarError {
+  GrammarError::Syntax {
+    line,
+    message: message.to_string(),
+  }
+}
+
+fn parse_lhs(lhs: &str) -> Result<String, String> {
+  if lhs.len() > 2 && lhs.starts_with('<') && lhs.ends_with('>') {
+    let name = &lhs[1..lhs.len() - 1];
+    if !name.contains('<') && !name.contains('>') {
+      return Ok(name.to_string());
+    }
+  }
+  Err(format!("invalid rule name `{}`", lhs))
+}
+
+// Splits the right hand side of a rule on `|` and tokenizes each alternative
+fn parse_productions(body: &str) -> Result<Vec<Production>, String> {
+  let mut productions: Vec<Production> = vec![];
+  let mut production: Production = vec![];
+  let mut terminal = String::new();
+  let mut chars = body.chars();
+
+  while let Some(c) = chars.next() {
+    match c {
+      '<' => {
+        push_terminal(&mut production, &mut terminal);
+        let mut name = String::new();
+        loop {
+          match chars.next() {
+            Some('>') => break,
+            Some(n) if n != '<' => name.push(n),
+            _ => return Err("unterminated non-terminal".to_string()),
+          }
+        }
+        if name.is_empty() {
+          return Err("empty non-terminal `<>`".to_string());
+        }
+        match Domain::parse(&name) {
+          Some(domain) => production.push(Symbol::Domain(domain?)),
+          None => production.push(Symbol::NonTerminal(name)),
+        }
+      }
+      '"' => {
+        let mut quoted = String::new();
+        loop {
+          match chars.next() {
+            Some('"') => break,
+            Some(q) => quoted.push(q),
+            None => return Err("unterminated quote".to_string()),
+          }
+        }
+        push_terminal(&mut production, &mut terminal);
+        production.push(Symbol::Terminal(quoted));
+      }
+      '|' => {
+        push_terminal(&mut production, &mut terminal);
+        productions.push(finish(production)?);
+        production = vec![];
+      }
+      c if c.is_whitespace() => push_terminal(&mut production, &mut terminal),
+      c => terminal.push(c),
+    }
+  }
+  push_terminal(&mut production, &mut terminal);
+  productions.push(finish(production)?);
+  Ok(productions)
+}
+
+fn push_terminal(production: &mut Production, terminal: &mut String) {
+  if !terminal.is_empty() {
+    production.push(Symbol::Terminal(terminal.clone()));
+    terminal.clear();
+  }
+}
+
+fn finish(production: Production) -> Result<Production, String> {
+  if production.is_empty() {
+    return Err("empty production".to_string());
+  }
+  Ok(production)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_rules() {
+    let grammar = Grammar::parse("<code> ::= <stmnt> | <stmnt>::<code>\n<stmnt> ::= hhv:<ticker>:<param>").unwrap();
+    assert_eq!("code", grammar.start());
+    let code = grammar.productions("code").unwrap();
+    assert_eq!(2, code.len());
+    assert_eq!(
+      vec![
+        Symbol::NonTerminal("stmnt".to_string()),
+        Symbol::Terminal("::".to_string()),
+        Symbol::NonTerminal("code".to_string()),
+      ],
+      code[1]
+    );
+    assert_eq!("hhv:<ticker>:<param>", render(&grammar.productions("stmnt").unwrap()[0]));
+  }
+
+  #[test]
+  fn test_continuation_and_comments() {
+    let source = "# comment\n<a> ::= x\n    | y\n\n    | \"z z\"\n";
+    let grammar = Grammar::parse(source).unwrap();
+    let a = grammar.productions("a").unwrap();
+    assert_eq!(3, a.len());
+    assert_eq!(vec![Symbol::Terminal("z z".to_string())], a[2]);
+  }
+
+  #[test]
+  fn test_undefined_non_terminal() {
+    let err = Grammar::parse("<a> ::= <b>").unwrap_err();
+    assert_eq!(GrammarError::Undefined("b".to_string()), err);
+  }
+
+  #[test]
+  fn test_syntax_errors() {
+    match Grammar::parse("<a> ::= x\n<a> ::= y") {
+      Err(GrammarError::Syntax { line, .. }) => assert_eq!(2, line),
+      other => panic!("unexpected {:?}", other),
+    }
+    assert!(Grammar::parse("<a> = x").is_err());
+    assert!(Grammar::parse("<a> ::= x | ").is_err());
+    assert!(Grammar::parse("<a> ::= <b").is_err());
+    assert_eq!(GrammarError::Empty, Grammar::parse("# nothing").unwrap_err());
+  }
+
+  #[test]
+  fn test_domains() {
+    let grammar = Grammar::parse("<a> ::= hhv:<ticker>:<int:2:250> | x:<set:5,10,1.5> | y:<float:0.5:3.0>").unwrap();
+    let a = grammar.productions("a").unwrap();
+    assert_eq!(Symbol::Domain(Domain::Int(2, 250)), a[0][3]);
+    assert_eq!("hhv:<ticker>:<int:2:250>", render(&a[0]));
+    assert_eq!("x:<set:5,10,1.5>", render(&a[1]));
+    assert_eq!("y:<float:0.5:3.0>", render(&a[2]));
+    assert!(Grammar::parse("<a> ::= <int:10:2>").is_err());
+    assert!(Grammar::parse("<a> ::= <int:x:2>").is_err());
+    assert!(Grammar::parse("<a> ::= <set:>").is_err());
+  }
+
+  #[test]
+  fn test_pick_domain() {
+    assert_eq!(Param::Int(2), Domain::Int(2, 250).pick(0));
+    assert_eq!(Param::Int(3), Domain::Int(2, 250).pick(250));
+    assert_eq!(Param::Int(10), Domain::Int(1, 10).pick(19));
+    // Ranges wider than a codon are scaled
+    assert_eq!(Param::Int(5), Domain::Int(5, 500).pick(1));
+    assert_eq!(Param::Int(500), Domain::Int(5, 500).pick(255));
+    assert_eq!(Param::Int(i32::MIN), Domain::Int(i32::MIN, i32::MAX).pick(1));
+    assert_eq!(Param::Int(i32::MAX), Domain::Int(i32::MIN, i32::MAX).pick(255));
+    assert_eq!(Param::Int(-1), Domain::Int(-1, i32::MAX).pick(1));
+    let set = Domain::Set(vec![Param::Int(5), Param::Int(10), Param::Float(1.5)]);
+    assert_eq!(Param::Float(1.5), set.pick(8));
+    assert_eq!(Param::Float(0.5), Domain::Float(0.5, 3.0).pick(1));
+    assert_eq!(Param::Float(3.0), Domain::Float(0.5, 3.0).pick(255));
+    assert_eq!(Param::Float(1.75), Domain::Float(0.5, 3.0).pick(128));
+  }
+
+  #[test]
+  fn test_define() {
+    let mut grammar = Grammar::parse("<code> ::= <stmnt>\n<stmnt> ::= hhv:<ticker>:<param>").unwrap();
+    let llv = vec![
+      Symbol::Terminal("llv:".to_string()),
+      Symbol::NonTerminal("ticker".to_string()),
+      Symbol::Terminal(":".to_string()),
+      Symbol::Domain(Domain::Int(5, 500)),
+    ];
+    grammar.define(STRATEGIES, vec![llv]).unwrap();
+    let stmnt = grammar.productions("stmnt").unwrap();
+    assert_eq!(vec!["llv:<ticker>:<int:5:500>"], stmnt.iter().map(|p| render(p)).collect::<Vec<String>>());
+    let undefined = vec![vec![Symbol::NonTerminal("nope".to_string())]];
+    assert_eq!(Err(GrammarError::Undefined("nope".to_string())), grammar.define(STRATEGIES, undefined));
+    assert_eq!(1, grammar.productions("stmnt").unwrap().len());
+  }
+
+  #[test]
+  fn test_parse_partial() {
+    let source = "<code> ::= <stmnt> | <stmnt>::<code>";
+    assert_eq!(GrammarError::Undefined("stmnt".to_string()), Grammar::parse(source).unwrap_err());
+    let mut grammar = Grammar::parse_partial(source, &[STRATEGIES]).unwrap();
+    assert!(grammar.productions(STRATEGIES).is_none());
+    let hhv = vec![Symbol::Terminal("hhv:".to_string()), Symbol::NonTerminal("ticker".to_string())];
+    grammar.define(STRATEGIES, vec![hhv]).unwrap();
+    assert_eq!(1, grammar.productions(STRATEGIES).unwrap().len());
+  }
+
+  #[test]
+  fn test_default_grammar() {
+    let grammar = Grammar::default();
+    assert_eq!("rule", grammar.start());
+    assert_eq!(10, grammar.productions("stmnt").unwrap().len());
+  }
+}
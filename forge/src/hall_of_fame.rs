@@ -0,0 +1,147 @@
+//! Hall of fame
+//!
+//! Ranking replaces the chromosomes of a generation with the next, so a rule
+//! that was found early can be lost again a few generations later. The hall
+//! of fame keeps the best chromosomes ever evaluated, one per rule, ranked the
+//! same way as the population: by kelly, or by Pareto front and crowding
+//! distance.
+//!
+//! Members can be put back into the islands every generation, where they
+//! replace the least fit chromosomes like migrants do.
+use pareto;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::mem;
+use {sort_population, Chromosome, Config, Island, Ranking};
+
+/// The best chromosomes of a run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HallOfFame {
+  size: usize,
+  /// Members with the fittest last
+  members: Vec<Chromosome>,
+}
+
+impl HallOfFame {
+  /// Creates an empty hall of fame with room for `size` chromosomes
+  pub fn new(size: usize) -> HallOfFame {
+    HallOfFame { size, members: vec![] }
+  }
+
+  /// Members of the hall of fame with the fittest last
+  pub fn members(&self) -> &[Chromosome] {
+    &self.members
+  }
+
+  /// Ranks ranked chromosomes against the members
+  ///
+  /// Chromosomes with a rule that is already in the hall of fame, invalid
+  /// chromosomes and chromosomes without a fitness are left out. The least
+  /// fit members are dropped when there are more than `size`.
+  pub fn update(&mut self, chromosomes: &[Chromosome], config: &Config) {
+    let mut rules: HashSet<String> = self.members.iter().map(|c| c.chromosome.to_string()).collect();
+    for c in chromosomes.iter().filter(|c| c.valid && !c.fitness().is_nan()) {
+      if rules.insert(c.chromosome.to_string()) {
+        self.members.push(c.clone());
+      }
+    }
+    match config.ranking {
+      Ranking::Kelly => {
+        sort_population(&mut self.members, config);
+        let dropped = self.members.len().saturating_sub(self.size);
+        self.members.drain(..dropped);
+      }
+      Ranking::Pareto => {
+        let members = mem::take(&mut self.members);
+        self.members = pareto::survivors(members, &config.objectives, self.size);
+      }
+    }
+  }
+
+  /// Puts the `count` fittest members with a rule the island doesn't have
+  /// into the island
+  pub fn inject(&self, island: &mut Island, count: usize) {
+    let rules: HashSet<String> = island.population.iter().map(|c| c.chromosome.to_string()).collect();
+    let members: Vec<Chromosome> = self
+      .members
+      .iter()
+      .rev()
+      .filter(|c| !rules.contains(&c.chromosome.to_string()))
+      .take(count)
+      .cloned()
+      .collect();
+    island.receive(members);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use {testing, IslandSpec, Objective};
+
+  // Chromosomes with the given rules and kelly
+  fn chromosomes(scores: &[(&str, f32)]) -> Vec<Chromosome> {
+    let mut chromosomes = testing::chromosomes(scores.len());
+    for (c, (rule, kelly)) in chromosomes.iter_mut().zip(scores) {
+      c.chromosome = rule.parse().unwrap();
+      c.kelly = *kelly;
+    }
+    chromosomes
+  }
+
+  fn kellies(chromosomes: &[Chromosome]) -> Vec<f32> {
+    chromosomes.iter().map(|c| c.kelly).collect()
+  }
+
+  #[test]
+  fn test_update_keeps_the_best_rules() {
+    let config = Config::default();
+    let mut hall_of_fame = HallOfFame::new(3);
+    hall_of_fame.update(&chromosomes(&[("hhv:AAPL:20", 1.0), ("llv:AAPL:20", 3.0)]), &config);
+    assert_eq!(vec![1.0, 3.0], kellies(hall_of_fame.members()));
+    // The same rule isn't let in twice, even when it does better
+    let mut next = chromosomes(&[("hhv:AAPL:20", 5.0), ("abovema:AAPL:20", 2.0), ("belowma:AAPL:20", 4.0)]);
+    next[1].invalidate();
+    hall_of_fame.update(&next, &config);
+    assert_eq!(vec![1.0, 3.0, 4.0], kellies(hall_of_fame.members()));
+    hall_of_fame.update(&chromosomes(&[("hhv:AAPL:50", 2.0), ("llv:AAPL:50", f32::NAN)]), &config);
+    assert_eq!(vec![2.0, 3.0, 4.0], kellies(hall_of_fame.members()));
+  }
+
+  #[test]
+  fn test_update_by_pareto_front() {
+    let config = Config {
+      ranking: Ranking::Pareto,
+      objectives: vec![Objective::Kelly, Objective::MaxDrawdown],
+      ..Config::default()
+    };
+    let mut hall_of_fame = HallOfFame::new(2);
+    let mut population = chromosomes(&[("hhv:AAPL:20", 1.0), ("llv:AAPL:20", 3.0), ("abovema:AAPL:20", 0.5)]);
+    for (c, drawdown) in population.iter_mut().zip(&[0.1, 0.3, 0.2]) {
+      c.max_drawdown = *drawdown;
+    }
+    hall_of_fame.update(&population, &config);
+    // Both members are on the first front, which drops the third chromosome
+    let mut kept = kellies(hall_of_fame.members());
+    kept.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(vec![1.0, 3.0], kept);
+    assert!(hall_of_fame.members().iter().all(|c| c.front == 1));
+  }
+
+  #[test]
+  fn test_inject() {
+    let config = Config {
+      population_size: 3,
+      ..Config::default()
+    };
+    let mut hall_of_fame = HallOfFame::new(3);
+    hall_of_fame.update(&chromosomes(&[("hhv:AAPL:20", 7.0), ("llv:AAPL:20", 8.0), ("abovema:AAPL:20", 9.0)]), &config);
+    let tickers = vec!["AAPL".to_string()];
+    let mut island = Island::new(1, &IslandSpec::default(), &config, &tickers, 1).unwrap();
+    island.population = chromosomes(&[("hhv:AAPL:50", 1.0), ("llv:AAPL:50", 2.0), ("abovema:AAPL:20", 9.0)]);
+    hall_of_fame.inject(&mut island, 1);
+    // The fittest member is already on the island so the next one goes in
+    assert_eq!(vec![2.0, 8.0, 9.0], kellies(&island.population));
+    assert_eq!(1, island.population[1].island);
+  }
+}
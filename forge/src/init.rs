@@ -0,0 +1,383 @@
+//! Initialization of the first generation
+//!
+//! Uniform random codons mostly decode to the same short rules. Sensible
+//! initialization grows a derivation tree of the grammar for each chromosome
+//! instead and writes the choices it made back as dna. The depth of the trees
+//! is ramped from the shallowest tree the grammar allows up to a max depth.
+//! Half of the trees at each depth are grown, picking any production that
+//! fits, and half are full, preferring recursive productions until the depth
+//! is reached. Trees whose rule is already in the generation are rejected.
+//!
+//! Methods parse from these strings.
+//!
+//! ```text
+//! random       uniform random codons
+//! sensible:8   ramped trees up to a depth of 8
+//! ```
+use config::CODON_SIZE;
+use grammar::{Production, Symbol};
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use {generate_dnas, Config, Dna, Grammar, Rule};
+
+/// Trees tried for each chromosome before the rest of the generation falls
+/// back to random dna
+pub static INIT_ATTEMPTS: usize = 20;
+
+/// How the dna of the first generation is made
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Initialization {
+  Random,
+  /// Ramped derivation trees up to a max depth
+  Sensible(usize),
+}
+
+impl fmt::Display for Initialization {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Initialization::Random => write!(f, "random"),
+      Initialization::Sensible(depth) => write!(f, "sensible:{}", depth),
+    }
+  }
+}
+
+impl FromStr for Initialization {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Initialization, String> {
+    let mut parts = s.splitn(2, ':');
+    match (parts.next().unwrap_or(""), parts.next()) {
+      ("random", None) => Ok(Initialization::Random),
+      ("sensible", Some(depth)) => match depth.parse::<usize>() {
+        Ok(depth) if depth > 0 => Ok(Initialization::Sensible(depth)),
+        _ => Err(format!("max depth in `{}` should be a positive number", s)),
+      },
+      _ => Err(format!("unknown initialization `{}`", s)),
+    }
+  }
+}
+
+/// Makes the dna of the first generation with the initialization method in
+/// the config
+pub fn initial_dnas(
+  qty: i32,
+  grammar: &Grammar,
+  tickers: &Vec<String>,
+  config: &Config,
+  rng: &mut dyn RngCore,
+) -> Vec<Dna> {
+  match config.initialization {
+    Initialization::Random => generate_dnas(config.dna_length as i32, qty, rng),
+    Initialization::Sensible(max_depth) => sensible_dnas(qty, max_depth, grammar, tickers, config, rng),
+  }
+}
+
+/// Makes dna from ramped derivation trees
+///
+/// Trees whose rule is a duplicate, doesn't parse, has fewer or more genes
+/// than the config allows or whose dna is longer than `max_dna_length` are
+/// rejected. Dna shorter than `min_dna_length` gets a tail of random codons,
+/// which decoding never reaches. When too many trees are rejected the rest of
+/// the generation is random dna.
+pub fn sensible_dnas(
+  qty: i32,
+  max_depth: usize,
+  grammar: &Grammar,
+  tickers: &Vec<String>,
+  config: &Config,
+  rng: &mut dyn RngCore,
+) -> Vec<Dna> {
+  let qty = qty.max(0) as usize;
+  let depths = Depths::new(grammar);
+  let min_depth = match depths.min.get(grammar.start()) {
+    Some(&depth) => depth,
+    None => {
+      warn!("Grammar never finishes deriving <{}>, starting from random dna", grammar.start());
+      return generate_dnas(config.dna_length as i32, qty as i32, rng);
+    }
+  };
+  let levels = max_depth.max(min_depth) - min_depth + 1;
+  let start = Symbol::NonTerminal(grammar.start().to_string());
+  let mut rules: HashSet<String> = HashSet::new();
+  let mut dnas: Vec<Dna> = vec![];
+  let mut attempts = 0;
+  while dnas.len() < qty && attempts < qty * INIT_ATTEMPTS {
+    // Each depth gets a grown and a full tree in turn
+    let depth = min_depth + (attempts / 2) % levels;
+    let full = attempts % 2 == 1;
+    attempts += 1;
+    let mut tree = Tree {
+      grammar,
+      depths: &depths,
+      tickers,
+      full,
+      dna: vec![],
+      code: String::new(),
+    };
+    tree.grow(&start, depth, rng);
+    if tree.dna.len() > config.max_dna_length || rules.contains(&tree.code) {
+      continue;
+    }
+    let genes = match Rule::parse(&tree.code) {
+      Ok(rule) => rule.genes().len(),
+      Err(_) => continue,
+    };
+    let too_long = config.max_chromosome_length > 0 && genes > config.max_chromosome_length;
+    if genes < config.min_chromosome_length || too_long {
+      continue;
+    }
+    let mut dna = tree.dna;
+    while dna.len() < config.min_dna_length {
+      dna.push(rng.gen_range(1, CODON_SIZE));
+    }
+    rules.insert(tree.code);
+    dnas.push(dna);
+  }
+  if dnas.len() < qty {
+    warn!("Found {} distinct rules for the first generation, the rest is random dna", dnas.len());
+    let random = (qty - dnas.len()) as i32;
+    dnas.extend(generate_dnas(config.dna_length as i32, random, rng));
+  }
+  dnas
+}
+
+// Depth of the shallowest tree under each non-terminal and the non-terminals
+// that can derive themselves
+struct Depths<'a> {
+  grammar: &'a Grammar,
+  min: HashMap<String, usize>,
+  recursive: HashSet<String>,
+}
+
+impl<'a> Depths<'a> {
+  fn new(grammar: &'a Grammar) -> Depths<'a> {
+    let mut depths = Depths {
+      grammar,
+      min: HashMap::new(),
+      recursive: HashSet::new(),
+    };
+    // Depths only go down, so this settles once no non-terminal changes
+    let mut changed = true;
+    while changed {
+      changed = false;
+      for name in grammar.non_terminals() {
+        let shallowest = grammar
+          .productions(name)
+          .unwrap_or(&[])
+          .iter()
+          .filter_map(|p| depths.production(p))
+          .min();
+        if let Some(depth) = shallowest.map(|d| d + 1) {
+          if depth < depths.min.get(name).cloned().unwrap_or(usize::MAX) {
+            depths.min.insert(name.to_string(), depth);
+            changed = true;
+          }
+        }
+      }
+    }
+    for name in grammar.non_terminals() {
+      if depths.reaches(name, name) {
+        depths.recursive.insert(name.to_string());
+      }
+    }
+    depths
+  }
+
+  // Depth of the shallowest tree under a symbol. None when it never finishes
+  fn symbol(&self, symbol: &Symbol) -> Option<usize> {
+    match symbol {
+      Symbol::Terminal(_) => Some(0),
+      Symbol::Domain(_) => Some(1),
+      Symbol::NonTerminal(name) => match self.grammar.productions(name) {
+        Some(_) => self.min.get(name).cloned(),
+        None => Some(1),
+      },
+    }
+  }
+
+  fn production(&self, production: &Production) -> Option<usize> {
+    production.iter().try_fold(0, |max, s| self.symbol(s).map(|d| max.max(d)))
+  }
+
+  fn is_recursive(&self, production: &Production) -> bool {
+    production.iter().any(|s| match s {
+      Symbol::NonTerminal(name) => self.recursive.contains(name),
+      _ => false,
+    })
+  }
+
+  // Whether `to` can be derived from `from` in one or more steps
+  fn reaches(&self, from: &str, to: &str) -> bool {
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut stack = vec![from];
+    while let Some(name) = stack.pop() {
+      for production in self.grammar.productions(name).unwrap_or(&[]) {
+        for symbol in production {
+          if let Symbol::NonTerminal(next) = symbol {
+            if next.as_str() == to {
+              return true;
+            }
+            if seen.insert(next.as_str()) {
+              stack.push(next);
+            }
+          }
+        }
+      }
+    }
+    false
+  }
+}
+
+// A derivation tree as it grows, kept as the codons that pick its productions
+// and the code it derives
+struct Tree<'a> {
+  grammar: &'a Grammar,
+  depths: &'a Depths<'a>,
+  tickers: &'a Vec<String>,
+  full: bool,
+  dna: Dna,
+  code: String,
+}
+
+impl<'a> Tree<'a> {
+  // Expands the symbol depth first from the left, the order decoding reads
+  // codons in. `depth` counts the levels left including the symbol
+  fn grow(&mut self, symbol: &Symbol, depth: usize, rng: &mut dyn RngCore) {
+    let name = match symbol {
+      Symbol::Terminal(t) => {
+        self.code.push_str(t);
+        return;
+      }
+      Symbol::Domain(domain) => {
+        let base = rng.gen_range(1, CODON_SIZE);
+        self.dna.push(base);
+        self.code.push_str(&domain.pick(base).to_string());
+        return;
+      }
+      Symbol::NonTerminal(name) => name,
+    };
+    let productions = match self.grammar.productions(name) {
+      Some(productions) => productions,
+      None => return self.builtin(name, rng),
+    };
+    let fits: Vec<usize> = (0..productions.len())
+      .filter(|&i| self.depths.production(&productions[i]).map_or(false, |d| d < depth))
+      .collect();
+    let recursive: Vec<usize> = fits
+      .iter()
+      .cloned()
+      .filter(|&i| self.depths.is_recursive(&productions[i]))
+      .collect();
+    let choices = if self.full && !recursive.is_empty() { recursive } else { fits };
+    // The parent only picks productions that fit, so there is always a choice
+    let index = choices[rng.gen_range(0, choices.len())];
+    // Non-terminals with a single production don't use up a codon
+    if productions.len() > 1 {
+      self.dna.push(codon(index, productions.len(), rng));
+    }
+    for child in &productions[index] {
+      self.grow(child, depth - 1, rng);
+    }
+  }
+
+  fn builtin(&mut self, name: &str, rng: &mut dyn RngCore) {
+    let base = rng.gen_range(0, CODON_SIZE);
+    self.dna.push(base);
+    match name {
+      "ticker" => self.code.push_str(&self.tickers[base as usize % self.tickers.len()]),
+      "param" => self.code.push_str(&base.to_string()),
+      _ => unreachable!("grammar validation allows only builtins without productions"),
+    }
+  }
+}
+
+// A random codon that picks production `index` out of `len`
+fn codon(index: usize, len: usize, rng: &mut dyn RngCore) -> i32 {
+  let (index, len) = (index as i32, len as i32);
+  index + len * rng.gen_range(0, (CODON_SIZE - 1 - index).max(0) / len + 1)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use {decode_dna, seeded_rng};
+
+  fn tickers() -> Vec<String> {
+    vec!["AAPL".to_string(), "MSFT".to_string(), "DAL".to_string()]
+  }
+
+  #[test]
+  fn test_parse_initialization() {
+    assert_eq!(Ok(Initialization::Random), "random".parse());
+    assert_eq!(Ok(Initialization::Sensible(8)), "sensible:8".parse());
+    assert_eq!(Ok(Initialization::Sensible(6)), Initialization::Sensible(6).to_string().parse());
+    assert!("sensible".parse::<Initialization>().is_err());
+    assert!("sensible:0".parse::<Initialization>().is_err());
+    assert!("ramped".parse::<Initialization>().is_err());
+  }
+
+  #[test]
+  fn test_depths() {
+    let grammar = Grammar::default();
+    let depths = Depths::new(&grammar);
+    // <rule>, <code>, <stmnt>, <int:5:500>
+    assert_eq!(Some(&4), depths.min.get("rule"));
+    assert_eq!(Some(&2), depths.min.get("stmnt"));
+    assert!(depths.recursive.contains("code"));
+    assert!(!depths.recursive.contains("rule"));
+    assert!(!depths.recursive.contains("stmnt"));
+  }
+
+  #[test]
+  fn test_sensible_dnas_decode_to_distinct_rules() {
+    let config = Config {
+      initialization: Initialization::Sensible(7),
+      ..Config::default()
+    };
+    let dnas = initial_dnas(200, &Grammar::default(), &tickers(), &config, &mut seeded_rng(1));
+    assert_eq!(200, dnas.len());
+    let rules: Vec<String> = dnas
+      .iter()
+      .map(|dna| decode_dna(&Grammar::default(), dna, &tickers(), 0).expect("sensible dna decodes without wrapping"))
+      .collect();
+    let distinct: HashSet<&String> = rules.iter().collect();
+    assert_eq!(200, distinct.len());
+    let genes: Vec<usize> = rules.iter().map(|r| Rule::parse(r).unwrap().genes().len()).collect();
+    assert_eq!(Some(&1), genes.iter().min());
+    assert_eq!(Some(&4), genes.iter().max());
+    assert!(dnas.iter().all(|dna| dna.len() >= config.min_dna_length));
+  }
+
+  #[test]
+  fn test_full_trees_reach_the_depth() {
+    let grammar = Grammar::default();
+    let depths = Depths::new(&grammar);
+    let tickers = tickers();
+    let mut tree = Tree {
+      grammar: &grammar,
+      depths: &depths,
+      tickers: &tickers,
+      full: true,
+      dna: vec![],
+      code: String::new(),
+    };
+    tree.grow(&Symbol::NonTerminal("rule".to_string()), 6, &mut seeded_rng(1));
+    assert_eq!(3, Rule::parse(&tree.code).unwrap().genes().len());
+  }
+
+  #[test]
+  fn test_small_grammars_fall_back_to_random_dna() {
+    let grammar = Grammar::parse("<code> ::= hhv:<ticker>:<set:5,10>").unwrap();
+    let config = Config {
+      initialization: Initialization::Sensible(4),
+      ..Config::default()
+    };
+    let dnas = initial_dnas(10, &grammar, &tickers(), &config, &mut seeded_rng(1));
+    assert_eq!(10, dnas.len());
+    let rules: HashSet<String> = dnas.iter().map(|dna| decode_dna(&grammar, dna, &tickers(), 0).unwrap()).collect();
+    assert_eq!(6, rules.len());
+  }
+}
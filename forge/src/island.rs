@@ -0,0 +1,456 @@
+//! Island model
+//!
+//! The population is split into islands that evolve on their own. Each island
+//! draws from its own random number generator and can have its own mutation
+//! rates and pool of tickers, so islands drift towards different rules
+//! instead of the whole population converging on a few tickers.
+//!
+//! Every few generations the fittest chromosomes of each island migrate to
+//! its neighbours and replace their least fit chromosomes. Neighbours are set
+//! by a `Topology`, which parses from the strings below.
+//!
+//! ```text
+//! ring   each island sends migrants to the next island
+//! full   each island sends migrants to every other island
+//! ```
+use diversity::Diversity;
+use pareto;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+use {
+  generate_chromosomes, init, seeded_rng, sort_population, AdaptiveRates, Chromosome, Config, Dna, Grammar,
+  Mutation, Random, Ranking,
+};
+
+/// Which islands exchange migrants
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Topology {
+  /// Island `i` sends migrants to island `i + 1` and the last island to the
+  /// first
+  Ring,
+  /// Every island sends migrants to every other island
+  FullyConnected,
+}
+
+impl Topology {
+  /// Islands that send migrants to island `to` out of `islands`
+  pub fn sources(self, to: usize, islands: usize) -> Vec<usize> {
+    match self {
+      Topology::Ring if islands > 1 => vec![(to + islands - 1) % islands],
+      Topology::Ring => vec![],
+      Topology::FullyConnected => (0..islands).filter(|from| *from != to).collect(),
+    }
+  }
+}
+
+impl fmt::Display for Topology {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Topology::Ring => write!(f, "ring"),
+      Topology::FullyConnected => write!(f, "full"),
+    }
+  }
+}
+
+impl FromStr for Topology {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Topology, String> {
+    match s {
+      "ring" => Ok(Topology::Ring),
+      "full" => Ok(Topology::FullyConnected),
+      _ => Err(format!("unknown topology `{}`", s)),
+    }
+  }
+}
+
+/// Settings of one island that differ from the rest of the run
+///
+/// Parses from `seed=7;mutation=creep=0.3,reset=0.01;tickers=AAPL,MSFT`.
+/// Settings that are left out of the string are taken from the run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IslandSpec {
+  /// Seed of the random number generator of the island
+  pub seed: Option<u64>,
+  /// Rates of the mutation operators on the island
+  pub mutation: Option<Mutation>,
+  /// Tickers the rules of the island are built from
+  pub tickers: Option<Vec<String>>,
+}
+
+impl FromStr for IslandSpec {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<IslandSpec, String> {
+    let mut spec = IslandSpec::default();
+    for part in s.split(';').filter(|p| !p.is_empty()) {
+      let mut kv = part.splitn(2, '=');
+      let key = kv.next().unwrap_or("");
+      let value = match kv.next() {
+        Some(value) => value,
+        None => return Err(format!("invalid island setting `{}`", part)),
+      };
+      match key {
+        "seed" => spec.seed = Some(value.parse().map_err(|_| format!("invalid island seed `{}`", value))?),
+        "mutation" => spec.mutation = Some(value.parse()?),
+        "tickers" => {
+          let tickers: Vec<String> = value.split(',').filter(|t| !t.is_empty()).map(|t| t.to_string()).collect();
+          if tickers.is_empty() {
+            return Err(format!("island setting `{}` has no tickers", part));
+          }
+          spec.tickers = Some(tickers);
+        }
+        _ => return Err(format!("unknown island setting `{}`", key)),
+      }
+    }
+    Ok(spec)
+  }
+}
+
+/// A population that evolves on its own
+///
+/// Between generations the random number generator of an island has just
+/// been seeded from `seed`, so an island is saved with its seed and restored
+/// to the same state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "SavedIsland", into = "SavedIsland")]
+pub struct Island {
+  /// Tags the chromosomes of the island, starting from 0
+  pub id: i32,
+  /// Config of the run with the settings of the island applied
+  pub config: Config,
+  /// Tickers the rules of the island are built from
+  pub tickers: Vec<String>,
+  /// Seed the random number generator was last seeded from
+  pub seed: u64,
+  /// Random number generator of the island
+  pub rng: Random,
+  /// Ranked chromosomes of the island with the fittest last
+  pub population: Vec<Chromosome>,
+  /// Dna the first generation starts from before random dna fills up the
+  /// rest of the population
+  pub seeds: Vec<Dna>,
+  /// Mutation and crossover rates of the island as they adapt
+  pub adaptive: AdaptiveRates,
+}
+
+// An island without its random number generator
+#[derive(Serialize, Deserialize)]
+struct SavedIsland {
+  id: i32,
+  config: Config,
+  tickers: Vec<String>,
+  seed: u64,
+  population: Vec<Chromosome>,
+  seeds: Vec<Dna>,
+  adaptive: AdaptiveRates,
+}
+
+impl From<SavedIsland> for Island {
+  fn from(saved: SavedIsland) -> Island {
+    Island {
+      id: saved.id,
+      config: saved.config,
+      tickers: saved.tickers,
+      seed: saved.seed,
+      rng: seeded_rng(saved.seed),
+      population: saved.population,
+      seeds: saved.seeds,
+      adaptive: saved.adaptive,
+    }
+  }
+}
+
+impl From<Island> for SavedIsland {
+  fn from(island: Island) -> SavedIsland {
+    SavedIsland {
+      id: island.id,
+      config: island.config,
+      tickers: island.tickers,
+      seed: island.seed,
+      population: island.population,
+      seeds: island.seeds,
+      adaptive: island.adaptive,
+    }
+  }
+}
+
+/// Seed of an island without its own seed
+///
+/// Other islands hash their id into the seed of the run with a splitmix64
+/// step, so island 1 of seed 7 isn't island 0 of seed 8.
+fn island_seed(seed: u64, id: i32) -> u64 {
+  if id == 0 {
+    return seed;
+  }
+  let mut z = seed ^ (id as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+  z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+  z ^ (z >> 31)
+}
+
+impl Island {
+  /// Creates an island from the settings of the run and of the island
+  ///
+  /// Islands without their own seed mix their id into the seed of the run.
+  /// The first island keeps the seed of the run, so a run with a single
+  /// island draws the same numbers as a run without islands. Tickers of the
+  /// island must be in the pool of the run.
+  pub fn new(id: i32, spec: &IslandSpec, config: &Config, tickers: &Vec<String>, seed: u64) -> Result<Island, String> {
+    let mut config = config.clone();
+    if let Some(ref mutation) = spec.mutation {
+      config.mutation = mutation.clone();
+    }
+    let tickers = match spec.tickers {
+      Some(ref subset) => {
+        if let Some(unknown) = subset.iter().find(|t| !tickers.contains(t)) {
+          return Err(format!("island {} has ticker `{}` which isn't in the pool", id, unknown));
+        }
+        subset.clone()
+      }
+      None => tickers.clone(),
+    };
+    let seed = spec.seed.unwrap_or_else(|| island_seed(seed, id));
+    let adaptive = AdaptiveRates::new(&config);
+    Ok(Island {
+      id,
+      config,
+      tickers,
+      seed,
+      rng: seeded_rng(seed),
+      population: vec![],
+      seeds: vec![],
+      adaptive,
+    })
+  }
+
+  /// Generates the chromosomes of the next generation of the island
+  ///
+  /// An island without ranked chromosomes, i.e. in the first generation,
+  /// starts from its seeds and the dna of the initialization method in the
+  /// config. Otherwise the ranked chromosomes are evolved.
+  /// The random number generator is seeded again from itself afterwards.
+  pub fn next_generation(&mut self, generation: i32, target_ticker: &str, grammar: &Grammar) -> Vec<Chromosome> {
+    let mut chromosomes = self.breed(generation, target_ticker, grammar);
+    for chromosome in &mut chromosomes {
+      chromosome.island = self.id;
+    }
+    self.seed = self.rng.gen();
+    self.rng = seeded_rng(self.seed);
+    chromosomes
+  }
+
+  fn breed(&mut self, generation: i32, target_ticker: &str, grammar: &Grammar) -> Vec<Chromosome> {
+    let rng: &mut dyn RngCore = &mut self.rng;
+    if self.population.is_empty() {
+      let mut dnas = mem::take(&mut self.seeds);
+      dnas.truncate(self.config.population_size.max(0) as usize);
+      let random = self.config.population_size - dnas.len() as i32;
+      dnas.extend(init::initial_dnas(random, grammar, &self.tickers, &self.config, rng));
+      generate_chromosomes(dnas, generation, target_ticker, &self.tickers, grammar, &self.config, rng)
+    } else {
+      ::evolve(
+        self.population.clone(),
+        generation,
+        &self.tickers,
+        target_ticker,
+        grammar,
+        &self.config,
+        rng,
+      )
+    }
+  }
+
+  /// Adapts the mutation and crossover rates of the island to its last
+  /// ranked generation
+  ///
+  /// Does nothing unless the config has adaptive rates.
+  pub fn adapt(&mut self, diversity: &Diversity) {
+    let adaptation = match self.config.adaptation {
+      Some(ref adaptation) => adaptation.clone(),
+      None => return,
+    };
+    let best_fitness = self.population.iter().map(|c| c.fitness()).fold(f32::NEG_INFINITY, f32::max);
+    let diversity = diversity.blend(self.config.genotype_weight);
+    self.adaptive.update(best_fitness, diversity, &adaptation, self.config.epsilon);
+    self.config.mutation = self.adaptive.mutation();
+    self.config.crossover_prob = self.adaptive.crossover_prob();
+  }
+
+  /// Replaces the least fit chromosomes of the island with migrants
+  ///
+  /// Fronts are sorted again when ranking by Pareto front since the migrants
+  /// were given their front on another island.
+  pub fn receive(&mut self, migrants: Vec<Chromosome>) {
+    let replaced = migrants.len().min(self.population.len());
+    self.population.drain(..replaced);
+    for mut migrant in migrants {
+      migrant.island = self.id;
+      self.population.push(migrant);
+    }
+    if self.config.ranking == Ranking::Pareto {
+      pareto::assign_fronts(&mut self.population, &self.config.objectives);
+    }
+    sort_population(&mut self.population, &self.config);
+  }
+}
+
+/// Copies the `count` fittest chromosomes of each island to its neighbours
+///
+/// Migrants replace the least fit chromosomes of the island they arrive on,
+/// which is then ranked again. Islands keep their own copy of the chromosomes
+/// they send.
+pub fn migrate(islands: &mut [Island], topology: Topology, count: usize) {
+  let emigrants: Vec<Vec<Chromosome>> = islands
+    .iter()
+    .map(|island| island.population.iter().rev().take(count).cloned().collect())
+    .collect();
+  let n = islands.len();
+  for (to, island) in islands.iter_mut().enumerate() {
+    let migrants: Vec<Chromosome> = topology
+      .sources(to, n)
+      .into_iter()
+      .flat_map(|from| emigrants[from].clone())
+      .collect();
+    island.receive(migrants);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn tickers() -> Vec<String> {
+    vec!["AAPL".to_string(), "MSFT".to_string(), "DAL".to_string()]
+  }
+
+  // Islands of three chromosomes with kelly `10 * island + i`
+  fn islands(n: i32) -> Vec<Island> {
+    let config = Config {
+      population_size: 3,
+      ..Config::default()
+    };
+    (0..n)
+      .map(|id| {
+        let mut island = Island::new(id, &IslandSpec::default(), &config, &tickers(), 1).unwrap();
+        island.population = island.next_generation(1, "SPY", &Grammar::default());
+        for (i, c) in island.population.iter_mut().enumerate() {
+          c.kelly = (10 * id) as f32 + i as f32;
+        }
+        island
+      })
+      .collect()
+  }
+
+  fn kellies(island: &Island) -> Vec<f32> {
+    island.population.iter().map(|c| c.kelly).collect()
+  }
+
+  #[test]
+  fn test_sources() {
+    assert_eq!(vec![3], Topology::Ring.sources(0, 4));
+    assert_eq!(vec![1], Topology::Ring.sources(2, 4));
+    assert_eq!(Vec::<usize>::new(), Topology::Ring.sources(0, 1));
+    assert_eq!(vec![0, 1, 3], Topology::FullyConnected.sources(2, 4));
+  }
+
+  #[test]
+  fn test_migrate_ring() {
+    let mut islands = islands(3);
+    migrate(&mut islands, Topology::Ring, 1);
+    assert_eq!(vec![1.0, 2.0, 22.0], kellies(&islands[0]));
+    assert_eq!(vec![2.0, 11.0, 12.0], kellies(&islands[1]));
+    assert_eq!(vec![12.0, 21.0, 22.0], kellies(&islands[2]));
+    assert!(islands.iter().all(|island| island.population.iter().all(|c| c.island == island.id)));
+  }
+
+  #[test]
+  fn test_migrate_fully_connected() {
+    let mut islands = islands(3);
+    migrate(&mut islands, Topology::FullyConnected, 1);
+    assert_eq!(vec![2.0, 12.0, 22.0], kellies(&islands[0]));
+    assert_eq!(vec![2.0, 12.0, 22.0], kellies(&islands[1]));
+  }
+
+  #[test]
+  fn test_island_settings() {
+    let spec: IslandSpec = "seed=7;mutation=creep=0.3,reset=0.01;tickers=AAPL,DAL".parse().unwrap();
+    let island = Island::new(2, &spec, &Config::default(), &tickers(), 1).unwrap();
+    assert_eq!(0.3, island.config.mutation.creep);
+    assert_eq!(0.01, island.config.mutation.reset);
+    assert_eq!(vec!["AAPL".to_string(), "DAL".to_string()], island.tickers);
+    let spec: IslandSpec = "tickers=AAPL,TSLA".parse().unwrap();
+    assert!(Island::new(0, &spec, &Config::default(), &tickers(), 1).is_err());
+    for spec in &["seed", "seed=x", "tickers=", "mutation=flip=1", "size=3"] {
+      assert!(spec.parse::<IslandSpec>().is_err(), "{}", spec);
+    }
+  }
+
+  #[test]
+  fn test_saved_island_carries_on() {
+    let mut island = islands(1).remove(0);
+    let saved: Island = ::bincode::deserialize(&::bincode::serialize(&island).unwrap()).unwrap();
+    assert_eq!(island.seed, saved.seed);
+    assert_eq!(kellies(&island), kellies(&saved));
+    let mut saved = saved;
+    let dnas = |island: &mut Island| -> Vec<Dna> {
+      island.next_generation(2, "SPY", &Grammar::default()).into_iter().map(|c| c.dna).collect()
+    };
+    assert_eq!(dnas(&mut island), dnas(&mut saved));
+  }
+
+  #[test]
+  fn test_islands_draw_from_their_own_seed() {
+    let dna = |spec: &str, id: i32| {
+      let mut island = Island::new(id, &spec.parse().unwrap(), &Config::default(), &tickers(), 1).unwrap();
+      ::generate_dnas(4, 1, &mut island.rng)
+    };
+    assert_eq!(dna("", 0), ::generate_dnas(4, 1, &mut seeded_rng(1)));
+    assert_eq!(dna("seed=7", 1), dna("seed=7", 0));
+    assert_ne!(dna("", 0), dna("", 1));
+    // Runs with nearby seeds don't share islands
+    assert_ne!(dna("", 1), dna("seed=2", 0));
+    assert_ne!(island_seed(1, 2), island_seed(2, 1));
+  }
+
+  #[test]
+  fn test_adapt() {
+    let diverse = Diversity {
+      genotype: 0.5,
+      behaviour: 0.5,
+    };
+    let mut island = islands(1).remove(0);
+    island.adapt(&diverse);
+    assert_eq!(Mutation::default(), island.config.mutation);
+    island.config.adaptation = Some("factor=2".parse().unwrap());
+    island.adapt(&diverse);
+    assert_eq!(0.1, island.config.mutation.creep);
+    // The best fitness didn't improve
+    island.adapt(&diverse);
+    island.adapt(&diverse);
+    assert_eq!(0.4, island.config.mutation.creep);
+    assert_eq!(0.5, island.config.crossover_prob);
+  }
+
+  #[test]
+  fn test_seeds_start_the_first_generation() {
+    let config = Config {
+      population_size: 3,
+      ..Config::default()
+    };
+    let mut island = Island::new(0, &IslandSpec::default(), &config, &tickers(), 1).unwrap();
+    island.seeds = vec![vec![1, 2, 3, 4], vec![5, 6, 7, 8]];
+    let chromosomes = island.next_generation(1, "SPY", &Grammar::default());
+    assert_eq!(3, chromosomes.len());
+    assert_eq!(vec![1, 2, 3, 4], chromosomes[0].dna);
+    assert_eq!(vec![5, 6, 7, 8], chromosomes[1].dna);
+    assert!(island.seeds.is_empty());
+    // Seeds past the population size are dropped
+    let mut island = Island::new(0, &IslandSpec::default(), &config, &tickers(), 1).unwrap();
+    island.seeds = vec![vec![1, 2, 3, 4]; 5];
+    assert_eq!(3, island.next_generation(1, "SPY", &Grammar::default()).len());
+  }
+}
@@ -7,18 +7,53 @@ extern crate uuid;
 #[macro_use]
 extern crate log;
 extern crate env_logger;
+extern crate serde;
+#[cfg(test)]
+extern crate bincode;
 
+use grammar::Symbol;
 use rand::prelude::*;
+use rand::prng::ChaChaRng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use uuid::Uuid;
 
-mod config;
+pub mod adaptive;
+pub mod config;
+pub mod crossover;
+pub mod diversity;
+pub mod grammar;
+pub mod hall_of_fame;
+pub mod init;
+pub mod island;
+pub mod mutation;
+pub mod pareto;
+pub mod rule;
+pub mod selection;
+pub mod termination;
+#[cfg(test)]
+pub mod testing;
+
+pub use adaptive::{Adaptation, AdaptiveRates};
+pub use config::Config;
+pub use crossover::Crossover;
+pub use diversity::Niching;
+pub use grammar::Grammar;
+pub use hall_of_fame::HallOfFame;
+pub use init::Initialization;
+pub use island::{Island, IslandSpec, Topology};
+pub use mutation::Mutation;
+pub use pareto::{Objective, Ranking};
+pub use rule::{Gene, Param, ParseError, Rule};
+pub use selection::Selection;
+pub use termination::{StopReason, Termination};
 
 /// chromosome type
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Chromosome {
   pub id: Uuid,
   pub target_ticker: String,
-  pub chromosome: String,
+  pub chromosome: Rule,
   pub dna: Vec<i32>,
   pub generation: i32,
   pub chromosome_length: i32,
@@ -32,33 +67,131 @@ pub struct Chromosome {
   pub losing_trades: i32,
   pub percentage_winners: f32,
   pub rank: i32,
+  /// False when the dna couldn't be decoded or the rule couldn't be evaluated
+  pub valid: bool,
+  /// Summary of the days the rule went long
+  pub long: LegStats,
+  /// Summary of the days the rule went short
+  pub short: LegStats,
+  /// Largest drop of cumulative pnl from its running peak
+  pub max_drawdown: f32,
+  /// Pareto front the chromosome is in, starting from 1. 0 until ranked
+  pub front: i32,
+  /// Crowding distance within the Pareto front
+  pub crowding_distance: f32,
+  /// Island the chromosome lives on. 0 when there is a single population
+  pub island: i32,
+  /// Days the rule took a position on and its direction, sorted by
+  /// timestamp. Empty until the chromosome is evaluated
+  pub behaviour: Vec<(i64, i32)>,
+  /// Ids of the chromosomes this one was bred from. Empty in the first
+  /// generation and for elites
+  pub parents: Vec<Uuid>,
+  /// Parsimony penalty taken off kelly for each gene in the rule
+  pub penalty: f32,
 }
 
-/// Generate chromosomes
+/// Summary data for the trades on one side of the market
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LegStats {
+  pub num_of_trades: i32,
+  pub cum_pnl: f32,
+  pub mean_return: f32,
+  pub variance: f32,
+  pub kelly: f32,
+  pub winning_trades: i32,
+  pub losing_trades: i32,
+  pub percentage_winners: f32,
+}
+
+impl Chromosome {
+  /// Fitness used to rank and select chromosomes
+  ///
+  /// Fitness is kelly less the parsimony penalty of the rule.
+  pub fn fitness(&self) -> f32 {
+    self.kelly - self.penalty
+  }
+
+  /// Marks the chromosome invalid and gives it the worst fitness
+  ///
+  /// The rule is kept, so the chromosome still reads back from a generation
+  /// file.
+  pub fn invalidate(&mut self) {
+    self.valid = false;
+    self.kelly = config::WORST_FITNESS;
+    self.w_kelly = config::WORST_FITNESS;
+  }
+}
+
+/// Random number generator of a run
 ///
-/// ## Example
-/// ```
+/// Every random choice forge makes is drawn from one generator, so runs that
+/// start from the same seed evolve the same chromosomes.
+pub type Random = ChaChaRng;
+
+/// Creates the random number generator for a run from a seed
+pub fn seeded_rng(seed: u64) -> Random {
+  ChaChaRng::seed_from_u64(seed)
+}
+
+/// Draws a seed for runs that aren't given one
+pub fn random_seed() -> u64 {
+  thread_rng().gen()
+}
+
+/// Generates a v4 uuid from the random number generator
+pub fn generate_id(rng: &mut dyn RngCore) -> Uuid {
+  let mut bytes = [0u8; 16];
+  rng.fill_bytes(&mut bytes);
+  bytes[6] = (bytes[6] & 0x0f) | 0x40;
+  bytes[8] = (bytes[8] & 0x3f) | 0x80;
+  Uuid::from_bytes(&bytes).expect("16 bytes make a uuid")
+}
+
+/// Generate chromosomes
 ///
-/// ```
+/// Dna that can't be decoded into a rule still becomes a chromosome. It is
+/// marked invalid with the `INVALID` rule and the worst fitness so it never
+/// gets evaluated or selected. So are rules with fewer or more genes than the
+/// config allows.
 pub fn generate_chromosomes(
   dnas: Vec<Dna>,
   generation: i32,
   ticker: &str,
   tickers: &Vec<String>,
+  grammar: &Grammar,
+  config: &Config,
+  rng: &mut dyn RngCore,
 ) -> Vec<Chromosome> {
   // debug!("generate chromosomes");
   let mut chromosomes: Vec<Chromosome> = vec![];
   // let tickers = open_tickers(tickers_path);
   for dna in dnas {
-    let strategies = decode_dna("<code>".to_string(), &dna, &tickers);
-    let strategies_vec: &Vec<&str> = &strategies.split("::").collect();
-    let chromosome = Chromosome {
-      id: uuid::Uuid::new_v4(),
+    let rule = decode_dna(grammar, &dna, tickers, config.max_wraps)
+      .and_then(|code| Rule::parse(&code).map_err(DecodeError::Invalid));
+    let (rule, valid) = match rule {
+      Ok(rule) => (rule, true),
+      Err(e) => {
+        debug!("Invalid dna {:?}: {}", dna, e);
+        (Rule::Invalid, false)
+      }
+    };
+    let chromosome_length = rule.genes().len() as i32;
+    let length = chromosome_length as usize;
+    let too_long = config.max_chromosome_length > 0 && length > config.max_chromosome_length;
+    let valid = if valid && (length < config.min_chromosome_length || too_long) {
+      debug!("Rule {} has {} genes", rule, length);
+      false
+    } else {
+      valid
+    };
+    let mut chromosome = Chromosome {
+      id: generate_id(rng),
       target_ticker: ticker.to_string(),
-      chromosome: strategies.clone(),
+      chromosome: rule,
       dna: dna,
       generation: generation,
-      chromosome_length: strategies_vec.len() as i32,
+      chromosome_length,
       kelly: 0.0,
       cum_pnl: 0.0,
       variance: 0.0,
@@ -69,45 +202,122 @@ pub fn generate_chromosomes(
       losing_trades: 0,
       percentage_winners: 0.0,
       rank: 0,
+      valid: true,
+      long: LegStats::default(),
+      short: LegStats::default(),
+      max_drawdown: 0.0,
+      front: 0,
+      crowding_distance: 0.0,
+      island: 0,
+      behaviour: vec![],
+      parents: vec![],
+      penalty: config.parsimony * chromosome_length as f32,
     };
+    if !valid {
+      chromosome.invalidate();
+    }
     chromosomes.push(chromosome);
   }
   chromosomes
 }
 
+/// Errors raised while decoding dna
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+  /// Non-terminals were left after wrapping the dna `max_wraps` times
+  Incomplete(String),
+  /// The grammar produced something that isn't a rule
+  Invalid(ParseError),
+}
+
+impl fmt::Display for DecodeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      DecodeError::Incomplete(code) => write!(f, "ran out of dna decoding `{}`", code),
+      DecodeError::Invalid(e) => write!(f, "{}", e),
+    }
+  }
+}
+
 /// Decodes dna
 ///
-/// Takes the array of i32 in Dna and decodes grammar
-pub fn decode_dna(code: String, dna: &Dna, tickers: &Vec<String>) -> String {
-  let mut code = code;
-  for base in dna {
-    code = expand_code(code, base, tickers);
+/// Runs a leftmost derivation of the grammar starting from its start symbol.
+/// Each codon in Dna picks the production for the leftmost non-terminal.
+/// Non-terminals with a single production don't use up a codon.
+///
+/// When we run out of codons decoding wraps around to the start of the dna,
+/// up to `max_wraps` times. If non-terminals are still left after that the
+/// dna is incomplete.
+pub fn decode_dna(
+  grammar: &Grammar,
+  dna: &Dna,
+  tickers: &Vec<String>,
+  max_wraps: usize,
+) -> Result<String, DecodeError> {
+  derive(grammar, dna, tickers, max_wraps).0
+}
+
+/// Symbols expanded by each codon consumed while decoding dna
+///
+/// The history lines up with the dna, i.e. `history[0]` is the symbol the
+/// first codon expanded. It is longer than the dna when decoding wrapped and
+/// shorter when some codons weren't needed.
+pub fn derivation_history(grammar: &Grammar, dna: &Dna, tickers: &Vec<String>, max_wraps: usize) -> Vec<String> {
+  derive(grammar, dna, tickers, max_wraps).1
+}
+
+fn derive(
+  grammar: &Grammar,
+  dna: &Dna,
+  tickers: &Vec<String>,
+  max_wraps: usize,
+) -> (Result<String, DecodeError>, Vec<String>) {
+  let mut code: Vec<Symbol> = vec![Symbol::NonTerminal(grammar.start().to_string())];
+  let mut history: Vec<String> = vec![];
+  let mut bases = dna.iter().cycle().take(dna.len() * (max_wraps + 1));
+  while let Some(idx) = code.iter().position(|s| s.is_non_terminal()) {
+    let single = match &code[idx] {
+      Symbol::NonTerminal(name) => grammar.productions(name).filter(|p| p.len() == 1),
+      _ => None,
+    };
+    let expansion = match single {
+      Some(p) => p[0].clone(),
+      None => match bases.next() {
+        Some(base) => {
+          history.push(code[idx].to_string());
+          expand_code(grammar, &code[idx], base, tickers)
+        }
+        None => return (Err(DecodeError::Incomplete(grammar::render(&code))), history),
+      },
+    };
+    code.splice(idx..idx + 1, expansion);
   }
-  code = code.replace("::<code>", "");
-  code
+  (Ok(grammar::render(&code)), history)
 }
 
 /// Expands dna to code
 ///
-/// Takes the current state of the grammar and the integer from Dna and
-/// replaces the keys words until we get through the array
-fn expand_code(code: String, base: &i32, tickers: &Vec<String>) -> String {
-  if code.contains("<ticker>") {
-    let index = base % tickers.len() as i32;
-    return code.replace("<ticker>", &tickers[index as usize]);
-  };
-  if code.contains("<param>") {
-    return code.replace("<param>", &base.to_string());
+/// Picks the production for a non-terminal with the integer from Dna. Built in
+/// non-terminals that the grammar doesn't define are filled in with a ticker
+/// or the value of the base. Domains map the base to a parameter value.
+fn expand_code(grammar: &Grammar, symbol: &Symbol, base: &i32, tickers: &Vec<String>) -> Vec<Symbol> {
+  let name = match symbol {
+    Symbol::Domain(domain) => return vec![Symbol::Terminal(domain.pick(*base).to_string())],
+    Symbol::NonTerminal(name) => name,
+    Symbol::Terminal(_) => unreachable!("terminals are never expanded"),
   };
-  if code.contains("<stmnt>") {
-    let index = base % config::strategies_length();
-    return code.replace("<stmnt>", config::STRATEGIES[index as usize]);
-  };
-  if code.contains("<code>") {
-    let index = base % config::statements_length();
-    return code.replace("<code>", config::STATEMENTS[index as usize]);
+  if let Some(productions) = grammar.productions(name) {
+    let index = base % productions.len() as i32;
+    return productions[index as usize].clone();
+  }
+  match name.as_str() {
+    "ticker" => {
+      let index = base % tickers.len() as i32;
+      vec![Symbol::Terminal(tickers[index as usize].clone())]
+    }
+    "param" => vec![Symbol::Terminal(base.to_string())],
+    _ => unreachable!("grammar validation allows only builtins without productions"),
   }
-  return code;
 }
 
 /// Dna type
@@ -118,118 +328,121 @@ pub type Dna = Vec<i32>;
 /// Generates a population dnas of length `len`
 /// The length determines the number of strategies in
 /// the dna
-pub fn generate_dnas(len: i32, qty: i32) -> Vec<Dna> {
+pub fn generate_dnas(len: i32, qty: i32, rng: &mut dyn RngCore) -> Vec<Dna> {
   let mut dnas: Vec<Dna> = vec![];
   for _i in 0..qty {
-    let dna = generate_dna(len);
+    let dna = generate_dna(len, rng);
     dnas.push(dna);
   }
   dnas
 }
 
 /// Generates random dna
-fn generate_dna(len: i32) -> Dna {
+fn generate_dna(len: i32, rng: &mut dyn RngCore) -> Dna {
   let mut dna: Vec<i32> = vec![];
-  let mut rng = thread_rng();
   for _i in 0..len {
-    let base: i32 = rng.gen_range(1, 256);
+    let base: i32 = rng.gen_range(1, config::CODON_SIZE);
     dna.push(base);
   }
   dna
 }
 
+/// Sorts ranked chromosomes with the fittest last
+///
+/// Chromosomes are ordered by fitness or, when ranking by Pareto front, by
+/// the crowded comparison of NSGA-II on the fronts they were given. With
+/// `config.parsimony_ties` the shorter rule is fitter when two chromosomes
+/// tie.
+pub fn sort_population(population: &mut [Chromosome], config: &Config) {
+  population.sort_by(|a, b| {
+    let order = match config.ranking {
+      Ranking::Kelly => a.fitness().partial_cmp(&b.fitness()).unwrap_or(std::cmp::Ordering::Equal),
+      Ranking::Pareto => pareto::crowded_cmp(a, b),
+    };
+    if config.parsimony_ties {
+      order.then(b.chromosome_length.cmp(&a.chromosome_length))
+    } else {
+      order
+    }
+  })
+}
+
 /// evolve chromosomes
 ///
-/// When evolving chromosomes we take the fittest 500 chromosomes and generate a pool
-/// of ranked chromosomes. Better chromosomes are more frequent in the pool. Based on the
-/// desired size of the new population we pull two random dnas and have them mate.
+/// The selection operator in the config picks pairs of parents, which mate
+/// to make the dna of two new chromosomes. The fittest `config.elitism`
+/// chromosomes are copied into the new generation without mating.
+///
+/// Chromosomes are ordered by fitness or, when ranking by Pareto front, by the
+/// crowded comparison of NSGA-II. With fitness sharing they are ordered by
+/// shared fitness for selection instead. Roulette selection always spins on
+/// fitness. Children record the ids of their parents.
 pub fn evolve(
   ranked_chromosomes: Vec<Chromosome>,
   generation: i32,
   tickers: &Vec<String>,
   target_ticker: &str,
-  fittest: usize,
-  population_size: i32
+  grammar: &Grammar,
+  config: &Config,
+  rng: &mut dyn RngCore,
 ) -> Vec<Chromosome> {
-  let start = &ranked_chromosomes.len() - fittest;
-  let fittest_chromosomes = &ranked_chromosomes[start..];
-  let pool = generate_pool(fittest_chromosomes);
-  let dnas = mate(&pool, population_size);
-  generate_chromosomes(dnas.clone(), generation, target_ticker, tickers)
-}
-
-fn generate_pool(ranked_chromosomes: &[Chromosome]) -> Vec<Dna> {
-  debug! {"generate pool"};
-  let mut pool: Vec<Dna> = Vec::new();
-  for c in ranked_chromosomes {
-    for _i in 0..c.rank {
-      pool.push(c.dna.clone());
-    }
+  let mut population = ranked_chromosomes;
+  sort_population(&mut population, config);
+  let elites = config.elitism.min(population.len()).min(config.population_size.max(0) as usize);
+  let mut dnas: Vec<Dna> = population.iter().rev().take(elites).map(|c| c.dna.clone()).collect();
+  let mut lineage: Vec<Vec<Uuid>> = vec![vec![]; elites];
+  let offspring = (config.population_size as usize).saturating_sub(elites);
+  if let Niching::Sharing(radius) = config.niching {
+    diversity::share(&mut population, radius, config.genotype_weight);
+  }
+  let selection = config.selection.selection();
+  let parents = selection.select(&population, offspring + offspring % 2, rng);
+  dnas.extend(mate(&parents, grammar, tickers, config, rng).into_iter().take(offspring));
+  // Mating makes two children from each pair of parents
+  lineage.extend((0..offspring).map(|i| vec![parents[i / 2 * 2].id, parents[i / 2 * 2 + 1].id]));
+  let mut chromosomes = generate_chromosomes(dnas, generation, target_ticker, tickers, grammar, config, rng);
+  for (chromosome, parents) in chromosomes.iter_mut().zip(lineage) {
+    chromosome.parents = parents;
   }
-  pool
+  chromosomes
 }
 
-fn mate(pool: &Vec<Dna>, population_size: i32) -> Vec<Dna> {
+/// Mates pairs of parents
+///
+/// Each pair is recombined with the crossover operator in the config with
+/// probability `config.crossover_prob`, otherwise the children are copies of
+/// their parents. The children are then mutated, which also keeps their
+/// length within the bounds of the config.
+fn mate(
+  parents: &[&Chromosome],
+  grammar: &Grammar,
+  tickers: &Vec<String>,
+  config: &Config,
+  rng: &mut dyn RngCore,
+) -> Vec<Dna> {
   debug!("mate");
-  let mut rng = thread_rng();
+  let operator = config.crossover.crossover();
+  let parent = |c| -> crossover::Parent {
+    let history = if operator.needs_history() {
+      derivation_history(grammar, c, tickers, config.max_wraps)
+    } else {
+      vec![]
+    };
+    crossover::Parent { dna: c, history }
+  };
   let mut new_dnas: Vec<Dna> = Vec::new();
-  for _i in 0..population_size {
-    let x_dna = get_random_dna(&pool);
-    let y_dna = get_random_dna(&pool);
-    let splice_point = rng.gen_range(0, x_dna.len());
-    let offspring = mutate_dna(crossover(x_dna, y_dna, splice_point));
-    new_dnas.push(offspring);
-  }
-  new_dnas
-}
-
-fn get_random_dna(pool: &Vec<Dna>) -> Dna {
-  let mut rng = thread_rng();
-  let idx = rng.gen_range(0, pool.len());
-  pool[idx].clone()
-}
-
-fn crossover(x: Dna, y: Dna, splice_point: usize) -> Dna {
-  let papa = &x[..splice_point];
-  let mama = &y[splice_point..];
-  let mut offspring: Dna = vec![];
-  offspring.extend(papa);
-  offspring.extend(mama);
-  debug!("offspring len: {}", offspring.len());
-  offspring
-}
-
-// Mutates each base in dna based on MUTATE PROB
-fn mutate_dna(dna: Dna) -> Dna {
-  let mut mutated_dna: Dna = vec![];
-  for base in dna {
-    let b: i32;
-    let p = rand::random::<f32>();
-    if p < config::MUTATE_PROB {
-      b = mutate_base(base);
+  for pair in parents.chunks(2) {
+    let (x, y) = (parent(&pair[0].dna), parent(&pair[pair.len() - 1].dna));
+    let (a, b) = if rng.gen::<f32>() < config.crossover_prob {
+      operator.crossover(&x, &y, rng)
     } else {
-      b = base;
-    }
-    mutated_dna.push(b);
-  }
-  mutated_dna
-}
-
-// Randomly either increments or decrements base by 1
-fn mutate_base(base: i32) -> i32 {
-  if rand::random() {
-    return floor(base + 1);
-  }
-  return floor(base - 1);
-}
-
-// Ensures that we don't get a number less than 1
-// A parameter of zero might end up with current day calculations
-fn floor(base: i32) -> i32 {
-  if base < 1 {
-    return 1;
+      (x.dna.clone(), y.dna.clone())
+    };
+    debug!("offspring len: {} {}", a.len(), b.len());
+    new_dnas.push(mutation::mutate_dna(a, config, rng));
+    new_dnas.push(mutation::mutate_dna(b, config, rng));
   }
-  return base;
+  new_dnas
 }
 
 #[cfg(test)]
@@ -238,17 +451,47 @@ mod tests {
 
   #[test]
   fn test_generate() {
-    let dna = generate_dna(12);
+    let dna = generate_dna(12, &mut thread_rng());
     assert_eq!(12, dna.len());
   }
 
   #[test]
   fn test_generate_dnas() {
-    let dnas = generate_dnas(12, 10000);
+    let dnas = generate_dnas(12, 10000, &mut thread_rng());
     assert_eq!(10000, dnas.len());
     assert_eq!(12, dnas[0].len())
   }
 
+  #[test]
+  fn test_seeded_runs_repeat() {
+    let run = |seed| {
+      let mut rng = seeded_rng(seed);
+      let config = Config {
+        population_size: 20,
+        ..Config::default()
+      };
+      let dnas = generate_dnas(12, 20, &mut rng);
+      let mut ranked = generate_chromosomes(dnas, 1, "SPY", &tickers(), &Grammar::default(), &config, &mut rng);
+      for (i, c) in ranked.iter_mut().enumerate() {
+        c.kelly = i as f32;
+      }
+      let evolved = evolve(ranked, 2, &tickers(), "SPY", &Grammar::default(), &config, &mut rng);
+      evolved
+        .iter()
+        .map(|c| format!("{} {:?} {}", c.id, c.dna, c.chromosome))
+        .collect::<Vec<String>>()
+    };
+    assert_eq!(run(7), run(7));
+    assert_ne!(run(7), run(8));
+  }
+
+  #[test]
+  fn test_generate_id() {
+    let id = generate_id(&mut seeded_rng(1));
+    assert_eq!(4, id.get_version_num());
+    assert_eq!(id, generate_id(&mut seeded_rng(1)));
+  }
+
   #[test]
   fn test_modulo() {
     let x = 11 % 4;
@@ -257,10 +500,140 @@ mod tests {
     assert_eq!(0, y)
   }
 
+  fn tickers() -> Vec<String> {
+    vec!["AAPL", "DAL", "MSFT", "ISRG", "XOM"]
+      .iter()
+      .map(|t| t.to_string())
+      .collect()
+  }
+
   #[test]
   fn test_decode_dna() {
-    let dna = vec![241, 252, 253, 8, 13, 118, 184, 1, 225, 54, 141, 95];
-    let chromosome = decode_dna("<code>".to_string(), &dna);
-    assert_eq!("conupdays:ISRG:8::stdevd:XOM:1::gapup:DAL:95", chromosome);
+    let dna = vec![2, 241, 252, 253, 8, 13, 118, 184, 1, 226, 54, 141, 95];
+    let chromosome = decode_dna(&Grammar::default(), &dna, &tickers(), 0);
+    assert_eq!(Ok("conupdays:ISRG:9::stdevd:XOM:10::gapup:DAL:6".to_string()), chromosome);
+  }
+
+  #[test]
+  fn test_decode_short_dna() {
+    // SHORT (<code>), <stmnt>, gapdown, DAL, 4
+    let dna = vec![1, 0, 5, 1, 3];
+    let chromosome = decode_dna(&Grammar::default(), &dna, &tickers(), 0);
+    assert_eq!(Ok("SHORT (gapdown:DAL:4)".to_string()), chromosome);
+  }
+
+  #[test]
+  fn test_derivation_history() {
+    let dna = vec![1, 0, 5, 1, 3, 200, 100];
+    let history = derivation_history(&Grammar::default(), &dna, &tickers(), 0);
+    assert_eq!(vec!["<rule>", "<code>", "<stmnt>", "<ticker>", "<int:1:10>"], history);
+  }
+
+  #[test]
+  fn test_decode_dna_wraps() {
+    // <code>, <stmnt>::<code>, conupdays, ISRG, 9, <stmnt> then wrap to hhv, DAL, 7
+    let dna = vec![0, 1, 2, 3, 8, 0];
+    let chromosome = decode_dna(&Grammar::default(), &dna, &tickers(), 1);
+    assert_eq!(Ok("conupdays:ISRG:9::hhv:DAL:7".to_string()), chromosome);
+    let chromosome = decode_dna(&Grammar::default(), &dna, &tickers(), 0);
+    assert_eq!(Err(DecodeError::Incomplete("conupdays:ISRG:9::<stmnt>".to_string())), chromosome);
+  }
+
+  #[test]
+  fn test_decode_dna_never_finishes() {
+    // Every <code> picks <stmnt>::<code>
+    let dna = vec![1, 1, 1, 1, 1];
+    let chromosome = decode_dna(&Grammar::default(), &dna, &tickers(), 2);
+    match chromosome {
+      Err(DecodeError::Incomplete(code)) => assert!(code.contains("::<code>")),
+      other => panic!("expected incomplete dna, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn test_generate_invalid_chromosomes() {
+    let dnas = vec![vec![0, 1, 2, 3, 8, 0], vec![1, 1, 1, 1]];
+    let chromosomes = generate_chromosomes(dnas, 1, "SPY", &tickers(), &Grammar::default(), &Config::default(), &mut seeded_rng(1));
+    assert_eq!(2, chromosomes.len());
+    assert!(chromosomes[0].valid);
+    assert_eq!(2, chromosomes[0].chromosome_length);
+    assert!(!chromosomes[1].valid);
+    assert_eq!(Rule::Invalid, chromosomes[1].chromosome);
+    assert_eq!(Ok(Rule::Invalid), Rule::parse(&chromosomes[1].chromosome.to_string()));
+    assert_eq!(0, chromosomes[1].chromosome_length);
+    assert_eq!(config::WORST_FITNESS, chromosomes[1].kelly);
+  }
+
+  #[test]
+  fn test_chromosome_length_bounds() {
+    let generate = |min_chromosome_length, max_chromosome_length| {
+      let config = Config {
+        min_chromosome_length,
+        max_chromosome_length,
+        ..Config::default()
+      };
+      let dnas = vec![vec![0, 1, 2, 3, 8, 0]];
+      generate_chromosomes(dnas, 1, "SPY", &tickers(), &Grammar::default(), &config, &mut seeded_rng(1)).remove(0)
+    };
+    assert!(generate(1, 0).valid);
+    assert!(generate(2, 2).valid);
+    assert!(!generate(3, 0).valid);
+    let c = generate(1, 1);
+    assert!(!c.valid);
+    assert_eq!(config::WORST_FITNESS, c.kelly);
+  }
+
+  #[test]
+  fn test_parsimony() {
+    let config = Config {
+      parsimony: 0.5,
+      ..Config::default()
+    };
+    let dnas = vec![vec![0, 1, 2, 3, 8, 0], vec![0, 0, 0, 2]];
+    let mut chromosomes = generate_chromosomes(dnas, 1, "SPY", &tickers(), &Grammar::default(), &config, &mut seeded_rng(1));
+    for c in &mut chromosomes {
+      c.kelly = 2.0;
+    }
+    assert_eq!(1.0, chromosomes[0].fitness());
+    assert_eq!(1.5, chromosomes[1].fitness());
+    // Ties go to the shorter rule
+    let config = Config {
+      parsimony_ties: true,
+      ..Config::default()
+    };
+    let dnas = vec![vec![0, 0, 0, 2], vec![0, 1, 2, 3, 8, 0]];
+    let mut chromosomes = generate_chromosomes(dnas, 1, "SPY", &tickers(), &Grammar::default(), &config, &mut seeded_rng(1));
+    sort_population(&mut chromosomes, &config);
+    assert_eq!(vec![2, 1], chromosomes.iter().map(|c| c.chromosome_length).collect::<Vec<i32>>());
+  }
+
+  #[test]
+  fn test_evolve_keeps_elites() {
+    let dnas = (1..5).map(|i| vec![0, 0, 0, i * 2]).collect();
+    let mut ranked = generate_chromosomes(dnas, 1, "SPY", &tickers(), &Grammar::default(), &Config::default(), &mut seeded_rng(1));
+    for (i, c) in ranked.iter_mut().enumerate() {
+      c.kelly = i as f32;
+    }
+    let config = Config {
+      population_size: 10,
+      elitism: 2,
+      ..Config::default()
+    };
+    let evolved = evolve(ranked, 2, &tickers(), "SPY", &Grammar::default(), &config, &mut seeded_rng(1));
+    assert_eq!(10, evolved.len());
+    assert_eq!(vec![0, 0, 0, 8], evolved[0].dna);
+    assert_eq!(vec![0, 0, 0, 6], evolved[1].dna);
+    assert!(evolved.iter().all(|c| c.generation == 2));
+  }
+
+  #[test]
+  fn test_decode_boolean_grammar() {
+    let grammar = Grammar::parse(include_str!("../boolean.bnf")).unwrap();
+    let tickers = vec!["AAPL".to_string(), "MSFT".to_string()];
+    // <expr>, <term> OR <expr>, NOT <stmnt>, hhv, MSFT, 42, <term>, <stmnt>, gapup, AAPL, 4
+    let dna = vec![0, 2, 1, 0, 1, 20, 0, 0, 4, 0, 3];
+    let chromosome = decode_dna(&grammar, &dna, &tickers, 0).unwrap();
+    assert_eq!("NOT hhv:MSFT:42 OR gapup:AAPL:4", chromosome);
+    assert_eq!(2, Rule::parse(&chromosome).unwrap().genes().len());
   }
 }
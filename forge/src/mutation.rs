@@ -0,0 +1,233 @@
+//! Mutation operators
+//!
+//! Each operator has its own rate. Point mutations are applied to each codon
+//! with their rate, insertion and deletion are applied to the whole dna.
+//!
+//! ```text
+//! creep      nudges the codon up or down by one
+//! reset      replaces the codon with a random one
+//! gaussian   adds a step drawn from a normal distribution with sd `sigma`
+//! insertion  inserts a random codon at a random position
+//! deletion   deletes the codon at a random position
+//! ```
+//!
+//! Insertion and deletion let the length of the dna evolve. Dna is kept
+//! between `min_dna_length` and `max_dna_length` of the config by padding it
+//! with random codons or cutting off its tail.
+use config;
+use rand::distributions::Normal;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use {Config, Dna};
+
+/// Rates of the mutation operators
+///
+/// Parses from and displays as `creep=0.2,reset=0.01,...`. Operators that are
+/// left out of the string keep their default rate.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mutation {
+  /// Probability that a codon is nudged up or down by one
+  pub creep: f32,
+  /// Probability that a codon is replaced with a random one
+  pub reset: f32,
+  /// Probability that a codon takes a gaussian step
+  pub gaussian: f32,
+  /// Standard deviation of gaussian steps
+  pub sigma: f32,
+  /// Probability that a random codon is inserted in the dna
+  pub insertion: f32,
+  /// Probability that a codon is deleted from the dna
+  pub deletion: f32,
+}
+
+impl Default for Mutation {
+  fn default() -> Mutation {
+    Mutation {
+      creep: config::MUTATE_PROB,
+      reset: 0.0,
+      gaussian: 0.0,
+      sigma: 8.0,
+      insertion: 0.0,
+      deletion: 0.0,
+    }
+  }
+}
+
+/// Mutates dna with the rates in the config
+pub fn mutate_dna(dna: Dna, config: &Config, rng: &mut dyn RngCore) -> Dna {
+  let rates = &config.mutation;
+  let normal = Normal::new(0.0, f64::from(rates.sigma));
+  let mut mutated_dna: Dna = dna
+    .into_iter()
+    .map(|base| {
+      let mut b = base;
+      if rng.gen::<f32>() < rates.creep {
+        b = mutate_base(b, rng);
+      }
+      if rng.gen::<f32>() < rates.reset {
+        b = random_base(rng);
+      }
+      if rng.gen::<f32>() < rates.gaussian {
+        b = clamp(b + rng.sample(normal).round() as i32);
+      }
+      b
+    })
+    .collect();
+  if rng.gen::<f32>() < rates.insertion && mutated_dna.len() < config.max_dna_length {
+    let idx = rng.gen_range(0, mutated_dna.len() + 1);
+    mutated_dna.insert(idx, random_base(rng));
+  }
+  if rng.gen::<f32>() < rates.deletion && mutated_dna.len() > config.min_dna_length {
+    let idx = rng.gen_range(0, mutated_dna.len());
+    mutated_dna.remove(idx);
+  }
+  fit_length(mutated_dna, config, rng)
+}
+
+/// Pads dna with random codons or cuts off its tail to keep it within the
+/// length bounds of the config
+pub fn fit_length(dna: Dna, config: &Config, rng: &mut dyn RngCore) -> Dna {
+  let mut dna = dna;
+  dna.truncate(config.max_dna_length);
+  while dna.len() < config.min_dna_length {
+    dna.push(random_base(rng));
+  }
+  dna
+}
+
+fn random_base(rng: &mut dyn RngCore) -> i32 {
+  rng.gen_range(1, config::CODON_SIZE)
+}
+
+// Randomly either increments or decrements base by 1
+fn mutate_base(base: i32, rng: &mut dyn RngCore) -> i32 {
+  if rng.gen() {
+    return clamp(base + 1);
+  }
+  clamp(base - 1)
+}
+
+// Keeps bases within the range they are generated in. A parameter of zero
+// might end up with current day calculations
+fn clamp(base: i32) -> i32 {
+  base.max(1).min(config::CODON_SIZE - 1)
+}
+
+impl fmt::Display for Mutation {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "creep={:?},reset={:?},gaussian={:?},sigma={:?},insertion={:?},deletion={:?}",
+      self.creep, self.reset, self.gaussian, self.sigma, self.insertion, self.deletion
+    )
+  }
+}
+
+impl FromStr for Mutation {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Mutation, String> {
+    let mut mutation = Mutation::default();
+    for part in s.split(',').filter(|p| !p.is_empty()) {
+      let mut kv = part.splitn(2, '=');
+      let key = kv.next().unwrap_or("");
+      let value: f32 = match kv.next().map(|v| v.parse()) {
+        Some(Ok(value)) => value,
+        _ => return Err(format!("invalid mutation rate `{}`", part)),
+      };
+      let rate = match key {
+        "creep" => &mut mutation.creep,
+        "reset" => &mut mutation.reset,
+        "gaussian" => &mut mutation.gaussian,
+        "sigma" => &mut mutation.sigma,
+        "insertion" => &mut mutation.insertion,
+        "deletion" => &mut mutation.deletion,
+        _ => return Err(format!("unknown mutation `{}`", key)),
+      };
+      let in_range = if key == "sigma" {
+        value.is_finite() && value > 0.0
+      } else {
+        (0.0..=1.0).contains(&value)
+      };
+      if !in_range {
+        return Err(format!("mutation rate `{}` is out of range", part));
+      }
+      *rate = value;
+    }
+    Ok(mutation)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::prng::ChaChaRng;
+  use rand::SeedableRng;
+
+  fn config(mutation: &str) -> Config {
+    Config {
+      mutation: mutation.parse().unwrap(),
+      min_dna_length: 4,
+      max_dna_length: 6,
+      ..Config::default()
+    }
+  }
+
+  #[test]
+  fn test_point_mutations() {
+    let mut rng = ChaChaRng::seed_from_u64(3);
+    let dna = vec![1, 100, 255, 50, 60];
+    let crept = mutate_dna(dna.clone(), &config("creep=1"), &mut rng);
+    assert!(crept.iter().zip(&dna).all(|(a, b)| (a - b).abs() <= 1 && *a >= 1 && *a < 256));
+    assert_ne!(dna, crept);
+    let reset = mutate_dna(dna.clone(), &config("creep=0,reset=1"), &mut rng);
+    assert_eq!(dna.len(), reset.len());
+    assert!(reset.iter().all(|b| *b >= 1 && *b < 256));
+    let stepped = mutate_dna(dna.clone(), &config("creep=0,gaussian=1,sigma=20"), &mut rng);
+    assert!(stepped.iter().zip(&dna).filter(|(a, b)| a != b).count() >= 3);
+    assert!(stepped.iter().all(|b| *b >= 1 && *b < 256));
+    let unchanged = mutate_dna(dna.clone(), &config("creep=0"), &mut rng);
+    assert_eq!(dna, unchanged);
+  }
+
+  #[test]
+  fn test_insertion_and_deletion() {
+    let mut rng = ChaChaRng::seed_from_u64(3);
+    let inserted = mutate_dna(vec![1, 2, 3, 4, 5], &config("creep=0,insertion=1"), &mut rng);
+    assert_eq!(6, inserted.len());
+    let capped = mutate_dna(inserted, &config("creep=0,insertion=1"), &mut rng);
+    assert_eq!(6, capped.len());
+    let deleted = mutate_dna(vec![1, 2, 3, 4, 5], &config("creep=0,deletion=1"), &mut rng);
+    assert_eq!(4, deleted.len());
+    assert!(deleted.iter().all(|b| *b >= 1 && *b <= 5));
+    let floored = mutate_dna(deleted, &config("creep=0,deletion=1"), &mut rng);
+    assert_eq!(4, floored.len());
+  }
+
+  #[test]
+  fn test_fit_length() {
+    let mut rng = ChaChaRng::seed_from_u64(3);
+    assert_eq!(vec![1, 2, 3, 4, 5, 6], fit_length(vec![1, 2, 3, 4, 5, 6, 7, 8], &config(""), &mut rng));
+    let padded = fit_length(vec![1, 2], &config(""), &mut rng);
+    assert_eq!(4, padded.len());
+    assert!(padded.starts_with(&[1, 2]));
+  }
+
+  #[test]
+  fn test_parse_mutation() {
+    let mutation: Mutation = "reset=0.01,insertion=0.1".parse().unwrap();
+    assert_eq!(config::MUTATE_PROB, mutation.creep);
+    assert_eq!(0.01, mutation.reset);
+    assert_eq!(0.1, mutation.insertion);
+    assert_eq!(Ok(mutation.clone()), mutation.to_string().parse());
+    assert_eq!(Ok(Mutation::default()), "".parse());
+    let specs = [
+      "creep", "creep=x", "creep=2", "reset=-1", "flip=0.1", "gaussian=NaN", "sigma=NaN", "sigma=inf", "sigma=0",
+    ];
+    for spec in &specs {
+      assert!(spec.parse::<Mutation>().is_err(), "{}", spec);
+    }
+  }
+}
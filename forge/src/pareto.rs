@@ -0,0 +1,352 @@
+//! Multi-objective ranking with NSGA-II
+//!
+//! Chromosomes are sorted into Pareto fronts. The first front holds the
+//! chromosomes no other chromosome dominates, i.e. beats on one objective
+//! without losing on another. The second front holds the chromosomes that are
+//! only dominated by the first, and so on. Within a front, chromosomes in
+//! sparsely populated regions have a larger crowding distance and are
+//! preferred to keep the front spread out.
+//!
+//! Objectives parse from the strings below.
+//!
+//! ```text
+//! kelly          maximised
+//! cum_pnl        maximised
+//! num_of_trades  maximised
+//! max_drawdown   minimised
+//! ```
+use std::cmp::Ordering;
+use std::f32;
+use std::fmt;
+use std::str::FromStr;
+use Chromosome;
+use serde::{Deserialize, Serialize};
+
+/// Something to optimise a chromosome for
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Objective {
+  Kelly,
+  CumPnl,
+  NumOfTrades,
+  MaxDrawdown,
+}
+
+impl Objective {
+  /// Every objective
+  pub fn all() -> Vec<Objective> {
+    vec![Objective::Kelly, Objective::CumPnl, Objective::NumOfTrades, Objective::MaxDrawdown]
+  }
+
+  /// Value of the objective for a chromosome where higher is better
+  ///
+  /// Values that aren't a number are the worst possible value.
+  pub fn score(self, chromosome: &Chromosome) -> f32 {
+    let score = match self {
+      Objective::Kelly => chromosome.kelly,
+      Objective::CumPnl => chromosome.cum_pnl,
+      Objective::NumOfTrades => chromosome.num_of_trades as f32,
+      Objective::MaxDrawdown => -chromosome.max_drawdown,
+    };
+    if score.is_nan() {
+      f32::NEG_INFINITY
+    } else {
+      score
+    }
+  }
+}
+
+impl fmt::Display for Objective {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      Objective::Kelly => "kelly",
+      Objective::CumPnl => "cum_pnl",
+      Objective::NumOfTrades => "num_of_trades",
+      Objective::MaxDrawdown => "max_drawdown",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+impl FromStr for Objective {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Objective, String> {
+    match s {
+      "kelly" => Ok(Objective::Kelly),
+      "cum_pnl" => Ok(Objective::CumPnl),
+      "num_of_trades" => Ok(Objective::NumOfTrades),
+      "max_drawdown" => Ok(Objective::MaxDrawdown),
+      _ => Err(format!("unknown objective `{}`", s)),
+    }
+  }
+}
+
+/// Parses a comma separated list of objectives
+pub fn parse_objectives(s: &str) -> Result<Vec<Objective>, String> {
+  let objectives = s.split(',').map(|o| o.trim().parse()).collect::<Result<Vec<Objective>, String>>()?;
+  if objectives.is_empty() {
+    return Err("no objectives".to_string());
+  }
+  Ok(objectives)
+}
+
+/// How chromosomes are ranked for survival and selection
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Ranking {
+  /// By kelly alone
+  Kelly,
+  /// By Pareto front and then crowding distance
+  Pareto,
+}
+
+impl fmt::Display for Ranking {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Ranking::Kelly => write!(f, "kelly"),
+      Ranking::Pareto => write!(f, "pareto"),
+    }
+  }
+}
+
+impl FromStr for Ranking {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Ranking, String> {
+    match s {
+      "kelly" => Ok(Ranking::Kelly),
+      "pareto" => Ok(Ranking::Pareto),
+      _ => Err(format!("unknown ranking `{}`", s)),
+    }
+  }
+}
+
+/// Whether `a` dominates `b`
+///
+/// `a` dominates `b` when it is at least as good on every objective and
+/// better on at least one.
+pub fn dominates(a: &Chromosome, b: &Chromosome, objectives: &[Objective]) -> bool {
+  let mut better = false;
+  for objective in objectives {
+    let (x, y) = (objective.score(a), objective.score(b));
+    if x < y {
+      return false;
+    }
+    if x > y {
+      better = true;
+    }
+  }
+  better
+}
+
+/// Sorts chromosomes into Pareto fronts
+///
+/// Returns the indexes of the chromosomes in each front, starting with the
+/// first front.
+pub fn non_dominated_sort(population: &[Chromosome], objectives: &[Objective]) -> Vec<Vec<usize>> {
+  let n = population.len();
+  let mut dominated: Vec<Vec<usize>> = vec![vec![]; n];
+  let mut domination_count: Vec<usize> = vec![0; n];
+  for i in 0..n {
+    for j in i + 1..n {
+      if dominates(&population[i], &population[j], objectives) {
+        dominated[i].push(j);
+        domination_count[j] += 1;
+      } else if dominates(&population[j], &population[i], objectives) {
+        dominated[j].push(i);
+        domination_count[i] += 1;
+      }
+    }
+  }
+  let mut fronts: Vec<Vec<usize>> = vec![];
+  let mut front: Vec<usize> = (0..n).filter(|i| domination_count[*i] == 0).collect();
+  while !front.is_empty() {
+    let mut next: Vec<usize> = vec![];
+    for i in &front {
+      for j in &dominated[*i] {
+        domination_count[*j] -= 1;
+        if domination_count[*j] == 0 {
+          next.push(*j);
+        }
+      }
+    }
+    fronts.push(front);
+    front = next;
+  }
+  fronts
+}
+
+/// Crowding distance of each chromosome in a front
+///
+/// The distance is the sum over the objectives of the normalised gap between
+/// the neighbours on either side. Chromosomes at the ends of the front get an
+/// infinite distance so they are always kept.
+pub fn crowding_distances(population: &[Chromosome], front: &[usize], objectives: &[Objective]) -> Vec<f32> {
+  let mut distances = vec![0.0; front.len()];
+  if front.len() < 3 {
+    return vec![f32::INFINITY; front.len()];
+  }
+  for objective in objectives {
+    let mut order: Vec<usize> = (0..front.len()).collect();
+    let score = |k: usize| objective.score(&population[front[k]]);
+    order.sort_by(|a, b| score(*a).partial_cmp(&score(*b)).unwrap_or(Ordering::Equal));
+    let (min, max) = (score(order[0]), score(order[order.len() - 1]));
+    distances[order[0]] = f32::INFINITY;
+    distances[order[order.len() - 1]] = f32::INFINITY;
+    let range = max - min;
+    if !(range.is_finite() && range > 0.0) {
+      continue;
+    }
+    for k in 1..order.len() - 1 {
+      distances[order[k]] += (score(order[k + 1]) - score(order[k - 1])) / range;
+    }
+  }
+  distances
+}
+
+/// Sets the Pareto front and crowding distance of every chromosome
+///
+/// Fronts are numbered from 1, so chromosomes with `front == 1` make up the
+/// Pareto front of the population.
+pub fn assign_fronts(population: &mut [Chromosome], objectives: &[Objective]) {
+  let fronts = non_dominated_sort(population, objectives);
+  for (number, front) in fronts.iter().enumerate() {
+    let distances = crowding_distances(population, front, objectives);
+    for (i, distance) in front.iter().zip(distances) {
+      population[*i].front = number as i32 + 1;
+      population[*i].crowding_distance = distance;
+    }
+  }
+}
+
+/// Sets the front and crowding distance of the first front only
+///
+/// Chromosomes on the Pareto front get `front == 1` and the rest get front
+/// 0, i.e. not ranked. The front is found by keeping the chromosomes that
+/// nothing seen so far dominates, which takes time proportional to the
+/// population times the size of the front rather than the square of the
+/// population like `assign_fronts`.
+pub fn assign_first_front(population: &mut [Chromosome], objectives: &[Objective]) {
+  let mut front: Vec<usize> = vec![];
+  for i in 0..population.len() {
+    if front.iter().any(|j| dominates(&population[*j], &population[i], objectives)) {
+      continue;
+    }
+    front.retain(|j| !dominates(&population[i], &population[*j], objectives));
+    front.push(i);
+  }
+  for chromosome in population.iter_mut() {
+    chromosome.front = 0;
+    chromosome.crowding_distance = 0.0;
+  }
+  let distances = crowding_distances(population, &front, objectives);
+  for (i, distance) in front.iter().zip(distances) {
+    population[*i].front = 1;
+    population[*i].crowding_distance = distance;
+  }
+}
+
+/// Orders chromosomes by the crowded comparison of NSGA-II
+///
+/// Chromosomes in a lower front are better. Within a front a larger crowding
+/// distance is better. Better chromosomes compare greater so that sorting
+/// puts the fittest last, like sorting on kelly does.
+pub fn crowded_cmp(a: &Chromosome, b: &Chromosome) -> Ordering {
+  b.front
+    .cmp(&a.front)
+    .then(a.crowding_distance.partial_cmp(&b.crowding_distance).unwrap_or(Ordering::Equal))
+}
+
+/// Keeps the best `size` chromosomes by Pareto front and crowding distance
+///
+/// This is the survival step of NSGA-II. Whole fronts are kept while they
+/// fit, and the front that doesn't fit is cut down to the chromosomes with
+/// the largest crowding distance. Survivors are returned with the fittest
+/// last.
+pub fn survivors(population: Vec<Chromosome>, objectives: &[Objective], size: usize) -> Vec<Chromosome> {
+  let mut population = population;
+  assign_fronts(&mut population, objectives);
+  population.sort_by(|a, b| crowded_cmp(b, a));
+  population.truncate(size);
+  population.reverse();
+  population
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use testing;
+
+  // Chromosomes with the given kelly and max drawdown
+  fn population(scores: &[(f32, f32)]) -> Vec<Chromosome> {
+    let mut chromosomes = testing::chromosomes(scores.len());
+    for (c, (kelly, drawdown)) in chromosomes.iter_mut().zip(scores) {
+      c.kelly = *kelly;
+      c.max_drawdown = *drawdown;
+    }
+    chromosomes
+  }
+
+  const OBJECTIVES: [Objective; 2] = [Objective::Kelly, Objective::MaxDrawdown];
+
+  #[test]
+  fn test_dominates() {
+    let p = population(&[(2.0, 0.1), (1.0, 0.2), (3.0, 0.3), (2.0, 0.1)]);
+    assert!(dominates(&p[0], &p[1], &OBJECTIVES));
+    assert!(!dominates(&p[1], &p[0], &OBJECTIVES));
+    assert!(!dominates(&p[0], &p[2], &OBJECTIVES));
+    assert!(!dominates(&p[2], &p[0], &OBJECTIVES));
+    assert!(!dominates(&p[0], &p[3], &OBJECTIVES));
+  }
+
+  #[test]
+  fn test_non_dominated_sort() {
+    let p = population(&[(1.0, 0.2), (2.0, 0.1), (3.0, 0.3), (0.5, 0.5), (2.5, 0.4), (f32::NAN, 0.0)]);
+    let fronts = non_dominated_sort(&p, &OBJECTIVES);
+    assert_eq!(vec![vec![1, 2, 5], vec![0, 4], vec![3]], fronts);
+  }
+
+  #[test]
+  fn test_assign_first_front() {
+    let mut p = population(&[(1.0, 0.2), (2.0, 0.1), (3.0, 0.3), (0.5, 0.5), (2.5, 0.4), (f32::NAN, 0.0)]);
+    let mut sorted = p.clone();
+    assign_fronts(&mut sorted, &OBJECTIVES);
+    assign_first_front(&mut p, &OBJECTIVES);
+    let fronts: Vec<i32> = p.iter().map(|c| c.front).collect();
+    assert_eq!(vec![0, 1, 1, 0, 0, 1], fronts);
+    for (c, s) in p.iter().zip(&sorted).filter(|(c, _)| c.front == 1) {
+      assert_eq!(s.crowding_distance, c.crowding_distance);
+    }
+  }
+
+  #[test]
+  fn test_crowding_distances() {
+    let p = population(&[(1.0, 0.1), (2.0, 0.2), (2.5, 0.25), (4.0, 0.4)]);
+    let front = vec![0, 1, 2, 3];
+    let distances = crowding_distances(&p, &front, &OBJECTIVES);
+    assert_eq!(f32::INFINITY, distances[0]);
+    assert_eq!(f32::INFINITY, distances[3]);
+    assert!((distances[1] - 1.0).abs() < 1e-5);
+    assert!((distances[2] - 1.3333334).abs() < 1e-5);
+  }
+
+  #[test]
+  fn test_survivors() {
+    let p = population(&[(1.0, 0.1), (2.0, 0.2), (2.5, 0.25), (4.0, 0.4), (0.5, 0.5), (1.5, 0.3)]);
+    // The first front has four chromosomes. The one with the smallest crowding
+    // distance is cut first.
+    let kept = survivors(p.clone(), &OBJECTIVES, 4);
+    let kellies: Vec<f32> = kept.iter().map(|c| c.kelly).collect();
+    assert_eq!(vec![2.0, 2.5, 4.0, 1.0], kellies);
+    assert!(kept.iter().all(|c| c.front == 1));
+    let kept = survivors(p, &OBJECTIVES, 3);
+    let kellies: Vec<f32> = kept.iter().map(|c| c.kelly).collect();
+    assert_eq!(vec![2.5, 4.0, 1.0], kellies);
+  }
+
+  #[test]
+  fn test_parse_objectives() {
+    assert_eq!(Ok(Objective::all()), parse_objectives("kelly,cum_pnl,num_of_trades,max_drawdown"));
+    assert!(parse_objectives("kelly,sharpe").is_err());
+    assert_eq!(Ok(Ranking::Pareto), "pareto".parse());
+  }
+}
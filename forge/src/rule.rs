@@ -0,0 +1,505 @@
+//! Boolean trading rules
+//!
+//! A decoded chromosome is a boolean expression of genes
+//!
+//! ```text
+//! (hhv:AAPL:20 OR gapup:MSFT:3) AND NOT stdevf:SPY:30
+//! ```
+//!
+//! A gene is a strategy code, a ticker and one or more parameters separated by
+//! `:`. Parameters are integers or floats.
+//!
+//! `SHORT` flips a rule to the short side, i.e. `SHORT (hhv:AAPL:20 AND
+//! gapdown:MSFT:3)` goes short when both genes fire.
+//!
+//! `NOT` and `SHORT` bind tighter than `AND` which binds tighter than `OR`.
+//! Parentheses group sub expressions. `::` is the same as `AND` so chromosomes like
+//! `llv:AAPL:2::gapup:GOOG:10` are rules where every gene has to fire.
+//!
+//! Rules print in canonical form, `AND` instead of `::` and only the
+//! parentheses needed to parse back into the same tree.
+//!
+//! Dna that doesn't decode into a rule gets the `INVALID` rule, which has no
+//! genes and never takes a position.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Typed gene parameter
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Param {
+  Int(i32),
+  Float(f32),
+}
+
+impl Param {
+  /// Integer value of the parameter, `None` for floats
+  pub fn as_int(&self) -> Option<i32> {
+    match self {
+      Param::Int(i) => Some(*i),
+      Param::Float(_) => None,
+    }
+  }
+
+  /// Value of the parameter as a float
+  pub fn as_float(&self) -> f32 {
+    match self {
+      Param::Int(i) => *i as f32,
+      Param::Float(f) => *f,
+    }
+  }
+}
+
+impl fmt::Display for Param {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Param::Int(i) => write!(f, "{}", i),
+      // Debug keeps the decimal point so `2.0` doesn't come back as an integer
+      Param::Float(x) => write!(f, "{:?}", x),
+    }
+  }
+}
+
+/// A single strategy applied to a ticker, i.e. `hhv:AAPL:20`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Gene {
+  /// strategy code: `hhv`
+  pub code: String,
+  /// ticker symbol
+  pub ticker: String,
+  pub params: Vec<Param>,
+}
+
+impl fmt::Display for Gene {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}:{}", self.code, self.ticker)?;
+    for param in &self.params {
+      write!(f, ":{}", param)?;
+    }
+    Ok(())
+  }
+}
+
+impl FromStr for Gene {
+  type Err = ParseError;
+
+  fn from_str(gene: &str) -> Result<Gene, ParseError> {
+    parse_gene(gene, 0)
+  }
+}
+
+/// Expression tree of genes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Rule {
+  Gene(Gene),
+  Not(Box<Rule>),
+  Short(Box<Rule>),
+  And(Vec<Rule>),
+  Or(Vec<Rule>),
+  /// Rule of dna that couldn't be decoded
+  Invalid,
+}
+
+impl Rule {
+  /// Parses a rule from a decoded chromosome
+  pub fn parse(code: &str) -> Result<Rule, ParseError> {
+    parse_rule(code).map_err(|e| e.in_rule(code))
+  }
+
+  /// Genes in the rule from left to right
+  pub fn genes(&self) -> Vec<&Gene> {
+    match self {
+      Rule::Gene(g) => vec![g],
+      Rule::Not(r) | Rule::Short(r) => r.genes(),
+      Rule::And(rules) | Rule::Or(rules) => rules.iter().flat_map(|r| r.genes()).collect(),
+      Rule::Invalid => vec![],
+    }
+  }
+
+  // Children that have to be wrapped in parentheses to keep the tree intact
+  fn needs_parens(&self, child: &Rule) -> bool {
+    matches!(
+      (self, child),
+      (Rule::Not(_), Rule::And(_))
+        | (Rule::Not(_), Rule::Or(_))
+        | (Rule::Short(_), Rule::And(_))
+        | (Rule::Short(_), Rule::Or(_))
+        | (Rule::And(_), Rule::And(_))
+        | (Rule::And(_), Rule::Or(_))
+        | (Rule::Or(_), Rule::Or(_))
+    )
+  }
+
+  fn fmt_child(&self, child: &Rule, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.needs_parens(child) {
+      write!(f, "({})", child)
+    } else {
+      write!(f, "{}", child)
+    }
+  }
+}
+
+impl fmt::Display for Rule {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Rule::Gene(g) => write!(f, "{}", g),
+      Rule::Not(r) => {
+        write!(f, "NOT ")?;
+        self.fmt_child(r, f)
+      }
+      Rule::Short(r) => {
+        write!(f, "SHORT ")?;
+        self.fmt_child(r, f)
+      }
+      Rule::And(rules) | Rule::Or(rules) => {
+        let op = if let Rule::And(_) = self { " AND " } else { " OR " };
+        for (i, r) in rules.iter().enumerate() {
+          if i > 0 {
+            write!(f, "{}", op)?;
+          }
+          self.fmt_child(r, f)?;
+        }
+        Ok(())
+      }
+      Rule::Invalid => write!(f, "INVALID"),
+    }
+  }
+}
+
+impl FromStr for Rule {
+  type Err = ParseError;
+
+  fn from_str(code: &str) -> Result<Rule, ParseError> {
+    Rule::parse(code)
+  }
+}
+
+/// Error raised when a rule can't be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+  /// Column, starting at 1, where the error was found
+  pub column: usize,
+  pub message: String,
+  /// The rule that failed to parse
+  pub rule: String,
+}
+
+impl ParseError {
+  fn new(column: usize, message: &str) -> ParseError {
+    ParseError {
+      column: column + 1,
+      message: message.to_string(),
+      rule: String::new(),
+    }
+  }
+
+  fn in_rule(self, rule: &str) -> ParseError {
+    ParseError {
+      rule: rule.to_string(),
+      ..self
+    }
+  }
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} at column {} in `{}`", self.message, self.column, self.rule)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+  Gene(Gene),
+  And,
+  Or,
+  Not,
+  Short,
+  Open,
+  Close,
+}
+
+impl fmt::Display for Kind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Kind::Gene(g) => write!(f, "{}", g),
+      Kind::And => write!(f, "AND"),
+      Kind::Or => write!(f, "OR"),
+      Kind::Not => write!(f, "NOT"),
+      Kind::Short => write!(f, "SHORT"),
+      Kind::Open => write!(f, "("),
+      Kind::Close => write!(f, ")"),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+  kind: Kind,
+  column: usize,
+}
+
+fn parse_rule(code: &str) -> Result<Rule, ParseError> {
+  if code.trim() == "INVALID" {
+    return Ok(Rule::Invalid);
+  }
+  let tokens = tokenize(code)?;
+  let mut parser = Parser {
+    tokens,
+    pos: 0,
+    end: code.chars().count(),
+  };
+  let rule = parser.or()?;
+  match parser.peek() {
+    None => Ok(rule),
+    Some(t) => Err(ParseError::new(t.column, &format!("unexpected `{}`", t.kind))),
+  }
+}
+
+// Splits on whitespace, parentheses and `::`
+fn tokenize(code: &str) -> Result<Vec<Token>, ParseError> {
+  let mut tokens: Vec<Token> = vec![];
+  let mut word = String::new();
+  let mut start = 0;
+  let mut chars = code.chars().enumerate().peekable();
+  while let Some((i, c)) = chars.next() {
+    match c {
+      '(' | ')' => {
+        push_word(&mut tokens, &mut word, start)?;
+        let kind = if c == '(' { Kind::Open } else { Kind::Close };
+        tokens.push(Token { kind, column: i });
+      }
+      ':' if chars.peek().map(|p| p.1) == Some(':') => {
+        chars.next();
+        push_word(&mut tokens, &mut word, start)?;
+        tokens.push(Token {
+          kind: Kind::And,
+          column: i,
+        });
+      }
+      c if c.is_whitespace() => push_word(&mut tokens, &mut word, start)?,
+      c => {
+        if word.is_empty() {
+          start = i;
+        }
+        word.push(c)
+      }
+    }
+  }
+  push_word(&mut tokens, &mut word, start)?;
+  Ok(tokens)
+}
+
+fn push_word(tokens: &mut Vec<Token>, word: &mut String, column: usize) -> Result<(), ParseError> {
+  if word.is_empty() {
+    return Ok(());
+  }
+  let kind = match word.as_str() {
+    "AND" => Kind::And,
+    "OR" => Kind::Or,
+    "NOT" => Kind::Not,
+    "SHORT" => Kind::Short,
+    _ => Kind::Gene(parse_gene(word, column)?),
+  };
+  tokens.push(Token { kind, column });
+  word.clear();
+  Ok(())
+}
+
+fn parse_gene(gene: &str, column: usize) -> Result<Gene, ParseError> {
+  let parts: Vec<&str> = gene.split(':').collect();
+  if parts.len() < 3 {
+    let msg = format!("gene `{}` should look like `code:ticker:param`", gene);
+    return Err(ParseError::new(column, &msg).in_rule(gene));
+  }
+  if parts[0].is_empty() || parts[1].is_empty() {
+    let msg = format!("gene `{}` is missing its code or ticker", gene);
+    return Err(ParseError::new(column, &msg).in_rule(gene));
+  }
+  let mut params: Vec<Param> = vec![];
+  for part in &parts[2..] {
+    let param = match (part.parse::<i32>(), part.parse::<f32>()) {
+      (Ok(i), _) => Param::Int(i),
+      (_, Ok(f)) => Param::Float(f),
+      _ => {
+        let msg = format!("invalid parameter `{}` in gene `{}`", part, gene);
+        return Err(ParseError::new(column, &msg).in_rule(gene));
+      }
+    };
+    params.push(param);
+  }
+  Ok(Gene {
+    code: parts[0].to_string(),
+    ticker: parts[1].to_string(),
+    params,
+  })
+}
+
+// Recursive descent parser, one function per precedence level
+struct Parser {
+  tokens: Vec<Token>,
+  pos: usize,
+  end: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.pos)
+  }
+
+  fn peek_kind(&self) -> Option<&Kind> {
+    self.peek().map(|t| &t.kind)
+  }
+
+  fn next(&mut self) -> Option<Token> {
+    let token = self.tokens.get(self.pos).cloned();
+    self.pos += 1;
+    token
+  }
+
+  fn or(&mut self) -> Result<Rule, ParseError> {
+    let mut rules = vec![self.and()?];
+    while self.peek_kind() == Some(&Kind::Or) {
+      self.next();
+      rules.push(self.and()?);
+    }
+    Ok(collapse(rules, Rule::Or))
+  }
+
+  fn and(&mut self) -> Result<Rule, ParseError> {
+    let mut rules = vec![self.not()?];
+    while self.peek_kind() == Some(&Kind::And) {
+      self.next();
+      rules.push(self.not()?);
+    }
+    Ok(collapse(rules, Rule::And))
+  }
+
+  fn not(&mut self) -> Result<Rule, ParseError> {
+    match self.peek_kind() {
+      Some(&Kind::Not) => {
+        self.next();
+        Ok(Rule::Not(Box::new(self.not()?)))
+      }
+      Some(&Kind::Short) => {
+        self.next();
+        Ok(Rule::Short(Box::new(self.not()?)))
+      }
+      _ => self.atom(),
+    }
+  }
+
+  fn atom(&mut self) -> Result<Rule, ParseError> {
+    match self.next() {
+      Some(Token {
+        kind: Kind::Gene(g), ..
+      }) => Ok(Rule::Gene(g)),
+      Some(Token { kind: Kind::Open, column }) => {
+        let rule = self.or()?;
+        match self.next() {
+          Some(Token { kind: Kind::Close, .. }) => Ok(rule),
+          _ => Err(ParseError::new(column, "unclosed `(`")),
+        }
+      }
+      Some(t) => Err(ParseError::new(t.column, &format!("expected a gene, found `{}`", t.kind))),
+      None => Err(ParseError::new(self.end, "expected a gene, found end of rule")),
+    }
+  }
+}
+
+fn collapse(mut rules: Vec<Rule>, op: fn(Vec<Rule>) -> Rule) -> Rule {
+  if rules.len() == 1 {
+    return rules.remove(0);
+  }
+  op(rules)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn gene(g: &str) -> Rule {
+    Rule::Gene(g.parse().unwrap())
+  }
+
+  #[test]
+  fn test_parse_gene() {
+    let g: Gene = "hhv:AAPL:20".parse().unwrap();
+    assert_eq!("hhv", g.code);
+    assert_eq!("AAPL", g.ticker);
+    assert_eq!(vec![Param::Int(20)], g.params);
+    let g: Gene = "band:SPY:20:1.5".parse().unwrap();
+    assert_eq!(vec![Param::Int(20), Param::Float(1.5)], g.params);
+  }
+
+  #[test]
+  fn test_parse_legacy_chromosome() {
+    let rule = Rule::parse("llv:AAPL:2::gapup:GOOG:10").unwrap();
+    assert_eq!(Rule::And(vec![gene("llv:AAPL:2"), gene("gapup:GOOG:10")]), rule);
+    assert_eq!("llv:AAPL:2 AND gapup:GOOG:10", rule.to_string());
+  }
+
+  #[test]
+  fn test_parse_boolean_rule() {
+    let rule = Rule::parse("(hhv:AAPL:20 OR gapup:MSFT:3) AND NOT stdevf:SPY:30").unwrap();
+    let expected = Rule::And(vec![
+      Rule::Or(vec![gene("hhv:AAPL:20"), gene("gapup:MSFT:3")]),
+      Rule::Not(Box::new(gene("stdevf:SPY:30"))),
+    ]);
+    assert_eq!(expected, rule);
+    let genes: Vec<String> = rule.genes().iter().map(|g| g.to_string()).collect();
+    assert_eq!(vec!["hhv:AAPL:20", "gapup:MSFT:3", "stdevf:SPY:30"], genes);
+  }
+
+  #[test]
+  fn test_parse_short() {
+    let rule = Rule::parse("SHORT (a:X:1::b:X:2)").unwrap();
+    let expected = Rule::Short(Box::new(Rule::And(vec![gene("a:X:1"), gene("b:X:2")])));
+    assert_eq!(expected, rule);
+    let rule = Rule::parse("SHORT a:X:1 AND b:X:2").unwrap();
+    let expected = Rule::And(vec![Rule::Short(Box::new(gene("a:X:1"))), gene("b:X:2")]);
+    assert_eq!(expected, rule);
+  }
+
+  #[test]
+  fn test_precedence() {
+    let rule = Rule::parse("a:X:1 OR b:X:1 AND c:X:1").unwrap();
+    let expected = Rule::Or(vec![gene("a:X:1"), Rule::And(vec![gene("b:X:1"), gene("c:X:1")])]);
+    assert_eq!(expected, rule);
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let rules = vec![
+      "hhv:AAPL:20",
+      "(hhv:AAPL:20 OR gapup:MSFT:3) AND NOT stdevf:SPY:30",
+      "a:X:1 OR b:X:2 AND c:X:3",
+      "(a:X:1 AND b:X:2) AND c:X:3",
+      "NOT (a:X:1 OR NOT b:X:2.0)",
+      "SHORT (a:X:1 AND b:X:2) OR SHORT NOT c:X:3",
+      "band:SPY:20:1.5",
+      "INVALID",
+    ];
+    for r in rules {
+      let rule = Rule::parse(r).unwrap();
+      assert_eq!(r, rule.to_string());
+      assert_eq!(rule, Rule::parse(&rule.to_string()).unwrap());
+    }
+  }
+
+  #[test]
+  fn test_parse_errors() {
+    let err = Rule::parse("(a:X:1 OR b:X:1").unwrap_err();
+    assert_eq!(1, err.column);
+    assert_eq!("unclosed `(` at column 1 in `(a:X:1 OR b:X:1`", err.to_string());
+    let err = Rule::parse("a:X:1 AND").unwrap_err();
+    assert_eq!(10, err.column);
+    let err = Rule::parse("a:X:1 b:X:1").unwrap_err();
+    assert_eq!(7, err.column);
+    let err = Rule::parse("a:X:1 AND hhv:AAPL:<param>").unwrap_err();
+    assert_eq!(11, err.column);
+    assert!(err.message.contains("invalid parameter `<param>`"));
+    assert!(Rule::parse("hhv:AAPL").is_err());
+    assert!(Rule::parse("INVALID AND hhv:AAPL:20").is_err());
+    assert!(Rule::parse("").is_err());
+  }
+}
@@ -0,0 +1,251 @@
+//! Selection operators
+//!
+//! A selection operator picks the parents that mate to make the next
+//! generation. Operators get the population sorted in ascending order of
+//! fitness so the fittest chromosome is last, which is the order helix ranks
+//! chromosomes in.
+//!
+//! The operator used for a run is picked with a `Method`, which parses from
+//! the strings below.
+//!
+//! ```text
+//! tournament:7   best of 7 chromosomes drawn at random
+//! roulette       chance proportional to fitness
+//! rank:1.5       chance proportional to rank, with selection pressure 1.5
+//! truncation:500 uniformly from the fittest 500
+//! ```
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use Chromosome;
+
+/// Picks parents from a population
+pub trait Selection: fmt::Debug {
+  /// Selects `count` parents from a population sorted in ascending order of
+  /// fitness. The population must not be empty.
+  fn select<'a>(&self, population: &'a [Chromosome], count: usize, rng: &mut dyn RngCore) -> Vec<&'a Chromosome>;
+}
+
+/// Picks the fittest of `size` chromosomes drawn at random
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tournament {
+  pub size: usize,
+}
+
+impl Selection for Tournament {
+  fn select<'a>(&self, population: &'a [Chromosome], count: usize, rng: &mut dyn RngCore) -> Vec<&'a Chromosome> {
+    (0..count)
+      .map(|_| {
+        // The population is sorted so the highest index is the fittest
+        let winner = (0..self.size.max(1)).map(|_| rng.gen_range(0, population.len())).max().unwrap();
+        &population[winner]
+      })
+      .collect()
+  }
+}
+
+/// Picks chromosomes with a chance proportional to their fitness
+///
+/// Fitness can be negative, so it is shifted to make the least fit chromosome
+/// zero. When every chromosome is equally fit they are picked uniformly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Roulette;
+
+impl Selection for Roulette {
+  fn select<'a>(&self, population: &'a [Chromosome], count: usize, rng: &mut dyn RngCore) -> Vec<&'a Chromosome> {
+    let min = population.iter().map(|c| c.fitness()).fold(f32::MAX, f32::min);
+    let weights: Vec<f32> = population.iter().map(|c| c.fitness() - min).collect();
+    spin(population, &weights, count, rng)
+  }
+}
+
+/// Picks chromosomes with a chance proportional to their rank
+///
+/// `pressure` is between 1.0 and 2.0. The fittest chromosome is `pressure`
+/// times as likely to be picked as the average one. At 1.0 every chromosome
+/// is equally likely and at 2.0 the least fit is never picked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinearRank {
+  pub pressure: f32,
+}
+
+impl Selection for LinearRank {
+  fn select<'a>(&self, population: &'a [Chromosome], count: usize, rng: &mut dyn RngCore) -> Vec<&'a Chromosome> {
+    let n = population.len();
+    let step = if n > 1 { 2.0 * (self.pressure - 1.0) / (n - 1) as f32 } else { 0.0 };
+    let weights: Vec<f32> = (0..n).map(|rank| 2.0 - self.pressure + rank as f32 * step).collect();
+    spin(population, &weights, count, rng)
+  }
+}
+
+/// Picks uniformly from the `fittest` chromosomes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Truncation {
+  pub fittest: usize,
+}
+
+impl Selection for Truncation {
+  fn select<'a>(&self, population: &'a [Chromosome], count: usize, rng: &mut dyn RngCore) -> Vec<&'a Chromosome> {
+    let start = population.len().saturating_sub(self.fittest.max(1));
+    (0..count)
+      .map(|_| &population[rng.gen_range(start, population.len())])
+      .collect()
+  }
+}
+
+/// Spins a roulette wheel with a slot of `weights[i]` for each chromosome
+fn spin<'a>(population: &'a [Chromosome], weights: &[f32], count: usize, rng: &mut dyn RngCore) -> Vec<&'a Chromosome> {
+  let mut cumulative: Vec<f32> = Vec::with_capacity(weights.len());
+  let mut total = 0.0;
+  for weight in weights {
+    total += weight.max(0.0);
+    cumulative.push(total);
+  }
+  if total <= 0.0 || !total.is_finite() {
+    return (0..count)
+      .map(|_| &population[rng.gen_range(0, population.len())])
+      .collect();
+  }
+  (0..count)
+    .map(|_| {
+      let ball = rng.gen::<f32>() * total;
+      let idx = cumulative.partition_point(|c| *c <= ball).min(population.len() - 1);
+      &population[idx]
+    })
+    .collect()
+}
+
+/// Selection operator picked in the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Method {
+  Tournament(usize),
+  Roulette,
+  LinearRank(f32),
+  Truncation(usize),
+}
+
+impl Method {
+  /// Builds the selection operator
+  pub fn selection(&self) -> Box<dyn Selection> {
+    match *self {
+      Method::Tournament(size) => Box::new(Tournament { size }),
+      Method::Roulette => Box::new(Roulette),
+      Method::LinearRank(pressure) => Box::new(LinearRank { pressure }),
+      Method::Truncation(fittest) => Box::new(Truncation { fittest }),
+    }
+  }
+}
+
+impl fmt::Display for Method {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Method::Tournament(size) => write!(f, "tournament:{}", size),
+      Method::Roulette => write!(f, "roulette"),
+      Method::LinearRank(pressure) => write!(f, "rank:{:?}", pressure),
+      Method::Truncation(fittest) => write!(f, "truncation:{}", fittest),
+    }
+  }
+}
+
+impl FromStr for Method {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Method, String> {
+    let mut parts = s.splitn(2, ':');
+    let name = parts.next().unwrap_or("");
+    let arg = parts.next();
+    let method = match (name, arg) {
+      ("tournament", Some(size)) => Method::Tournament(parse_arg(s, size)?),
+      ("roulette", None) => Method::Roulette,
+      ("rank", Some(pressure)) => Method::LinearRank(parse_arg(s, pressure)?),
+      ("truncation", Some(fittest)) => Method::Truncation(parse_arg(s, fittest)?),
+      _ => return Err(format!("unknown selection `{}`", s)),
+    };
+    match method {
+      Method::Tournament(0) | Method::Truncation(0) => Err(format!("`{}` needs at least one chromosome", s)),
+      Method::LinearRank(p) if !(1.0..=2.0).contains(&p) => Err(format!("rank pressure in `{}` should be between 1 and 2", s)),
+      _ => Ok(method),
+    }
+  }
+}
+
+fn parse_arg<T: FromStr>(s: &str, arg: &str) -> Result<T, String> {
+  arg.parse().map_err(|_| format!("invalid argument in selection `{}`", s))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::prng::ChaChaRng;
+  use rand::SeedableRng;
+  use testing;
+
+  // Five chromosomes with kelly 0 to 4, sorted by fitness
+  fn population() -> Vec<Chromosome> {
+    let mut chromosomes = testing::chromosomes(5);
+    for (i, c) in chromosomes.iter_mut().enumerate() {
+      c.kelly = i as f32;
+    }
+    chromosomes
+  }
+
+  fn picks(selection: &dyn Selection, population: &[Chromosome]) -> Vec<usize> {
+    let mut rng = ChaChaRng::seed_from_u64(7);
+    let mut counts = vec![0; population.len()];
+    for c in selection.select(population, 5000, &mut rng) {
+      counts[c.kelly as usize] += 1;
+    }
+    counts
+  }
+
+  #[test]
+  fn test_tournament() {
+    let population = population();
+    let counts = picks(&Tournament { size: 3 }, &population);
+    assert!(counts.windows(2).all(|w| w[0] < w[1]));
+    let counts = picks(&Tournament { size: 50 }, &population);
+    assert_eq!(5000, counts[4]);
+  }
+
+  #[test]
+  fn test_roulette() {
+    let population = population();
+    let counts = picks(&Roulette, &population);
+    // The least fit chromosome is shifted to zero
+    assert_eq!(0, counts[0]);
+    assert!(counts[4] > counts[1] * 3);
+  }
+
+  #[test]
+  fn test_linear_rank() {
+    let population = population();
+    let counts = picks(&LinearRank { pressure: 2.0 }, &population);
+    assert_eq!(0, counts[0]);
+    assert!(counts.windows(2).all(|w| w[0] < w[1]));
+    let counts = picks(&LinearRank { pressure: 1.0 }, &population);
+    assert!(counts.iter().all(|c| *c > 900 && *c < 1100));
+  }
+
+  #[test]
+  fn test_truncation() {
+    let population = population();
+    let counts = picks(&Truncation { fittest: 2 }, &population);
+    assert_eq!(vec![0, 0, 0], counts[..3].to_vec());
+    assert_eq!(5000, counts[3] + counts[4]);
+  }
+
+  #[test]
+  fn test_parse_method() {
+    assert_eq!(Ok(Method::Tournament(7)), "tournament:7".parse());
+    assert_eq!(Ok(Method::Roulette), "roulette".parse());
+    assert_eq!(Ok(Method::LinearRank(1.5)), "rank:1.5".parse());
+    assert_eq!(Ok(Method::Truncation(500)), "truncation:500".parse());
+    for method in &["tournament", "tournament:0", "roulette:1", "rank:3", "rank:x", "best:5"] {
+      assert!(method.parse::<Method>().is_err(), "{}", method);
+    }
+    for method in &[Method::Tournament(7), Method::Roulette, Method::LinearRank(2.0), Method::Truncation(5)] {
+      assert_eq!(Ok(method.clone()), method.to_string().parse());
+    }
+  }
+}
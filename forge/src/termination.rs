@@ -0,0 +1,149 @@
+//! When a run stops
+//!
+//! A run stops after the first generation that meets one of these:
+//!
+//! ```text
+//! max_generations  the generation is the last one allowed
+//! time_budget      the run has used up its wall clock budget
+//! stagnation       the best fitness hasn't improved by more than epsilon
+//!                  for a number of generations
+//! ```
+//!
+//! Conditions are checked between generations, so a run can go over its time
+//! budget by up to one generation. A run that is resumed from a checkpoint
+//! carries on with the time it had already spent.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{Duration, Instant};
+use Config;
+
+/// Why a run stopped
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+  /// Ran the maximum number of generations
+  MaxGenerations(i32),
+  /// Ran out of time after the given duration
+  TimeBudget(Duration),
+  /// The best fitness stalled for the given number of generations
+  Stagnation(usize),
+}
+
+impl fmt::Display for StopReason {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      StopReason::MaxGenerations(generations) => write!(f, "max_generations: ran {} generations", generations),
+      StopReason::TimeBudget(elapsed) => write!(f, "time_budget: ran for {}s", elapsed.as_secs()),
+      StopReason::Stagnation(generations) => {
+        write!(f, "stagnation: best fitness didn't improve for {} generations", generations)
+      }
+    }
+  }
+}
+
+/// Tracks a run against the stopping conditions of its config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Termination {
+  max_generations: i32,
+  time_budget: Option<Duration>,
+  stagnation: usize,
+  epsilon: f32,
+  /// Time spent up to the last update
+  spent: Duration,
+  /// When the clock was last read
+  #[serde(skip, default = "Instant::now")]
+  started: Instant,
+  best_fitness: f32,
+  stagnant: usize,
+}
+
+impl Termination {
+  /// Starts the clock on a run
+  pub fn new(config: &Config) -> Termination {
+    Termination {
+      max_generations: config.max_generations,
+      time_budget: config.time_budget.map(Duration::from_secs),
+      stagnation: config.stagnation,
+      epsilon: config.epsilon,
+      spent: Duration::from_secs(0),
+      started: Instant::now(),
+      best_fitness: f32::NEG_INFINITY,
+      stagnant: 0,
+    }
+  }
+
+  /// Records the best fitness of a generation and returns why the run should
+  /// stop, if it should
+  pub fn update(&mut self, generation: i32, best_fitness: f32) -> Option<StopReason> {
+    self.spent += self.started.elapsed();
+    self.started = Instant::now();
+    if best_fitness > self.best_fitness + self.epsilon {
+      self.best_fitness = best_fitness;
+      self.stagnant = 0;
+    } else {
+      self.stagnant += 1;
+    }
+    if generation >= self.max_generations {
+      return Some(StopReason::MaxGenerations(generation));
+    }
+    match self.time_budget {
+      Some(budget) if self.spent >= budget => return Some(StopReason::TimeBudget(self.spent)),
+      _ => {}
+    }
+    if self.stagnation > 0 && self.stagnant >= self.stagnation {
+      return Some(StopReason::Stagnation(self.stagnant));
+    }
+    None
+  }
+
+  /// Runs the same stopping conditions with a different number of
+  /// generations
+  pub fn set_max_generations(&mut self, max_generations: i32) {
+    self.max_generations = max_generations;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn termination(max_generations: i32, time_budget: Option<u64>, stagnation: usize) -> Termination {
+    Termination::new(&Config {
+      max_generations,
+      time_budget,
+      stagnation,
+      epsilon: 0.01,
+      ..Config::default()
+    })
+  }
+
+  #[test]
+  fn test_max_generations() {
+    let mut t = termination(3, None, 0);
+    assert_eq!(None, t.update(1, 1.0));
+    assert_eq!(None, t.update(2, 1.0));
+    assert_eq!(Some(StopReason::MaxGenerations(3)), t.update(3, 1.0));
+  }
+
+  #[test]
+  fn test_time_budget() {
+    let mut t = termination(10, Some(0), 0);
+    match t.update(1, 1.0) {
+      Some(StopReason::TimeBudget(_)) => {}
+      reason => panic!("{:?}", reason),
+    }
+    let mut t = termination(10, Some(3600), 0);
+    assert_eq!(None, t.update(1, 1.0));
+  }
+
+  #[test]
+  fn test_stagnation() {
+    let mut t = termination(100, None, 2);
+    assert_eq!(None, t.update(1, f32::NEG_INFINITY));
+    assert_eq!(None, t.update(2, 1.0));
+    // Improvements of no more than epsilon don't count
+    assert_eq!(None, t.update(3, 1.005));
+    assert_eq!(None, t.update(4, 1.02));
+    assert_eq!(None, t.update(5, 0.5));
+    assert_eq!(Some(StopReason::Stagnation(2)), t.update(6, 1.02));
+  }
+}
@@ -0,0 +1,12 @@
+//! Fixtures shared by the tests of forge
+use {seeded_rng, Chromosome, Config, Grammar};
+
+/// `n` chromosomes of the first generation decoded with the default grammar
+/// and config
+///
+/// The dna of chromosome `i` is `[0, 0, 0, i]`, so each genotype differs.
+pub fn chromosomes(n: usize) -> Vec<Chromosome> {
+  let tickers = vec!["AAPL".to_string()];
+  let dnas = (0..n).map(|i| vec![0, 0, 0, i as i32]).collect();
+  ::generate_chromosomes(dnas, 1, "SPY", &tickers, &Grammar::default(), &Config::default(), &mut seeded_rng(1))
+}
@@ -12,9 +12,31 @@ pub mod schemas;
 /// test sql statements
 pub mod sql;
 
-use csv::Reader;
+use csv::{Reader, ReaderBuilder, StringRecord};
+use forge::{Chromosome, LegStats, Rule};
 use schemas::Quote;
 use schemas::Return;
+use std::cmp::Ordering;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Columns of a generation file that hold a number, in the order they are
+/// written
+pub static GENERATION_COLUMNS: [&str; 13] = [
+  "generation",
+  "chromosome_length",
+  "kelly",
+  "cum_pnl",
+  "variance",
+  "mean_return",
+  "w_kelly",
+  "num_of_trades",
+  "winning_trades",
+  "losing_trades",
+  "percentage_winners",
+  "rank",
+  "max_drawdown",
+];
 
 /// Get quotes for ticker symbol
 pub fn get_quotes_by_symbol(ticker: &String, ticker_path: &str) -> Vec<Quote> {
@@ -62,6 +84,161 @@ pub fn get_returns(target_returns_path: &str) -> Vec<Return> {
   target_returns
 }
 
+/// Get chromosomes from a generation file
+///
+/// Reads the tab delimited file written by `writer::write_chromosomes`.
+/// Columns that files of older runs don't have yet, like the leg stats or the
+/// island, are left at their defaults. Chromosomes whose rule doesn't parse,
+/// i.e. the empty rule older runs wrote for undecodable dna, or that have the
+/// worst fitness come back invalid.
+pub fn get_generation(generation_path: &str) -> Result<Vec<Chromosome>, String> {
+  let mut rdr = ReaderBuilder::new()
+    .delimiter(b'\t')
+    .has_headers(false)
+    .flexible(true)
+    .quoting(false)
+    .from_path(generation_path)
+    .map_err(|e| format!("Couldn't open generation file {}: {}", generation_path, e))?;
+  let mut chromosomes: Vec<Chromosome> = vec![];
+  for (line, row) in rdr.records().enumerate() {
+    let record = row.map_err(|e| e.to_string())?;
+    let chromosome = parse_chromosome(&record).map_err(|e| format!("{} on line {}", e, line + 1))?;
+    chromosomes.push(chromosome);
+  }
+  Ok(chromosomes)
+}
+
+/// Parses a row of a generation file
+fn parse_chromosome(record: &StringRecord) -> Result<Chromosome, String> {
+  let id = column(record, 1)?;
+  let id = Uuid::parse_str(id).map_err(|_| format!("invalid id `{}`", id))?;
+  let rule = Rule::parse(column(record, 3)?).unwrap_or(Rule::Invalid);
+  let mut chromosome = Chromosome {
+    id,
+    target_ticker: column(record, 2)?.to_string(),
+    chromosome: rule.clone(),
+    dna: parse_dna(column(record, 4)?)?,
+    generation: parse_column(record, 5)?,
+    chromosome_length: parse_column(record, 6)?,
+    kelly: parse_column(record, 7)?,
+    cum_pnl: parse_column(record, 8)?,
+    variance: parse_column(record, 9)?,
+    mean_return: parse_column(record, 10)?,
+    w_kelly: parse_column(record, 11)?,
+    num_of_trades: parse_column(record, 12)?,
+    winning_trades: parse_column(record, 13)?,
+    losing_trades: parse_column(record, 14)?,
+    percentage_winners: parse_column(record, 15)?,
+    rank: parse_column(record, 16)?,
+    valid: true,
+    long: parse_leg_stats(record, 17)?,
+    short: parse_leg_stats(record, 25)?,
+    max_drawdown: parse_optional_column(record, 33)?,
+    front: parse_optional_column(record, 34)?,
+    crowding_distance: parse_optional_column(record, 35)?,
+    island: parse_optional_column(record, 36)?,
+    behaviour: vec![],
+    parents: vec![],
+    penalty: 0.0,
+  };
+  if rule == Rule::Invalid || chromosome.kelly == forge::config::WORST_FITNESS {
+    chromosome.invalidate();
+  }
+  Ok(chromosome)
+}
+
+/// Parses dna written as a postgresql array, i.e. `{1,2,3}`
+fn parse_dna(s: &str) -> Result<Vec<i32>, String> {
+  let codons = s.trim_start_matches('{').trim_end_matches('}');
+  codons
+    .split(',')
+    .map(|codon| codon.trim().parse().map_err(|_| format!("invalid dna `{}`", s)))
+    .collect()
+}
+
+/// Parses the eight columns of leg stats starting at `start`
+fn parse_leg_stats(record: &StringRecord, start: usize) -> Result<LegStats, String> {
+  Ok(LegStats {
+    num_of_trades: parse_optional_column(record, start)?,
+    cum_pnl: parse_optional_column(record, start + 1)?,
+    mean_return: parse_optional_column(record, start + 2)?,
+    variance: parse_optional_column(record, start + 3)?,
+    kelly: parse_optional_column(record, start + 4)?,
+    winning_trades: parse_optional_column(record, start + 5)?,
+    losing_trades: parse_optional_column(record, start + 6)?,
+    percentage_winners: parse_optional_column(record, start + 7)?,
+  })
+}
+
+fn column(record: &StringRecord, i: usize) -> Result<&str, String> {
+  record.get(i).ok_or_else(|| format!("missing column {}", i + 1))
+}
+
+fn parse_column<T: FromStr>(record: &StringRecord, i: usize) -> Result<T, String> {
+  let value = column(record, i)?;
+  value.parse().map_err(|_| format!("invalid value `{}` in column {}", value, i + 1))
+}
+
+// Columns past the end of the row are left at their default
+fn parse_optional_column<T: FromStr + Default>(record: &StringRecord, i: usize) -> Result<T, String> {
+  if i < record.len() {
+    parse_column(record, i)
+  } else {
+    Ok(T::default())
+  }
+}
+
+/// Value of a numeric column of a generation file for a chromosome
+pub fn column_value(chromosome: &Chromosome, column: &str) -> Result<f32, String> {
+  let c = chromosome;
+  let value = match column {
+    "generation" => c.generation as f32,
+    "chromosome_length" => c.chromosome_length as f32,
+    "kelly" => c.kelly,
+    "cum_pnl" => c.cum_pnl,
+    "variance" => c.variance,
+    "mean_return" => c.mean_return,
+    "w_kelly" => c.w_kelly,
+    "num_of_trades" => c.num_of_trades as f32,
+    "winning_trades" => c.winning_trades as f32,
+    "losing_trades" => c.losing_trades as f32,
+    "percentage_winners" => c.percentage_winners,
+    "rank" => c.rank as f32,
+    "max_drawdown" => c.max_drawdown,
+    _ => {
+      return Err(format!(
+        "unknown column `{}`, expected one of {}",
+        column,
+        GENERATION_COLUMNS.join(", ")
+      ))
+    }
+  };
+  Ok(value)
+}
+
+/// Keeps the `n` best chromosomes by a numeric column
+///
+/// Larger values are better except for `rank` and `max_drawdown`, where
+/// smaller values are. Values that aren't a number are the worst. The best
+/// chromosome comes first.
+pub fn top_chromosomes(chromosomes: Vec<Chromosome>, column: &str, n: usize) -> Result<Vec<Chromosome>, String> {
+  let smaller_is_better = column == "rank" || column == "max_drawdown";
+  let mut scored: Vec<(f32, Chromosome)> = vec![];
+  for c in chromosomes {
+    let value = column_value(&c, column)?;
+    let score = if value.is_nan() {
+      f32::NEG_INFINITY
+    } else if smaller_is_better {
+      -value
+    } else {
+      value
+    };
+    scored.push((score, c));
+  }
+  scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+  Ok(scored.into_iter().take(n).map(|(_, c)| c).collect())
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -78,6 +255,57 @@ mod tests {
     assert_eq!(first_quote.ticker, "MMM");
   }
 
+  #[test]
+  fn test_get_generation() {
+    let path = "/tmp/repo_test_generation_1.txt";
+    std::fs::write(
+      path,
+      "run\t6436607d-fe65-4ce6-8d29-9b44acea7141\tSPY\tstdevd:SPY:60\t{88,90,68}\t1\t1\t-19.68\t-0.4\t0.0002\t-0.0039\t-2.71\t102\t40\t62\t0.39\t974\t\
+       102\t-0.4\t-0.0039\t0.0002\t-19.68\t40\t62\t0.39\t0\t-0\t0\t0\t0\t0\t0\t0\t0.41\t5\tinf\t2\n\
+       run\tc35f56f9-f14a-4768-8b74-055944d35500\tSPY\t\t{1,2}\t1\t0\t-340282350000000000000000000000000000000\t0\t0\t0\t0\t0\t0\t0\t0\t0\n",
+    )
+    .unwrap();
+    let chromosomes = get_generation(path).unwrap();
+    assert_eq!(2, chromosomes.len());
+    let c = &chromosomes[0];
+    assert_eq!("stdevd:SPY:60", c.chromosome.to_string());
+    assert_eq!(vec![88, 90, 68], c.dna);
+    assert_eq!(-19.68, c.kelly);
+    assert_eq!(102, c.long.num_of_trades);
+    assert_eq!(5, c.front);
+    assert_eq!(f32::INFINITY, c.crowding_distance);
+    assert_eq!(2, c.island);
+    assert!(c.valid);
+    // Rows of older runs stop after the rank
+    let c = &chromosomes[1];
+    assert_eq!(vec![1, 2], c.dna);
+    assert_eq!(LegStats::default(), c.long);
+    assert_eq!(Rule::Invalid, c.chromosome);
+    assert!(!c.valid);
+  }
+
+  #[test]
+  fn test_top_chromosomes() {
+    let path = "/tmp/repo_test_generation_2.txt";
+    let rows: Vec<String> = [(1.0, 0.3), (3.0, 0.1), (2.0, 0.2)]
+      .iter()
+      .map(|(kelly, drawdown)| {
+        format!(
+          "run\t{}\tSPY\tstdevd:SPY:60\t{{1}}\t1\t1\t{}\t0\t0\t0\t0\t0\t0\t0\t0\t0\t{}\n",
+          Uuid::new_v4(),
+          kelly,
+          ["0"; 16].join("\t") + "\t" + &drawdown.to_string()
+        )
+      })
+      .collect();
+    std::fs::write(path, rows.concat()).unwrap();
+    let chromosomes = get_generation(path).unwrap();
+    let kellies = |cs: Vec<Chromosome>| cs.iter().map(|c| c.kelly).collect::<Vec<f32>>();
+    assert_eq!(vec![3.0, 2.0], kellies(top_chromosomes(chromosomes.clone(), "kelly", 2).unwrap()));
+    assert_eq!(vec![3.0, 2.0, 1.0], kellies(top_chromosomes(chromosomes.clone(), "max_drawdown", 5).unwrap()));
+    assert!(top_chromosomes(chromosomes, "sharpe", 2).is_err());
+  }
+
   #[test]
   fn test_get_returns() {
     let returns = get_returns("/home/choiway/data/spx_data_relix/aapl_returns.csv");
@@ -0,0 +1,68 @@
+//! Checkpoints of a run
+//!
+//! A checkpoint holds everything a run needs to carry on where it stopped:
+//! the options it was started with, the islands with their populations and
+//! random number generators, the chromosomes that were already evaluated, the
+//! hall of fame and how far the run has got towards stopping. Quotes and returns are loaded
+//! from the repo again, so they shouldn't change between runs.
+//!
+//! A checkpoint is written at the end of every generation and replaces the
+//! one before it. `helix resume <checkpoint>` continues the run.
+use bincode;
+use forge::{Chromosome, Config, HallOfFame, Island, Termination};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+
+/// State of a run between generations
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+  pub threads: usize,
+  /// Number of genes whose signals are cached
+  pub signal_cache: usize,
+  pub target_ticker: String,
+  pub backtest_id: String,
+  pub repo_path: String,
+  pub target_returns_path: String,
+  /// Source of the grammar used to decode dna
+  pub grammar: String,
+  /// Whether `<stmnt>` is filled in with every strategy registered in vger
+  pub all_strategies: bool,
+  pub seed: u64,
+  pub config: Config,
+  /// Last generation that finished. 0 before the first generation
+  pub generation: i32,
+  pub islands: Vec<Island>,
+  pub termination: Termination,
+  /// Best chromosomes of the run so far
+  pub hall_of_fame: HallOfFame,
+  /// Chromosomes that were already evaluated by rule
+  pub completed_chromosomes: HashMap<String, Chromosome>,
+}
+
+impl Checkpoint {
+  /// Where the checkpoint of the run is written
+  pub fn path(&self) -> String {
+    format!("/tmp/{}_checkpoint.bin", self.backtest_id)
+  }
+
+  /// Writes the checkpoint to disk
+  ///
+  /// The checkpoint is written to a temporary file first so that a run that
+  /// dies while writing keeps its last checkpoint.
+  pub fn write(&self) {
+    let path = self.path();
+    let tmp_path = format!("{}.tmp", path);
+    let mut f = BufWriter::new(File::create(&tmp_path).expect("Unable to create checkpoint"));
+    bincode::serialize_into(&mut f, self).expect("Unable to write checkpoint");
+    f.flush().expect("Unable to write checkpoint");
+    fs::rename(&tmp_path, &path).expect("Unable to replace checkpoint");
+  }
+
+  /// Reads a checkpoint from disk
+  pub fn read(path: &str) -> Result<Checkpoint, String> {
+    let f = File::open(path).map_err(|e| e.to_string())?;
+    bincode::deserialize_from(BufReader::new(f)).map_err(|e| e.to_string())
+  }
+}
@@ -1,6 +1,4 @@
 // pub static TARGET_TICKER: &str = "SPY";
 
-// The number of fittest chromosomes to evolve
+// The number of fittest chromosomes that are given a rank
 pub static FITTEST: usize = 1000;
-
-pub static POPULATION_SIZE: i32 = 10000;
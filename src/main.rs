@@ -55,13 +55,17 @@
 //!
 //! ### Helix Config
 //!
-//! Set up requires you to set config `TARGET_TICKER`, `FITTEST` and `POPULATION_SIZE`
-//! * `FITTEST` - the number of fittest chromosomes to use for the pool in the next generation (the most fit chromosomes)
-//! * `POPULATION_SIZE` - the population size of the pool
+//! Set up requires you to set config `TARGET_TICKER` and `FITTEST`
+//! * `FITTEST` - the number of fittest chromosomes that are given a rank
 //!
 //! ### Forge Config
 //!
+//! `forge::Config` holds the settings of the genetic algorithm. Population
+//! size, the selection, crossover and mutation operators, elitism, the
+//! bounds on dna length, multi-objective ranking, islands, the hall of fame
+//! and when the run stops are set from the command line.
 //!
+extern crate bincode;
 extern crate clap;
 #[macro_use]
 extern crate log;
@@ -70,28 +74,39 @@ extern crate env_logger;
 extern crate forge;
 extern crate glob;
 extern crate repo;
+extern crate serde;
 extern crate vger;
 extern crate writer;
 
+mod checkpoint;
 mod config;
 
-use clap::{App, Arg};
+use checkpoint::Checkpoint;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use forge::Chromosome;
+use forge::Grammar;
+use forge::Ranking;
 use glob::{glob_with, MatchOptions};
 use repo::schemas::Quote;
 use repo::schemas::Return;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
 use std::io::{self, Write};
 use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
 use std::thread;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use vger::cache::SignalCache;
+use vger::store::Store;
 
 pub fn main() {
   let matches = App::new("helix")
     .version("v0.4-beta")
     .author("choiway <waynechoi@gmail.com>")
     .about("Genetic Algorithm for Financial Data")
+    .setting(AppSettings::SubcommandsNegateReqs)
     .arg(
       Arg::with_name("threads")
         .short("t")
@@ -131,37 +146,297 @@ pub fn main() {
         .help("Filename of the target returns to predict. Should be located in the repo")
         .required(true),
     )
+    .arg(
+      Arg::with_name("grammar")
+        .short("g")
+        .long("grammar")
+        .value_name("FILE")
+        .help("BNF grammar used to decode dna. Defaults to the grammar shipped with forge"),
+    )
+    .arg(
+      Arg::with_name("all_strategies")
+        .long("all_strategies")
+        .help("Replaces the strategies of the grammar with every strategy registered in vger"),
+    )
+    .arg(
+      Arg::with_name("max_wraps")
+        .long("max_wraps")
+        .value_name("WRAPS")
+        .help("Times decoding may wrap around the dna before a chromosome is invalid (default 2)"),
+    )
+    .arg(
+      Arg::with_name("seed")
+        .long("seed")
+        .value_name("SEED")
+        .help("Seeds the random number generator so runs can be reproduced. Runs with the same seed and inputs write the same generation files"),
+    )
+    .arg(
+      Arg::with_name("seed_population")
+        .long("seed_population")
+        .value_name("FILE")
+        .help("Generation file of an earlier run whose dna starts the first generation next to random dna"),
+    )
+    .arg(
+      Arg::with_name("seed_top")
+        .long("seed_top")
+        .value_name("COUNT")
+        .help("Only seeds the best chromosomes of the seed population by seed_by (default all)"),
+    )
+    .arg(
+      Arg::with_name("seed_by")
+        .long("seed_by")
+        .value_name("COLUMN")
+        .help("Column of the seed population that picks the best chromosomes for seed_top (default kelly)"),
+    )
+    .arg(
+      Arg::with_name("population_size")
+        .long("population_size")
+        .value_name("SIZE")
+        .help("Number of chromosomes in each generation (default 10000)"),
+    )
+    .arg(
+      Arg::with_name("selection")
+        .long("selection")
+        .value_name("METHOD")
+        .help("Selection operator: tournament:<size>, roulette, rank:<pressure> or truncation:<fittest> (default rank:2.0)"),
+    )
+    .arg(
+      Arg::with_name("crossover")
+        .long("crossover")
+        .value_name("METHOD")
+        .help("Crossover operator: onepoint, twopoint, uniform, homologous or effective (default onepoint)"),
+    )
+    .arg(
+      Arg::with_name("crossover_prob")
+        .long("crossover_prob")
+        .value_name("PROB")
+        .help("Probability that two parents are recombined rather than copied (default 1.0)"),
+    )
+    .arg(
+      Arg::with_name("mutation")
+        .long("mutation")
+        .value_name("RATES")
+        .help("Mutation rates, i.e. creep=0.2,reset=0.01,gaussian=0.05,sigma=8,insertion=0.1,deletion=0.1"),
+    )
+    .arg(
+      Arg::with_name("initialization")
+        .long("initialization")
+        .value_name("METHOD")
+        .help("How the first generation is made: random or sensible:<max depth> (default random)"),
+    )
+    .arg(
+      Arg::with_name("dna_length")
+        .long("dna_length")
+        .value_name("LENGTH")
+        .help("Length of the random dna in the first generation (default 12)"),
+    )
+    .arg(
+      Arg::with_name("min_dna_length")
+        .long("min_dna_length")
+        .value_name("LENGTH")
+        .help("Dna shorter than this is padded with random codons (default 4)"),
+    )
+    .arg(
+      Arg::with_name("max_dna_length")
+        .long("max_dna_length")
+        .value_name("LENGTH")
+        .help("Dna longer than this is cut off (default 64)"),
+    )
+    .arg(
+      Arg::with_name("adaptive")
+        .long("adaptive")
+        .value_name("SETTINGS")
+        .help("Adapts mutation and crossover rates to the progress of each island, i.e. on or factor=1.5,min=0.25,max=4,diversity=0.1"),
+    )
+    .arg(
+      Arg::with_name("min_chromosome_length")
+        .long("min_chromosome_length")
+        .value_name("GENES")
+        .help("Rules with fewer genes are invalid (default 1)"),
+    )
+    .arg(
+      Arg::with_name("max_chromosome_length")
+        .long("max_chromosome_length")
+        .value_name("GENES")
+        .help("Rules with more genes are invalid. 0 doesn't limit the number of genes (default 0)"),
+    )
+    .arg(
+      Arg::with_name("parsimony")
+        .long("parsimony")
+        .value_name("PENALTY")
+        .help("Fitness taken off kelly for each gene in a rule (default 0)"),
+    )
+    .arg(
+      Arg::with_name("parsimony_ties")
+        .long("parsimony_ties")
+        .help("Ranks the shorter rule higher when two chromosomes are equally fit"),
+    )
+    .arg(
+      Arg::with_name("ranking")
+        .long("ranking")
+        .value_name("RANKING")
+        .help("Rank chromosomes by kelly or by pareto front with NSGA-II (default kelly)"),
+    )
+    .arg(
+      Arg::with_name("objectives")
+        .long("objectives")
+        .value_name("OBJECTIVES")
+        .help("Objectives of the pareto fronts (default kelly,cum_pnl,num_of_trades,max_drawdown)"),
+    )
+    .arg(
+      Arg::with_name("islands")
+        .long("islands")
+        .value_name("COUNT")
+        .help("Number of islands, each with population_size chromosomes (default 1)"),
+    )
+    .arg(
+      Arg::with_name("island")
+        .long("island")
+        .value_name("SETTINGS")
+        .multiple(true)
+        .number_of_values(1)
+        .help("Settings of the next island, i.e. seed=7;mutation=creep=0.3;tickers=AAPL,MSFT. Repeat for each island"),
+    )
+    .arg(
+      Arg::with_name("migration_interval")
+        .long("migration_interval")
+        .value_name("GENERATIONS")
+        .help("Generations between migrations. 0 turns migration off (default 1)"),
+    )
+    .arg(
+      Arg::with_name("migrants")
+        .long("migrants")
+        .value_name("COUNT")
+        .help("Number of the fittest chromosomes each island sends to its neighbours (default 10)"),
+    )
+    .arg(
+      Arg::with_name("topology")
+        .long("topology")
+        .value_name("TOPOLOGY")
+        .help("Islands that exchange migrants: ring or full (default ring)"),
+    )
+    .arg(
+      Arg::with_name("niching")
+        .long("niching")
+        .value_name("METHOD")
+        .help("Keeps the population diverse: off, sharing:<radius> or crowding. Sharing compares each chromosome with up to 500 others (default off)"),
+    )
+    .arg(
+      Arg::with_name("genotype_weight")
+        .long("genotype_weight")
+        .value_name("WEIGHT")
+        .help("Weight of the dna in the distance between chromosomes. Their signals get the rest (default 0.5)"),
+    )
+    .arg(
+      Arg::with_name("max_generations")
+        .long("max_generations")
+        .value_name("GENERATIONS")
+        .help("Number of generations in a run (default 3)"),
+    )
+    .arg(
+      Arg::with_name("time_budget")
+        .long("time_budget")
+        .value_name("SECONDS")
+        .help("Stops the run after the generation that uses up this many seconds"),
+    )
+    .arg(
+      Arg::with_name("stagnation")
+        .long("stagnation")
+        .value_name("GENERATIONS")
+        .help("Stops the run when the best fitness hasn't improved by more than epsilon for this many generations"),
+    )
+    .arg(
+      Arg::with_name("epsilon")
+        .long("epsilon")
+        .value_name("EPSILON")
+        .help("Smallest gain in the best fitness that counts as an improvement (default 0.0001)"),
+    )
+    .arg(
+      Arg::with_name("elitism")
+        .long("elitism")
+        .value_name("COUNT")
+        .help("Number of the fittest chromosomes copied unchanged into the next generation (default 0)"),
+    )
+    .arg(
+      Arg::with_name("hall_of_fame")
+        .long("hall_of_fame")
+        .value_name("SIZE")
+        .help("Number of the best chromosomes ever evaluated kept in the hall of fame (default 100)"),
+    )
+    .arg(
+      Arg::with_name("hall_of_fame_inject")
+        .long("hall_of_fame_inject")
+        .value_name("COUNT")
+        .help("Number of hall of fame members put back into each island every generation (default 0)"),
+    )
+    .arg(
+      Arg::with_name("min_trades")
+        .long("min_trades")
+        .value_name("TRADES")
+        .help("Chromosomes need more than this many trades to be ranked (default 100)"),
+    )
+    .arg(
+      Arg::with_name("signal_cache")
+        .long("signal_cache")
+        .value_name("GENES")
+        .help("Number of genes whose signals are cached across chromosomes. 0 turns the cache off (default 1000)"),
+    )
+    .subcommand(
+      SubCommand::with_name("resume")
+        .about("Continues a run from its checkpoint")
+        .arg(
+          Arg::with_name("checkpoint")
+            .value_name("CHECKPOINT")
+            .help("Checkpoint written at the end of a generation, i.e. /tmp/<backtest id>_checkpoint.bin")
+            .required(true),
+        )
+        .arg(
+          Arg::with_name("threads")
+            .short("t")
+            .long("threads")
+            .value_name("THREADS")
+            .help("Sets the number of threads to use. Defaults to the number the run was started with"),
+        )
+        .arg(
+          Arg::with_name("max_generations")
+            .long("max_generations")
+            .value_name("GENERATIONS")
+            .help("Number of generations in the run. Defaults to the number the run was started with"),
+        )
+        .arg(
+          Arg::with_name("signal_cache")
+            .long("signal_cache")
+            .value_name("GENES")
+            .help("Number of genes whose signals are cached. Defaults to the number the run was started with"),
+        ),
+    )
     .get_matches();
 
-  // Parse arguments
-
-  let num_of_threads: usize = matches.value_of("threads").unwrap_or("4").parse().unwrap();
-  info!("Number of threads: {}", num_of_threads);
-  let target_ticker: &str = matches.value_of("target_ticker").unwrap();
-  info!("Target Ticker: {}", target_ticker);
-  let pool_description: &str = matches.value_of("pool_description").unwrap();
-  info!("Pool Description: {}", pool_description);
-  let backtest_id: String = generate_backtest_id(pool_description, target_ticker);
-  info!("Backtest ID: {}", backtest_id);
-  let repo_path: &str = matches.value_of("repo_pathname").unwrap();
-  info!("Repo Path: {}", repo_path);
-  let returns_filename = matches.value_of("returns_filename").unwrap();
-  let target_returns_path: &str = &format!("{}{}", repo_path, returns_filename);
-  debug!("Target returns path: {}", target_returns_path);
+  env_logger::init();
+  let mut run = match matches.subcommand_matches("resume") {
+    Some(resume) => resume_run(resume),
+    None => new_run(&matches),
+  };
 
   // Init sequence
-  env_logger::init();
   info!("Starting grammatical revolution");
+  let grammar = load_grammar(&run.grammar, run.all_strategies).unwrap_or_else(|e| panic!("Invalid grammar: {}", e));
   info!("Initializing tickers");
-  let tickers = get_tickers(repo_path);
-  info!("Initializing quotes repo");
-  let quotes_repo = init_quotes_repo(&tickers, repo_path);
-  info!("Initializing chromosomes");
-  let mut completed_chromosomes = init_completed_chromosomes();
-  info!("Initializing returns");
-  let returns = init_returns(target_returns_path);
-  info!("Initializing ranked chromosomes");
-  let mut ranked_chromosomes: Vec<Chromosome> = vec![];
+  let tickers = get_tickers(&run.repo_path);
+  let store = Arc::new(init_store(&tickers, &run.repo_path, &run.target_returns_path));
+  info!(
+    "Quote store: {} quotes of {} tickers on {} days in {:.1} MB",
+    store.num_of_quotes(),
+    store.quotes.len(),
+    store.timeline.len(),
+    store.size() as f64 / 1e6
+  );
+  info!("Signal cache size: {}", run.signal_cache);
+  let evaluation = Evaluation {
+    store,
+    cache: Arc::new(SignalCache::new(run.signal_cache)),
+    backtest_id: run.backtest_id.clone(),
+  };
 
   // Run generations
   //
@@ -169,38 +444,323 @@ pub fn main() {
   // you'll have to extract this to a separate machine and figure out
   // how to coordinate all the threads on different nodes.
   // Good luck!
-  for generation in 1..4 {
-    let chromosomes = generate_chromosomes(ranked_chromosomes, generation, &tickers, target_ticker);
-    let chromosomes_len = *&chromosomes.len();
-    let (chromosomes_tx, chromosomes_rx) = init_chromosomes_channel();
-    let (throttle_tx, throttle_rx) = init_throttle(num_of_threads);
-    // Check completed chromosomes
+  let stop_reason = loop {
+    run.generation += 1;
+    let generation = run.generation;
+    let mut chromosomes: Vec<Chromosome> = vec![];
+    for island in &mut run.islands {
+      chromosomes.extend(generate_chromosomes(island, generation, &run.target_ticker, &grammar));
+    }
+    let chromosomes_len = chromosomes.len();
     info!("Processing chromosomes for generation: {}", generation);
-    process_chromosomes(
-      chromosomes,
-      &mut completed_chromosomes,
-      &quotes_repo,
-      &returns,
-      chromosomes_tx,
-      throttle_tx,
-      throttle_rx,
-      &backtest_id,
+    let started = Instant::now();
+    let mut updated_chromosomes =
+      process_chromosomes(chromosomes, &mut run.completed_chromosomes, &evaluation, run.threads);
+    let elapsed = started.elapsed().as_secs_f64();
+    info!(
+      "Processed {} chromosomes of generation {} in {:.2}s ({:.1} chromosomes/s)",
+      chromosomes_len,
+      generation,
+      elapsed,
+      chromosomes_len as f64 / elapsed
     );
-    info!("Updating chromosomes");
-    let updated_chromosomes: Vec<Chromosome> = chromosomes_rx
-      .iter()
-      .take(chromosomes_len)
-      .map(|c| c)
-      .collect();
+    info!("Signal cache after generation {}: {}", generation, evaluation.cache.stats());
+    // Chromosomes arrive in the order their threads finish. Sort them so
+    // runs with the same seed rank and write them in the same order.
+    updated_chromosomes.sort_by_key(|c| c.id);
     info!("Ranking chromosomes");
-    ranked_chromosomes = rank_chromosomes(updated_chromosomes);
+    let mut ranked_chromosomes: Vec<Chromosome> = vec![];
+    for island in &mut run.islands {
+      let island_chromosomes: Vec<Chromosome> =
+        updated_chromosomes.iter().filter(|c| c.island == island.id).cloned().collect();
+      let previous_chromosomes = std::mem::take(&mut island.population);
+      island.population = rank_chromosomes(island_chromosomes, previous_chromosomes, &island.config);
+      let pareto_front = island.population.iter().filter(|c| c.front == 1).count();
+      info!("Pareto front of generation {} on island {}: {} chromosomes", generation, island.id, pareto_front);
+      let diversity = forge::diversity::diversity(&island.population);
+      info!("Diversity of generation {} on island {}: {}", generation, island.id, diversity);
+      island.adapt(&diversity);
+      ranked_chromosomes.extend(island.population.iter().cloned());
+    }
+    run.hall_of_fame.update(&ranked_chromosomes, &run.config);
     info!("Writing chromosomes");
-    writer::write_chromosomes(&ranked_chromosomes, generation, &backtest_id);
-  }
+    writer::write_chromosomes(&ranked_chromosomes, generation, &run.backtest_id);
+    let best_fitness = ranked_chromosomes.iter().map(|c| c.fitness()).fold(f32::NEG_INFINITY, f32::max);
+    info!("Best fitness of generation {}: {}", generation, best_fitness);
+    let stop_reason = run.termination.update(generation, best_fitness);
+    let interval = run.config.migration_interval as i32;
+    if run.islands.len() > 1 && interval > 0 && generation % interval == 0 {
+      info!("Migrating chromosomes between islands");
+      forge::island::migrate(&mut run.islands, run.config.topology, run.config.migrants);
+    }
+    if run.config.hall_of_fame_inject > 0 {
+      for island in &mut run.islands {
+        run.hall_of_fame.inject(island, run.config.hall_of_fame_inject);
+      }
+    }
+    info!("Writing checkpoint to {}", run.path());
+    run.write();
+    if let Some(reason) = stop_reason {
+      break reason;
+    }
+  };
 
+  info!("Stopped after generation {}: {}", run.generation, stop_reason);
+  writer::write_stop_reason(&stop_reason, run.generation, &run.backtest_id);
+  info!("Writing hall of fame of {} chromosomes", run.hall_of_fame.members().len());
+  writer::write_hall_of_fame(run.hall_of_fame.members(), &run.backtest_id);
   info!("So long and thanks for all the fish!");
 }
 
+/// Parses a grammar and checks its strategy productions against the
+/// strategies registered in vger
+///
+/// `<stmnt>` is filled in with every registered strategy when the grammar
+/// leaves it undefined or `all_strategies` is set. Otherwise the grammar
+/// keeps its own strategies as long as they are all registered.
+fn load_grammar(source: &str, all_strategies: bool) -> Result<Grammar, forge::grammar::GrammarError> {
+  let strategies = forge::grammar::STRATEGIES;
+  let mut grammar = Grammar::parse_partial(source, &[strategies])?;
+  if all_strategies || grammar.productions(strategies).is_none() {
+    vger::strategies::define_strategies(&mut grammar)?;
+  } else {
+    vger::strategies::check_strategies(&grammar)?;
+  }
+  Ok(grammar)
+}
+
+/// Starts a run from the command line
+fn new_run(matches: &ArgMatches) -> Checkpoint {
+  // Parse arguments
+
+  let num_of_threads: usize = matches.value_of("threads").unwrap_or("4").parse().unwrap();
+  info!("Number of threads: {}", num_of_threads);
+  let signal_cache: usize = matches
+    .value_of("signal_cache")
+    .map(|genes| genes.parse().expect("signal_cache should be a number"))
+    .unwrap_or(vger::cache::DEFAULT_CAPACITY);
+  let target_ticker: &str = matches.value_of("target_ticker").unwrap();
+  info!("Target Ticker: {}", target_ticker);
+  let pool_description: &str = matches.value_of("pool_description").unwrap();
+  info!("Pool Description: {}", pool_description);
+  let seed: Option<u64> = matches.value_of("seed").map(|s| s.parse().expect("seed should be a number"));
+  let backtest_id: String = generate_backtest_id(pool_description, target_ticker, seed);
+  info!("Backtest ID: {}", backtest_id);
+  let repo_path: &str = matches.value_of("repo_pathname").unwrap();
+  info!("Repo Path: {}", repo_path);
+  let returns_filename = matches.value_of("returns_filename").unwrap();
+  let target_returns_path = format!("{}{}", repo_path, returns_filename);
+  debug!("Target returns path: {}", target_returns_path);
+  let grammar: String = match matches.value_of("grammar") {
+    Some(path) => fs::read_to_string(path).unwrap_or_else(|e| panic!("Invalid grammar {}: {}", path, e)),
+    None => forge::config::GRAMMAR.to_string(),
+  };
+  // The strategies of the grammar shipped with forge only decode dna for
+  // forge on its own
+  let all_strategies = matches.is_present("all_strategies") || !matches.is_present("grammar");
+  if let Err(e) = load_grammar(&grammar, all_strategies) {
+    panic!("Invalid grammar: {}", e);
+  }
+  let mut forge_config = forge::Config::default();
+  if let Some(wraps) = matches.value_of("max_wraps") {
+    forge_config.max_wraps = wraps.parse().expect("max_wraps should be a number");
+  }
+  if let Some(size) = matches.value_of("population_size") {
+    forge_config.population_size = size.parse().expect("population_size should be a number");
+  }
+  if let Some(method) = matches.value_of("selection") {
+    forge_config.selection = method.parse().unwrap_or_else(|e| panic!("Invalid selection: {}", e));
+  }
+  if let Some(method) = matches.value_of("crossover") {
+    forge_config.crossover = method.parse().unwrap_or_else(|e| panic!("Invalid crossover: {}", e));
+  }
+  if let Some(prob) = matches.value_of("crossover_prob") {
+    forge_config.crossover_prob = prob.parse().expect("crossover_prob should be a number");
+  }
+  if let Some(elitism) = matches.value_of("elitism") {
+    forge_config.elitism = elitism.parse().expect("elitism should be a number");
+  }
+  if let Some(size) = matches.value_of("hall_of_fame") {
+    forge_config.hall_of_fame = size.parse().expect("hall_of_fame should be a number");
+  }
+  if let Some(count) = matches.value_of("hall_of_fame_inject") {
+    forge_config.hall_of_fame_inject = count.parse().expect("hall_of_fame_inject should be a number");
+  }
+  if let Some(trades) = matches.value_of("min_trades") {
+    forge_config.min_trades = trades.parse().expect("min_trades should be a number");
+  }
+  if let Some(rates) = matches.value_of("mutation") {
+    forge_config.mutation = rates.parse().unwrap_or_else(|e| panic!("Invalid mutation: {}", e));
+  }
+  if let Some(settings) = matches.value_of("adaptive") {
+    let adaptation = settings.parse().unwrap_or_else(|e| panic!("Invalid adaptive: {}", e));
+    forge_config.adaptation = Some(adaptation);
+  }
+  if let Some(method) = matches.value_of("initialization") {
+    forge_config.initialization = method.parse().unwrap_or_else(|e| panic!("Invalid initialization: {}", e));
+  }
+  if let Some(len) = matches.value_of("dna_length") {
+    forge_config.dna_length = len.parse().expect("dna_length should be a number");
+  }
+  if let Some(len) = matches.value_of("min_dna_length") {
+    forge_config.min_dna_length = len.parse().expect("min_dna_length should be a number");
+  }
+  if let Some(len) = matches.value_of("max_dna_length") {
+    forge_config.max_dna_length = len.parse().expect("max_dna_length should be a number");
+  }
+  if let Some(len) = matches.value_of("min_chromosome_length") {
+    forge_config.min_chromosome_length = len.parse().expect("min_chromosome_length should be a number");
+  }
+  if let Some(len) = matches.value_of("max_chromosome_length") {
+    forge_config.max_chromosome_length = len.parse().expect("max_chromosome_length should be a number");
+  }
+  if let Some(penalty) = matches.value_of("parsimony") {
+    forge_config.parsimony = penalty.parse().expect("parsimony should be a number");
+  }
+  forge_config.parsimony_ties = matches.is_present("parsimony_ties");
+  if let Some(ranking) = matches.value_of("ranking") {
+    forge_config.ranking = ranking.parse().unwrap_or_else(|e| panic!("Invalid ranking: {}", e));
+  }
+  if let Some(objectives) = matches.value_of("objectives") {
+    forge_config.objectives =
+      forge::pareto::parse_objectives(objectives).unwrap_or_else(|e| panic!("Invalid objectives: {}", e));
+  }
+  if let Some(islands) = matches.value_of("islands") {
+    forge_config.islands = islands.parse().expect("islands should be a number");
+  }
+  if let Some(interval) = matches.value_of("migration_interval") {
+    forge_config.migration_interval = interval.parse().expect("migration_interval should be a number");
+  }
+  if let Some(migrants) = matches.value_of("migrants") {
+    forge_config.migrants = migrants.parse().expect("migrants should be a number");
+  }
+  if let Some(topology) = matches.value_of("topology") {
+    forge_config.topology = topology.parse().unwrap_or_else(|e| panic!("Invalid topology: {}", e));
+  }
+  if let Some(niching) = matches.value_of("niching") {
+    forge_config.niching = niching.parse().unwrap_or_else(|e| panic!("Invalid niching: {}", e));
+  }
+  if let Some(weight) = matches.value_of("genotype_weight") {
+    forge_config.genotype_weight = weight.parse().expect("genotype_weight should be a number");
+  }
+  if let (forge::Niching::Sharing(_), Ranking::Pareto) = (forge_config.niching, forge_config.ranking) {
+    panic!("Fitness sharing ranks by kelly and can't be used with pareto ranking");
+  }
+  if let (forge::Niching::Sharing(_), &forge::selection::Method::Roulette) =
+    (forge_config.niching, &forge_config.selection)
+  {
+    panic!("Fitness sharing orders the population and roulette selection spins on fitness, so they can't be used together");
+  }
+  if let Some(generations) = matches.value_of("max_generations") {
+    forge_config.max_generations = generations.parse().expect("max_generations should be a number");
+  }
+  if let Some(seconds) = matches.value_of("time_budget") {
+    forge_config.time_budget = Some(seconds.parse().expect("time_budget should be a number of seconds"));
+  }
+  if let Some(generations) = matches.value_of("stagnation") {
+    forge_config.stagnation = generations.parse().expect("stagnation should be a number");
+  }
+  if let Some(epsilon) = matches.value_of("epsilon") {
+    forge_config.epsilon = epsilon.parse().expect("epsilon should be a number");
+  }
+  if forge_config.max_generations < 1 {
+    panic!("max_generations should be at least 1");
+  }
+  let island_specs: Vec<forge::IslandSpec> = matches
+    .values_of("island")
+    .map(|specs| specs.map(|s| s.parse().unwrap_or_else(|e| panic!("Invalid island: {}", e))).collect())
+    .unwrap_or_default();
+  if island_specs.len() > forge_config.islands {
+    forge_config.islands = island_specs.len();
+  }
+  if forge_config.islands == 0 {
+    panic!("islands should be at least 1");
+  }
+  if forge_config.min_dna_length > forge_config.max_dna_length {
+    panic!("min_dna_length should not be greater than max_dna_length");
+  }
+  let max_chromosome_length = forge_config.max_chromosome_length;
+  if max_chromosome_length > 0 && forge_config.min_chromosome_length > max_chromosome_length {
+    panic!("min_chromosome_length should not be greater than max_chromosome_length");
+  }
+  info!("Forge config: {:?}", forge_config);
+  let seed = seed.unwrap_or_else(forge::random_seed);
+  info!("Seed: {}", seed);
+  let termination = forge::Termination::new(&forge_config);
+  let hall_of_fame = forge::HallOfFame::new(forge_config.hall_of_fame);
+  let tickers = get_tickers(repo_path);
+  let seeds = seed_population(matches);
+  let islands: Vec<forge::Island> = (0..forge_config.islands)
+    .map(|i| {
+      let spec = island_specs.get(i).cloned().unwrap_or_default();
+      let mut island = forge::Island::new(i as i32, &spec, &forge_config, &tickers, seed)
+        .unwrap_or_else(|e| panic!("Invalid island: {}", e));
+      island.seeds = seeds.clone();
+      island
+    })
+    .collect();
+  Checkpoint {
+    threads: num_of_threads,
+    signal_cache,
+    target_ticker: target_ticker.to_string(),
+    backtest_id,
+    repo_path: repo_path.to_string(),
+    target_returns_path,
+    grammar,
+    all_strategies,
+    seed,
+    config: forge_config,
+    generation: 0,
+    islands,
+    termination,
+    hall_of_fame,
+    completed_chromosomes: init_completed_chromosomes(),
+  }
+}
+
+/// Reads the dna that starts the first generation of every island
+///
+/// Takes the valid chromosomes of the seed population, or the best of them by
+/// a column, and drops repeated dna.
+fn seed_population(matches: &ArgMatches) -> Vec<forge::Dna> {
+  let path = match matches.value_of("seed_population") {
+    Some(path) => path,
+    None => return vec![],
+  };
+  let chromosomes = repo::get_generation(path).unwrap_or_else(|e| panic!("Invalid seed population: {}", e));
+  let mut chromosomes: Vec<Chromosome> = chromosomes.into_iter().filter(|c| c.valid).collect();
+  if let Some(top) = matches.value_of("seed_top") {
+    let top: usize = top.parse().expect("seed_top should be a number");
+    let column = matches.value_of("seed_by").unwrap_or("kelly");
+    chromosomes =
+      repo::top_chromosomes(chromosomes, column, top).unwrap_or_else(|e| panic!("Invalid seed_by: {}", e));
+  }
+  let mut seen: HashSet<forge::Dna> = HashSet::new();
+  let seeds: Vec<forge::Dna> = chromosomes.into_iter().map(|c| c.dna).filter(|dna| seen.insert(dna.clone())).collect();
+  info!("Seeding {} dnas from {}", seeds.len(), path);
+  seeds
+}
+
+/// Continues a run from its checkpoint
+fn resume_run(matches: &ArgMatches) -> Checkpoint {
+  let path = matches.value_of("checkpoint").unwrap();
+  let mut run = Checkpoint::read(path).unwrap_or_else(|e| panic!("Invalid checkpoint {}: {}", path, e));
+  info!("Resuming {} after generation {}", run.backtest_id, run.generation);
+  if let Some(threads) = matches.value_of("threads") {
+    run.threads = threads.parse().expect("threads should be a number");
+  }
+  info!("Number of threads: {}", run.threads);
+  if let Some(genes) = matches.value_of("signal_cache") {
+    run.signal_cache = genes.parse().expect("signal_cache should be a number");
+  }
+  if let Some(generations) = matches.value_of("max_generations") {
+    run.config.max_generations = generations.parse().expect("max_generations should be a number");
+    run.termination.set_max_generations(run.config.max_generations);
+  }
+  info!("Forge config: {:?}", run.config);
+  run
+}
+
 // Creates a vector of tickers from CSV file names
 // filenames should be named in the following format:
 // AAPL.csv
@@ -226,9 +786,13 @@ fn get_tickers(repo_path: &str) -> Vec<String> {
 // TARGET_TICKER-TICKER_POOL
 // i.e. SPX-SP500
 // We add epoch to differentiate between different runs of the same target-pool
+// Seeded runs use the seed instead so that they can be reproduced exactly
 //
-fn generate_backtest_id(pool_description: &str, target_ticker: &str) -> String {
+fn generate_backtest_id(pool_description: &str, target_ticker: &str, seed: Option<u64>) -> String {
   let id = format!("{}_{}", pool_description, target_ticker);
+  if let Some(seed) = seed {
+    return format!("{}-seed{}", id, seed);
+  }
   let start = SystemTime::now();
   let epoch = start
     .duration_since(UNIX_EPOCH)
@@ -236,6 +800,18 @@ fn generate_backtest_id(pool_description: &str, target_ticker: &str) -> String {
   format!("{}-{:?}", id, epoch)
 }
 
+/// Initializes the quote store shared by every chromosome
+///
+/// Quotes and returns are read row by row and kept by column. The rows are
+/// dropped once the store is built.
+fn init_store(tickers: &Vec<String>, repo_path: &str, target_returns_path: &str) -> Store {
+  info!("Initializing quotes repo");
+  let quotes_repo = init_quotes_repo(tickers, repo_path);
+  info!("Initializing returns");
+  let returns = init_returns(target_returns_path);
+  Store::new(&quotes_repo, &returns)
+}
+
 /// Initializes hashmap for quotes
 ///
 /// The quotes repo
@@ -276,21 +852,23 @@ fn init_completed_chromosomes() -> HashMap<String, Chromosome> {
   HashMap::new()
 }
 
-/// Generate or evolve chromosomes
+/// Generate or evolve the chromosomes of an island
 fn generate_chromosomes(
-  ranked_chromosomes: Vec<Chromosome>,
+  island: &mut forge::Island,
   generation: i32,
-  tickers: &Vec<String>,
   target_ticker: &str,
+  grammar: &Grammar,
 ) -> Vec<Chromosome> {
-  warn!("Running generation: {}", generation);
+  warn!("Running generation {} on island {}", generation, island.id);
+  info!(
+    "Rates of generation {} on island {}: mutation {}, crossover_prob {}",
+    generation, island.id, island.config.mutation, island.config.crossover_prob
+  );
 
-  if generation == 1 {
-    let dnas = forge::generate_dnas(12, config::POPULATION_SIZE);
-    return forge::generate_chromosomes(dnas.clone(), generation, target_ticker, tickers);
-  } else {
-    return forge::evolve(ranked_chromosomes, generation, tickers, target_ticker, config::FITTEST, config::POPULATION_SIZE);
-  }
+  let chromosomes = island.next_generation(generation, target_ticker, grammar);
+  let invalid = chromosomes.iter().filter(|c| !c.valid).count();
+  info!("Invalid chromosomes in generation {} on island {}: {}", generation, island.id, invalid);
+  chromosomes
 }
 
 /// Init channel to collect updated chromosomes
@@ -312,71 +890,124 @@ fn init_throttle(
   crossbeam_channel::bounded(workers)
 }
 
+/// Everything the threads evaluating chromosomes share
+#[derive(Clone)]
+pub struct Evaluation {
+  /// Quotes and returns of the run
+  pub store: Arc<Store>,
+  pub cache: Arc<SignalCache>,
+  /// Run the signals are written for
+  pub backtest_id: String,
+}
+
 /// Process chromosomes
 ///
-/// Nothing is returned. Instead chromosomes are collected after they are sent back to
-/// the chromosome receiver channel outside of the `for` loop.
+/// Valid chromosomes are evaluated on up to `threads` threads at a time. A
+/// rule is only evaluated once per run. Chromosomes whose rule was evaluated
+/// before, in an earlier generation or earlier in this one, get a copy of the
+/// evaluated chromosome instead. Elites, migrants, hall of fame members and
+/// seeds repeat rules on purpose, so they stay in the population this way.
+///
+/// Returns every chromosome, invalid ones included, once the threads are done.
 pub fn process_chromosomes(
   chromosomes: Vec<Chromosome>,
   completed_chromosomes: &mut HashMap<String, Chromosome>,
-  quotes_repo: &HashMap<String, Vec<Quote>>,
-  returns: &BTreeMap<String, Return>,
-  chromosome_tx: Sender<Chromosome>,
-  throttle_tx: crossbeam_channel::Sender<i32>,
-  throttle_rx: crossbeam_channel::Receiver<i32>,
-  backtest_id: &String,
-) {
+  evaluation: &Evaluation,
+  threads: usize,
+) -> Vec<Chromosome> {
+  let (chromosome_tx, chromosome_rx) = init_chromosomes_channel();
+  let (throttle_tx, throttle_rx) = init_throttle(threads);
+  let mut processed: Vec<Chromosome> = vec![];
+  let mut repeats: Vec<Chromosome> = vec![];
+  let mut evaluating: HashSet<String> = HashSet::new();
   for chromosome in chromosomes {
-    let q_clone = quotes_repo.clone();
-    let r_clone = returns.clone();
+    // Invalid chromosomes keep their worst fitness and skip evaluation
+    if !chromosome.valid {
+      processed.push(chromosome);
+      continue;
+    }
+    let key = chromosome.chromosome.to_string();
+    if completed_chromosomes.contains_key(&key) || !evaluating.insert(key) {
+      print!("*");
+      io::stdout().flush().unwrap();
+      repeats.push(chromosome);
+      continue;
+    }
+    print!(".");
+    let evaluation_clone = evaluation.clone();
     let chromosome_chan = chromosome_tx.clone();
     let throttle = throttle_rx.clone();
-    let backtest_id_clone = backtest_id.clone();
 
     throttle_tx.send(1); // The value doesn't matter
 
     debug!("Throttle length: {}", throttle_rx.len());
 
-    if completed_chromosomes.contains_key(&chromosome.chromosome) == false {
-      print!(".");
-      completed_chromosomes.insert(chromosome.chromosome.clone(), chromosome.clone());
-      thread::spawn(move || {
-        chromosome_chan
-          .send(process_chromosome(
-            &chromosome,
-            q_clone,
-            r_clone,
-            backtest_id_clone,
-          ))
-          .unwrap();
-        throttle.recv().unwrap();
-      });
-    } else {
-      print!("*");
-      io::stdout().flush().unwrap();
+    thread::spawn(move || {
+      chromosome_chan.send(process_chromosome(&chromosome, &evaluation_clone)).unwrap();
       throttle.recv().unwrap();
+    });
+  }
+  // The channel closes once every thread has sent its chromosome
+  drop(chromosome_tx);
+  for evaluated in chromosome_rx.iter() {
+    completed_chromosomes.insert(evaluated.chromosome.to_string(), evaluated.clone());
+    processed.push(evaluated);
+  }
+  for chromosome in repeats {
+    match completed_chromosomes.get(&chromosome.chromosome.to_string()) {
+      Some(evaluated) => processed.push(reuse_evaluation(evaluated, chromosome)),
+      None => error!("Dropping chromosome {}: its rule wasn't evaluated", chromosome.id),
     }
   }
+  processed
+}
+
+/// Copy of an evaluated chromosome for another chromosome with the same rule
+///
+/// The copy keeps the id, dna, lineage, island and generation of the
+/// chromosome and takes the stats of the evaluated one.
+fn reuse_evaluation(evaluated: &Chromosome, chromosome: Chromosome) -> Chromosome {
+  Chromosome {
+    id: chromosome.id,
+    dna: chromosome.dna,
+    generation: chromosome.generation,
+    island: chromosome.island,
+    parents: chromosome.parents,
+    ..evaluated.clone()
+  }
 }
 
 /// Generate signals and metadata for chromosome
-pub fn process_chromosome(
-  chromosome: &Chromosome,
-  quotes_repo: HashMap<String, Vec<Quote>>,
-  returns: BTreeMap<String, Return>,
-  backtest_id: String,
-) -> Chromosome {
-  let mut trade_signals = vger::generate_signals(&chromosome, quotes_repo);
-  vger::merge_returns(&mut trade_signals, &returns);
-  vger::calc_pnl(&mut trade_signals, chromosome.clone());
-  writer::write_signals(&trade_signals, &chromosome, backtest_id);
-  vger::update_chromosome(chromosome.clone(), trade_signals)
+///
+/// Chromosomes that can't be evaluated, i.e. a gene with an unknown strategy,
+/// are logged and sent back invalid with the worst fitness.
+pub fn process_chromosome(chromosome: &Chromosome, evaluation: &Evaluation) -> Chromosome {
+  let store = &evaluation.store;
+  let signals = match vger::generate_signals(chromosome, store, &evaluation.cache) {
+    Ok(signals) => signals,
+    Err(e) => {
+      error!("Skipping chromosome {}: {}", chromosome.chromosome, e);
+      let mut invalid = chromosome.clone();
+      invalid.invalidate();
+      return invalid;
+    }
+  };
+  writer::write_signals(signals.rows(store), chromosome, evaluation.backtest_id.clone());
+  signals.update_chromosome(chromosome.clone(), store)
 }
 
 /// Rank chromosomes by w_kelly
 ///
 /// Rank is determined by the offset of the chromosomes.
 ///
+/// When ranking by Pareto front the chromosomes of the previous generation
+/// compete with their children, and the best `population_size` of both
+/// survive by front and crowding distance as in NSGA-II. Otherwise
+/// chromosomes are sorted by kelly. Fronts are recorded either way.
+///
+/// With deterministic crowding each child first competes with the closer of
+/// its parents in the previous generation and only the winners are ranked.
+///
 /// ## Rank calculation
 /// ```
 /// Assume:
@@ -386,23 +1017,43 @@ pub fn process_chromosome(
 /// if fittest = 5 then the start idx = 5 and negative rank = 20 - 5 - 5 - 1 which
 /// makes the starting index 9.
 /// ```
-pub fn rank_chromosomes(updated_chromosomes: Vec<Chromosome>) -> Vec<Chromosome> {
+pub fn rank_chromosomes(
+  updated_chromosomes: Vec<Chromosome>,
+  previous_chromosomes: Vec<Chromosome>,
+  forge_config: &forge::Config,
+) -> Vec<Chromosome> {
   // Filter chromosomes by number of trades
   let mut filtered_chromosomes: Vec<Chromosome> = updated_chromosomes
     .into_iter()
-    .filter(|c| c.num_of_trades > 100)
+    .filter(|c| c.num_of_trades > forge_config.min_trades)
     .collect();
-  // Sort chromosomes
-  // Need to use sort_by for vectors since there's something quirky about
-  // comparing f32 in Rust
-  filtered_chromosomes.sort_by(|a, b| a.kelly.partial_cmp(&b.kelly).unwrap());
+  let objectives = &forge_config.objectives;
+  let previous_chromosomes = if forge_config.niching == forge::Niching::DeterministicCrowding {
+    filtered_chromosomes = forge::diversity::crowd(previous_chromosomes, filtered_chromosomes, forge_config);
+    vec![]
+  } else {
+    previous_chromosomes
+  };
+  match forge_config.ranking {
+    // Only the Pareto front is logged and written when ranking by kelly
+    Ranking::Kelly => {
+      forge::pareto::assign_first_front(&mut filtered_chromosomes, objectives);
+    }
+    Ranking::Pareto => {
+      filtered_chromosomes.extend(previous_chromosomes);
+      let size = forge_config.population_size as usize;
+      filtered_chromosomes = forge::pareto::survivors(filtered_chromosomes, objectives, size);
+    }
+  }
+  // Sort by fitness, or by front again to break ties on rule length
+  forge::sort_population(&mut filtered_chromosomes, forge_config);
   // Calculate starting index
   // The data is sorted in ascending order resulting in the fittest results
   // to be at the tail of the array. Therefore, the start index is the length
   // of the vector minus the number of fittest chromosomes we're looking for
   let end_idx = filtered_chromosomes.len() as i32;
   let fittest = config::FITTEST as i32;
-  let start_idx = end_idx - fittest;
+  let start_idx = (end_idx - fittest).max(0);
   // Since we sort in ascending order we have to run this for tag the 
   // top fittest chromosomes 
   for i in start_idx..end_idx {
@@ -422,4 +1073,90 @@ mod tests {
   fn it_works() {
     assert_eq!(2 + 2, 4);
   }
+
+  // A year of AAPL quotes that zig zag, with returns on every day
+  fn store() -> Store {
+    let quotes: Vec<Quote> = (0..365)
+      .map(|i| {
+        let close = 100.0 + (i % 7) as f32 - (i % 3) as f32;
+        Quote {
+          ticker: "AAPL".to_string(),
+          ts: 1528745804.0 + i as f64 * 86400.0,
+          open: close,
+          high: close + 1.0,
+          low: close - 1.0,
+          close,
+          volume: 1000.0,
+        }
+      })
+      .collect();
+    let returns: BTreeMap<String, Return> = quotes
+      .iter()
+      .enumerate()
+      .map(|(i, q)| (q.ts.to_string(), Return { ts: q.ts, ret: (i % 5) as f32 / 100.0 - 0.015 }))
+      .collect();
+    let mut quotes_repo = HashMap::new();
+    quotes_repo.insert("AAPL".to_string(), quotes);
+    Store::new(&quotes_repo, &returns)
+  }
+
+  #[test]
+  fn test_load_grammar_keeps_its_strategies() {
+    let stmnt = |grammar: &Grammar| grammar.productions(forge::grammar::STRATEGIES).unwrap().len();
+    let source = "<code> ::= <stmnt>\n<stmnt> ::= trend:<ticker>:<set:5,10>";
+    assert_eq!(1, stmnt(&load_grammar(source, false).unwrap()));
+    let registered = vger::strategies::REGISTRY.len();
+    assert_eq!(registered, stmnt(&load_grammar(source, true).unwrap()));
+    assert_eq!(registered, stmnt(&load_grammar("<code> ::= <stmnt>", false).unwrap()));
+    assert!(load_grammar("<code> ::= <stmnt>\n<stmnt> ::= sma:<ticker>:<param>", false).is_err());
+  }
+
+  #[test]
+  fn test_repeated_rules_survive_ranking() {
+    let evaluation = Evaluation {
+      store: Arc::new(store()),
+      cache: Arc::new(SignalCache::new(10)),
+      backtest_id: "helix_test_repeated_rules".to_string(),
+    };
+    let cache = &evaluation.cache;
+    let config = forge::Config::default();
+    let grammar = load_grammar(forge::config::GRAMMAR, true).unwrap();
+    let tickers = vec!["AAPL".to_string()];
+    let generate = |generation: i32, rules: &[&str]| -> Vec<Chromosome> {
+      let dnas = rules.iter().map(|_| vec![0, 0, 0, 0]).collect();
+      let mut rng = forge::seeded_rng(generation as u64);
+      forge::generate_chromosomes(dnas, generation, "AAPL", &tickers, &grammar, &config, &mut rng)
+        .into_iter()
+        .zip(rules)
+        .map(|(c, rule)| Chromosome {
+          chromosome: forge::Rule::parse(rule).unwrap(),
+          ..c
+        })
+        .collect()
+    };
+    let mut completed = init_completed_chromosomes();
+    // The same rule twice in one generation is evaluated once
+    let first = generate(1, &["trend:AAPL:5", "trend:AAPL:10", "trend:AAPL:5"]);
+    let evaluated = process_chromosomes(first.clone(), &mut completed, &evaluation, 2);
+    assert_eq!(3, evaluated.len());
+    assert_eq!(2, completed.len());
+    assert_eq!(2, cache.stats().misses);
+    let by_id = |id| evaluated.iter().find(|c: &&Chromosome| c.id == id).unwrap();
+    assert!(by_id(first[0].id).num_of_trades > 100);
+    assert_eq!(by_id(first[0].id).kelly, by_id(first[2].id).kelly);
+    let population = rank_chromosomes(evaluated.clone(), vec![], &config);
+    assert_eq!(3, population.len());
+    // An elite and an injected member repeat rules of the last generation
+    let mut next = generate(2, &["trend:AAPL:5", "trend:AAPL:10"]);
+    next[1].island = 3;
+    let evaluated = process_chromosomes(next.clone(), &mut completed, &evaluation, 2);
+    assert_eq!(2, cache.stats().misses);
+    let population = rank_chromosomes(evaluated, population, &config);
+    for chromosome in &next {
+      let survivor = population.iter().find(|c| c.id == chromosome.id).expect("repeat was dropped");
+      assert_eq!(2, survivor.generation);
+      assert_eq!(chromosome.island, survivor.island);
+      assert_eq!(completed[&chromosome.chromosome.to_string()].kelly, survivor.kelly);
+    }
+  }
 }
@@ -0,0 +1,148 @@
+//! Fixed length sets of bits
+//!
+//! Signals are kept as one bit per day of the timeline, so combining the
+//! signals of genes is a bitwise operation on 64 days at a time.
+use std::fmt;
+
+/// A set of bits `0..len`
+#[derive(Clone, PartialEq, Eq)]
+pub struct Bitset {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Bitset {
+    /// All bits off
+    pub fn new(len: usize) -> Bitset {
+        Bitset {
+            words: vec![0; (len + 63) / 64],
+            len,
+        }
+    }
+
+    /// All bits on
+    pub fn full(len: usize) -> Bitset {
+        let mut bits = Bitset {
+            words: vec![!0; (len + 63) / 64],
+            len,
+        };
+        bits.clear_tail();
+        bits
+    }
+
+    /// Bits of the indices an iterator yields
+    pub fn from_indices<I: IntoIterator<Item = usize>>(len: usize, indices: I) -> Bitset {
+        let mut bits = Bitset::new(len);
+        for i in indices {
+            bits.insert(i);
+        }
+        bits
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|w| *w == 0)
+    }
+
+    pub fn insert(&mut self, i: usize) {
+        assert!(i < self.len, "bit {} out of {}", i, self.len);
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    pub fn contains(&self, i: usize) -> bool {
+        i < self.len && self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    /// Number of bits on
+    pub fn count(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn and(&self, other: &Bitset) -> Bitset {
+        self.zip(other, |a, b| a & b)
+    }
+
+    pub fn or(&self, other: &Bitset) -> Bitset {
+        self.zip(other, |a, b| a | b)
+    }
+
+    /// Bits on in `self` but not in `other`
+    pub fn and_not(&self, other: &Bitset) -> Bitset {
+        self.zip(other, |a, b| a & !b)
+    }
+
+    pub fn not(&self) -> Bitset {
+        let mut bits = Bitset {
+            words: self.words.iter().map(|w| !w).collect(),
+            len: self.len,
+        };
+        bits.clear_tail();
+        bits
+    }
+
+    /// Indices of the bits on, in ascending order
+    pub fn ones<'a>(&'a self) -> impl Iterator<Item = usize> + 'a {
+        self.words.iter().enumerate().flat_map(|(i, word)| {
+            let mut word = *word;
+            (0..word.count_ones()).map(move |_| {
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                i * 64 + bit
+            })
+        })
+    }
+
+    /// Bytes taken by the bits
+    pub fn size(&self) -> usize {
+        self.words.len() * 8
+    }
+
+    fn zip<F: Fn(u64, u64) -> u64>(&self, other: &Bitset, f: F) -> Bitset {
+        assert_eq!(self.len, other.len, "bitsets of different lengths");
+        Bitset {
+            words: self.words.iter().zip(&other.words).map(|(a, b)| f(*a, *b)).collect(),
+            len: self.len,
+        }
+    }
+
+    /// Turns off the bits past `len` in the last word
+    fn clear_tail(&mut self) {
+        let tail = self.len % 64;
+        if tail > 0 {
+            if let Some(last) = self.words.last_mut() {
+                *last &= (1 << tail) - 1;
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Bitset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bits: String = (0..self.len)
+            .map(|i| if self.contains(i) { '1' } else { '0' })
+            .collect();
+        write!(f, "Bitset({})", bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operations() {
+        let a = Bitset::from_indices(70, vec![0, 3, 64, 69]);
+        let b = Bitset::from_indices(70, vec![3, 5, 69]);
+        assert_eq!(vec![3, 69], a.and(&b).ones().collect::<Vec<usize>>());
+        assert_eq!(vec![0, 3, 5, 64, 69], a.or(&b).ones().collect::<Vec<usize>>());
+        assert_eq!(vec![0, 64], a.and_not(&b).ones().collect::<Vec<usize>>());
+        assert_eq!(66, a.not().count());
+        assert!(!a.not().contains(70));
+        assert_eq!(Bitset::full(70), a.or(&a.not()));
+        assert!(a.and(&a.not()).is_empty());
+        assert_eq!(16, a.size());
+    }
+}
@@ -0,0 +1,259 @@
+//! Cache of the signals of genes
+//!
+//! The signals of a gene only depend on its strategy, ticker and parameter,
+//! not on the chromosome it is in. Later generations are mostly made of genes
+//! that were already evaluated, so the signals of each gene are computed once
+//! and shared by every chromosome and thread that needs them.
+//!
+//! The cache holds at most `capacity` genes. When it is full the gene that
+//! was used least recently is evicted.
+use signals::GeneSignals;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use store::Quotes;
+use strategies::StrategyCall;
+use timeline::Timeline;
+use Error;
+
+/// Number of genes cached by default
+pub static DEFAULT_CAPACITY: usize = 1000;
+
+/// What the signals of a gene depend on, i.e. `hhv`, `AAPL` and `43`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GeneKey {
+    pub code: String,
+    pub ticker: String,
+    pub param: i32,
+}
+
+impl GeneKey {
+    pub fn new(strategy: &StrategyCall) -> GeneKey {
+        GeneKey {
+            code: strategy.code.clone(),
+            ticker: strategy.ticker.clone(),
+            param: strategy.param,
+        }
+    }
+}
+
+/// Cached signals with the tick they were last used on
+struct Entries {
+    signals: HashMap<GeneKey, (Arc<GeneSignals>, u64)>,
+    tick: u64,
+}
+
+/// Signals of genes shared across threads
+pub struct SignalCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    evictions: AtomicUsize,
+}
+
+impl SignalCache {
+    /// Cache of up to `capacity` genes. A capacity of 0 turns caching off
+    pub fn new(capacity: usize) -> SignalCache {
+        SignalCache {
+            capacity,
+            entries: Mutex::new(Entries {
+                signals: HashMap::new(),
+                tick: 0,
+            }),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+            evictions: AtomicUsize::new(0),
+        }
+    }
+
+    /// Gets the signals of a gene, computing them on a miss
+    ///
+    /// The lock isn't held while the signals are computed, so two threads
+    /// that miss the same gene at once both compute it.
+    pub fn signals(
+        &self,
+        strategy: &StrategyCall,
+        quotes: &Quotes,
+        timeline: &Timeline,
+    ) -> Result<Arc<GeneSignals>, Error> {
+        let key = GeneKey::new(strategy);
+        if let Some(signals) = self.get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(signals);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let signals = Arc::new(GeneSignals::new(strategy, quotes, timeline)?);
+        self.insert(key, signals.clone());
+        Ok(signals)
+    }
+
+    /// Statistics of the cache so far
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            genes: self.entries.lock().unwrap().signals.len(),
+            capacity: self.capacity,
+        }
+    }
+
+    fn get(&self, key: &GeneKey) -> Option<Arc<GeneSignals>> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.tick += 1;
+        let tick = entries.tick;
+        entries.signals.get_mut(key).map(|entry| {
+            entry.1 = tick;
+            entry.0.clone()
+        })
+    }
+
+    fn insert(&self, key: GeneKey, signals: Arc<GeneSignals>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.tick += 1;
+        let tick = entries.tick;
+        entries.signals.insert(key, (signals, tick));
+        if entries.signals.len() > self.capacity {
+            let oldest = entries
+                .signals
+                .iter()
+                .min_by_key(|(_, entry)| entry.1)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.signals.remove(&oldest);
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Hits and misses of a `SignalCache`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+    /// Genes in the cache
+    pub genes: usize,
+    pub capacity: usize,
+}
+
+impl CacheStats {
+    /// Share of lookups that were hits, 0 before the first lookup
+    pub fn hit_rate(&self) -> f32 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f32 / lookups as f32
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses ({:.1}% hits), {} evictions, {}/{} genes",
+            self.hits,
+            self.misses,
+            self.hit_rate() * 100.0,
+            self.evictions,
+            self.genes,
+            self.capacity
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use strategies;
+    use uuid::Uuid;
+
+    fn call(gene: &str) -> StrategyCall {
+        let parts: Vec<&str> = gene.split(':').collect();
+        StrategyCall {
+            chromosome_id: Uuid::new_v4(),
+            strategy: gene.to_string(),
+            code: parts[0].to_string(),
+            ticker: parts[1].to_string(),
+            param: parts[2].parse().unwrap(),
+            target_ticker: "SPY".to_string(),
+            generation: 1,
+        }
+    }
+
+    #[test]
+    fn test_hits_and_misses() {
+        let quotes = Quotes::new(&strategies::random_walk(100));
+        let timeline = Timeline::new(vec![&quotes]);
+        let cache = SignalCache::new(10);
+        let first = cache.signals(&call("hhv:AAPL:5"), &quotes, &timeline).unwrap();
+        let second = cache.signals(&call("hhv:AAPL:5"), &quotes, &timeline).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(GeneSignals::new(&call("hhv:AAPL:5"), &quotes, &timeline).unwrap(), *first);
+        cache.signals(&call("hhv:AAPL:6"), &quotes, &timeline).unwrap();
+        let stats = cache.stats();
+        assert_eq!((1, 2, 0, 2), (stats.hits, stats.misses, stats.evictions, stats.genes));
+        assert_eq!(1.0 / 3.0, stats.hit_rate());
+        assert_eq!(
+            Err(Error::UnknownStrategy("xyz".to_string())),
+            cache.signals(&call("xyz:AAPL:5"), &quotes, &timeline)
+        );
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let quotes = Quotes::new(&strategies::random_walk(100));
+        let timeline = Timeline::new(vec![&quotes]);
+        let cache = SignalCache::new(2);
+        cache.signals(&call("hhv:AAPL:5"), &quotes, &timeline).unwrap();
+        cache.signals(&call("hhv:AAPL:6"), &quotes, &timeline).unwrap();
+        cache.signals(&call("hhv:AAPL:5"), &quotes, &timeline).unwrap();
+        cache.signals(&call("hhv:AAPL:7"), &quotes, &timeline).unwrap();
+        assert_eq!(1, cache.stats().evictions);
+        assert_eq!(2, cache.stats().genes);
+        cache.signals(&call("hhv:AAPL:5"), &quotes, &timeline).unwrap();
+        assert_eq!(2, cache.stats().hits);
+        cache.signals(&call("hhv:AAPL:6"), &quotes, &timeline).unwrap();
+        assert_eq!(4, cache.stats().misses);
+
+        let off = SignalCache::new(0);
+        off.signals(&call("hhv:AAPL:5"), &quotes, &timeline).unwrap();
+        off.signals(&call("hhv:AAPL:5"), &quotes, &timeline).unwrap();
+        assert_eq!((0, 2, 0), (off.stats().hits, off.stats().misses, off.stats().genes));
+    }
+
+    #[test]
+    fn test_shared_across_threads() {
+        let quotes = Arc::new(Quotes::new(&strategies::random_walk(100)));
+        let timeline = Arc::new(Timeline::new(vec![&*quotes]));
+        let cache = Arc::new(SignalCache::new(10));
+        let handles: Vec<thread::JoinHandle<()>> = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                let quotes = quotes.clone();
+                let timeline = timeline.clone();
+                thread::spawn(move || {
+                    for param in 5..8 {
+                        cache
+                            .signals(&call(&format!("llv:AAPL:{}", param)), &quotes, &timeline)
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let stats = cache.stats();
+        assert_eq!(12, stats.hits + stats.misses);
+        assert_eq!(3, stats.genes);
+    }
+}
@@ -1,4 +1,11 @@
 //! Calculation functions
+//!
+//! The rolling functions compute a statistic for every window of `n` values
+//! in one pass, like `slice::windows`: `result[i]` covers `values[i..i + n]`.
+//! A series shorter than the window has no windows and an empty window has a
+//! NaN mean and standard deviation.
+use std::collections::VecDeque;
+use std::f32;
 
 /// Calculates average from vector of f32s
 ///
@@ -36,4 +43,159 @@ pub fn kelly(mean: f32, variance: f32) -> f32 {
         return mean / variance;
     }
     return 0.0;
-}
\ No newline at end of file
+}
+
+/// Calculates the mean of every window of `n` values
+///
+/// The sum is carried from one window to the next.
+pub fn rolling_mean(values: &[f32], n: usize) -> Vec<f32> {
+    rolling_sums(values, n)
+        .into_iter()
+        .map(|(sum, _)| sum as f32 / n as f32)
+        .collect()
+}
+
+/// Calculates the standard deviation of every window of `n` values
+///
+/// Like `std_dev` this is the population standard deviation.
+pub fn rolling_std(values: &[f32], n: usize) -> Vec<f32> {
+    rolling_sums(values, n)
+        .into_iter()
+        .map(|(sum, square_sum)| {
+            let mean = sum / n as f64;
+            let variance = square_sum / n as f64 - mean * mean;
+            // Rounding can take a flat window just below 0
+            if variance < 0.0 {
+                0.0
+            } else {
+                variance.sqrt() as f32
+            }
+        })
+        .collect()
+}
+
+/// Calculates the largest value of every window of `n` values
+pub fn rolling_max(values: &[f32], n: usize) -> Vec<f32> {
+    rolling_extreme(values, n, f32::NEG_INFINITY, |a, b| a >= b)
+}
+
+/// Calculates the smallest value of every window of `n` values
+pub fn rolling_min(values: &[f32], n: usize) -> Vec<f32> {
+    rolling_extreme(values, n, f32::INFINITY, |a, b| a <= b)
+}
+
+/// Counts the flags in a row that are set, ending at each flag
+///
+/// ```text
+/// flags        [1, 1, 0, 1, 1, 1]
+/// run lengths  [1, 2, 0, 1, 2, 3]
+/// ```
+pub fn run_lengths(flags: &[bool]) -> Vec<usize> {
+    let mut run = 0;
+    flags
+        .iter()
+        .map(|&flag| {
+            run = if flag { run + 1 } else { 0 };
+            run
+        })
+        .collect()
+}
+
+/// Calculates the change of every value from the value `lag` before it
+pub fn diff(values: &[f32], lag: usize) -> Vec<f32> {
+    if values.len() < lag {
+        return vec![];
+    }
+    (lag..values.len()).map(|i| values[i] - values[i - lag]).collect()
+}
+
+// Sum and sum of squares of every window, carried in f64 so that adding and
+// dropping values doesn't drift
+fn rolling_sums(values: &[f32], n: usize) -> Vec<(f64, f64)> {
+    if n == 0 {
+        return vec![(0.0, 0.0); values.len() + 1];
+    }
+    if values.len() < n {
+        return vec![];
+    }
+    let mut sums = Vec::with_capacity(values.len() - n + 1);
+    let (mut sum, mut square_sum) = (0.0, 0.0);
+    for (i, &value) in values.iter().enumerate() {
+        if i >= n {
+            sums.push((sum, square_sum));
+            let dropped = values[i - n] as f64;
+            sum -= dropped;
+            square_sum -= dropped * dropped;
+        }
+        sum += value as f64;
+        square_sum += value as f64 * value as f64;
+    }
+    sums.push((sum, square_sum));
+    sums
+}
+
+// Keeps the indexes of the values that can still be the extreme of a window,
+// most extreme first, so each value is pushed and popped once
+fn rolling_extreme<F: Fn(f32, f32) -> bool>(values: &[f32], n: usize, empty: f32, keeps: F) -> Vec<f32> {
+    if n == 0 {
+        return vec![empty; values.len() + 1];
+    }
+    let mut extremes = Vec::with_capacity((values.len() + 1).saturating_sub(n));
+    let mut candidates: VecDeque<usize> = VecDeque::new();
+    for (i, &value) in values.iter().enumerate() {
+        while matches!(candidates.back(), Some(&j) if !keeps(values[j], value)) {
+            candidates.pop_back();
+        }
+        candidates.push_back(i);
+        if candidates[0] + n <= i {
+            candidates.pop_front();
+        }
+        if i + 1 >= n {
+            extremes.push(values[candidates[0]]);
+        }
+    }
+    extremes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolling_mean_and_std() {
+        let values: Vec<f32> = vec![6.0, 2.0, 3.0, 1.0, 8.0];
+        assert_eq!(vec![3.0, 3.5], rolling_mean(&values, 4));
+        let stds = rolling_std(&values, 4);
+        assert_eq!(std_dev(values[0..4].to_vec()), stds[0]);
+        assert!((std_dev(values[1..5].to_vec()) - stds[1]).abs() < 1e-6);
+        assert!(rolling_mean(&values, 6).is_empty());
+        let empty = rolling_mean(&values, 0);
+        assert_eq!(6, empty.len());
+        assert!(empty[0].is_nan());
+        assert!(rolling_std(&values, 0)[0].is_nan());
+    }
+
+    #[test]
+    fn test_rolling_max_and_min() {
+        let values: Vec<f32> = vec![1.0, 3.0, 2.0, 2.0, 5.0, 0.0, 1.0];
+        assert_eq!(vec![3.0, 3.0, 5.0, 5.0, 5.0], rolling_max(&values, 3));
+        assert_eq!(vec![1.0, 2.0, 2.0, 0.0, 0.0], rolling_min(&values, 3));
+        assert_eq!(values, rolling_max(&values, 1));
+        assert_eq!(vec![f32::NEG_INFINITY; 8], rolling_max(&values, 0));
+        assert!(rolling_min(&values, 8).is_empty());
+    }
+
+    #[test]
+    fn test_run_lengths() {
+        let flags = vec![true, true, false, true, true, true];
+        assert_eq!(vec![1, 2, 0, 1, 2, 3], run_lengths(&flags));
+    }
+
+    #[test]
+    fn test_diff() {
+        let values: Vec<f32> = vec![1.0, 3.0, 2.0, 6.0];
+        assert_eq!(vec![2.0, -1.0, 4.0], diff(&values, 1));
+        assert_eq!(vec![1.0, 3.0], diff(&values, 2));
+        assert!(diff(&values, 5).is_empty());
+    }
+}
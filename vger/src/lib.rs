@@ -6,16 +6,56 @@ extern crate log;
 extern crate forge;
 extern crate repo;
 
+use cache::SignalCache;
 use forge::Chromosome;
+use forge::LegStats;
+use forge::Rule;
 use repo::schemas::Quote;
 use repo::schemas::Return;
+use signals::Signals;
 use std::collections::BTreeMap;
-use std::collections::HashMap;
-use strategies::Strategy;
+use std::fmt;
+use store::Store;
+use strategies::StrategyCall;
 use uuid::Uuid;
 
+pub mod bitset;
+pub mod cache;
 pub mod calc;
+pub mod signals;
+pub mod store;
 pub mod strategies;
+pub mod timeline;
+
+/// Signal to go long
+pub const LONG: i32 = 1;
+
+/// Signal to go short
+pub const SHORT: i32 = -1;
+
+/// Signal to stay out of the market
+pub const FLAT: i32 = 0;
+
+/// Errors raised while generating signals for a chromosome
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+  /// The gene doesn't have the parameters its strategy needs
+  InvalidGene(String),
+  /// There is no strategy for the gene's code
+  UnknownStrategy(String),
+  /// There are no quotes for the gene's ticker
+  MissingQuotes(String),
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::InvalidGene(gene) => write!(f, "invalid gene {}", gene),
+      Error::UnknownStrategy(code) => write!(f, "no such strategy {}", code),
+      Error::MissingQuotes(ticker) => write!(f, "no quotes for {}", ticker),
+    }
+  }
+}
 
 /// Struct for grouping daily trade signal data
 #[derive(Debug, Clone)]
@@ -32,12 +72,12 @@ pub struct TradeSignal {
 }
 
 /// Initializes empty trade signal
-pub fn init_trade_signal(strategy: &Strategy, window: &Window, signal: &i32) -> TradeSignal {
+pub fn init_trade_signal(strategy: &StrategyCall, ts: f64, signal: &i32) -> TradeSignal {
   let strategies = vec![strategy.strategy.clone()];
   let signals = vec![*signal];
   TradeSignal {
     chromosome_id: strategy.chromosome_id,
-    ts: window.current_quote.ts,
+    ts,
     strategies: strategies,
     signals: signals,
     target_ticker: strategy.target_ticker.clone(),
@@ -75,45 +115,21 @@ impl Window {
   }
 }
 
-/// Generate strategy signals
-pub fn generate_strategy_signals(
-  strategy: Strategy,
-  trade_signals: &mut BTreeMap<String, TradeSignal>,
-  quotes: &Vec<Quote>,
-) {
-  match strategy.code.as_ref() {
-    "llv" => strategies::lowest_low_value::call(strategy, trade_signals, quotes),
-    "hhv" => strategies::highest_high_value::call(strategy, trade_signals, quotes),
-    "conupdays" => strategies::con_up_days::call(strategy, trade_signals, quotes),
-    "condowndays" => strategies::con_down_days::call(strategy, trade_signals, quotes),
-    "gapup" => strategies::gap_up_days::call(strategy, trade_signals, quotes),
-    "gapdown" => strategies::gap_down_days::call(strategy, trade_signals, quotes),
-    "belowma" => strategies::below_ma::call(strategy, trade_signals, quotes),
-    "abovema" => strategies::above_ma::call(strategy, trade_signals, quotes),
-    "stdeva" => strategies::stddev_a::call(strategy, trade_signals, quotes),
-    "stdevb" => strategies::stddev_b::call(strategy, trade_signals, quotes),
-    "stdevd" => strategies::stddev_d::call(strategy, trade_signals, quotes),
-    "stdevf" => strategies::stddev_f::call(strategy, trade_signals, quotes),
-    _ => panic!("No such strategy"),
-  };
-}
-
 /// Generate signals from chromosome
-pub fn generate_signals(
-  chromosome: &Chromosome,
-  quotes_repo: HashMap<String, Vec<Quote>>,
-) -> BTreeMap<String, TradeSignal> {
-  let mut trade_signals: BTreeMap<String, TradeSignal> = BTreeMap::new();
-  let strategies = strategies::expand_strategies(chromosome.clone());
-  for strategy in strategies {
-    match quotes_repo.get(&strategy.ticker) {
-      Some(quotes) => {
-        generate_strategy_signals(strategy, &mut trade_signals, quotes);
-      }
-      None => panic!("No quotes for strategy {:?}", strategy),
+///
+/// The signals of each gene come from the cache, which computes them the
+/// first time the gene is seen, and are combined by the rule of the
+/// chromosome.
+pub fn generate_signals(chromosome: &Chromosome, store: &Store, cache: &SignalCache) -> Result<Signals, Error> {
+  let mut genes = vec![];
+  for strategy in strategies::expand_strategies(chromosome)? {
+    let signals = match store.get(&strategy.ticker) {
+      Some(quotes) => cache.signals(&strategy, quotes, &store.timeline)?,
+      None => return Err(Error::MissingQuotes(strategy.ticker)),
     };
+    genes.push((strategy.strategy, signals));
   }
-  trade_signals
+  Ok(Signals::new(chromosome, genes, &store.timeline))
 }
 
 /// Merge returns into trade signals
@@ -149,82 +165,211 @@ pub fn update_merge_trade_signal(
 }
 
 /// Calculate hard signal and pnl
+///
+/// The hard signal is the chromosome's rule evaluated against the signals of
+/// its genes on each day. Pnl is the return signed by the side we're on.
 pub fn calc_pnl(trade_signals: &mut BTreeMap<String, TradeSignal>, chromosome: Chromosome) {
   let local = trade_signals.clone();
   for trade_signal in &local {
     let mut s = trade_signal.1.clone();
-    let agg_signal: i32 = s.signals.iter().sum();
-    if chromosome.chromosome_length == agg_signal {
-      s.hard_signal = 1;
-      s.pnl = s.ret * 1.0;
-    }
+    s.hard_signal = hard_signal(&chromosome.chromosome, &s);
+    s.pnl = s.ret * s.hard_signal as f32;
     trade_signals.insert(trade_signal.0.clone(), s);
   }
 }
 
+/// Evaluates a rule against the gene signals of a trade signal
+///
+/// Returns `LONG`, `SHORT` or `FLAT`. Genes without a signal on the day, i.e.
+/// while their window is still filling up, are flat.
+///
+/// ```text
+/// NOT   long when the rule is flat, otherwise flat
+/// SHORT flips long and short
+/// AND   the common side when every rule is on it, otherwise flat
+/// OR    the side of the rules that aren't flat, flat if they disagree
+/// ```
+///
+/// The `INVALID` rule is always flat.
+pub fn hard_signal(rule: &Rule, trade_signal: &TradeSignal) -> i32 {
+  match rule {
+    Rule::Gene(gene) => trade_signal
+      .strategies
+      .iter()
+      .position(|s| *s == gene.to_string())
+      .map(|idx| trade_signal.signals[idx].signum())
+      .unwrap_or(FLAT),
+    Rule::Not(r) => {
+      if hard_signal(r, trade_signal) == FLAT {
+        LONG
+      } else {
+        FLAT
+      }
+    }
+    Rule::Short(r) => -hard_signal(r, trade_signal),
+    Rule::And(rules) => {
+      let signals: Vec<i32> = rules.iter().map(|r| hard_signal(r, trade_signal)).collect();
+      match signals.first() {
+        Some(&first) if signals.iter().all(|s| *s == first) => first,
+        _ => FLAT,
+      }
+    }
+    Rule::Or(rules) => {
+      let signals: Vec<i32> = rules
+        .iter()
+        .map(|r| hard_signal(r, trade_signal))
+        .filter(|s| *s != FLAT)
+        .collect();
+      match signals.first() {
+        Some(&first) if signals.iter().all(|s| *s == first) => first,
+        _ => FLAT,
+      }
+    }
+    Rule::Invalid => FLAT,
+  }
+}
+
 /// Calculate mean return from triggered trade signals
 pub fn mean_return(signaled_trades: &Vec<TradeSignal>) -> f32 {
-  let cum_pnl: f32 = signaled_trades.iter().map(|x| x.pnl).sum();
-  if signaled_trades.len() > 0 {
-    let mean_return: f32 = cum_pnl / signaled_trades.len() as f32;
-    return mean_return;
-  };
-  return 0.0 as f32;
+  mean(&pnls(signaled_trades))
 }
 
 /// Calculates variance
 pub fn variance(signaled_trades: &Vec<TradeSignal>) -> f32 {
-  if signaled_trades.len() > 0 {
-    let mean = mean_return(&signaled_trades);
-    let diffs: Vec<f32> = signaled_trades
-      .iter()
-      .map(|x| (x.pnl - mean).powi(2) as f32)
-      .collect();
-    let sum_diffs: f32 = diffs.iter().sum();
-    let v: f32 = sum_diffs / signaled_trades.len() as f32;
-    return v;
-  }
-  0.0
+  pnl_variance(&pnls(signaled_trades))
 }
 
 /// Updates chromsome with summary data
+///
+/// Every trade signal with a hard signal is a trade.
 pub fn update_chromosome(
   chromosome: Chromosome,
   trade_signals: BTreeMap<String, TradeSignal>,
 ) -> Chromosome {
-  let mut updated_chromosome = chromosome.clone();
-  let total_trade_signals = &trade_signals.len();
-  let signaled_trades: Vec<TradeSignal> = trade_signals
-    .into_iter()
-    .map(|x| x.1)
-    .filter(|signal| signal.hard_signal == 1)
+  let trades: Vec<(f64, i32, f32)> = trade_signals
+    .values()
+    .filter(|signal| signal.hard_signal != FLAT)
+    .map(|signal| (signal.ts, signal.hard_signal, signal.pnl))
     .collect();
+  summarise(chromosome, &trades, trade_signals.len())
+}
+
+/// Updates chromsome with the summary of its trades
+///
+/// `trades` are the timestamp, hard signal and pnl of the days the chromosome
+/// is in the market, out of the `days` it has signals on. The headline stats
+/// cover every trade. The long and short legs are also summarised on their
+/// own.
+pub fn summarise(chromosome: Chromosome, trades: &[(f64, i32, f32)], days: usize) -> Chromosome {
+  let mut updated_chromosome = chromosome.clone();
+  let pnls: Vec<f32> = trades.iter().map(|trade| trade.2).collect();
+  let long_pnls: Vec<f32> = trades.iter().filter(|trade| trade.1 == LONG).map(|trade| trade.2).collect();
+  let short_pnls: Vec<f32> = trades.iter().filter(|trade| trade.1 != LONG).map(|trade| trade.2).collect();
+  let mut by_ts = trades.to_vec();
+  by_ts.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+  let max_drawdown = pnl_drawdown(by_ts.iter().map(|trade| trade.2));
 
   // Calculate summary data
-  let cum_pnl: f32 = signaled_trades.iter().map(|x| x.pnl).sum();
-  let mean_return = mean_return(&signaled_trades);
-  let variance = variance(&signaled_trades);
-  let kelly = calc::kelly(mean_return, variance);
-  let num_of_trades = signaled_trades.len() as i32;
-  let winning_trades: i32 = winning_trades(&signaled_trades);
-  let losing_trades: i32 = losing_trades(&signaled_trades);
-  let percentage_winners: f32 = percentage_winners(winning_trades, num_of_trades);
+  let stats = pnl_stats(&pnls);
 
   // Update chromosome
-  updated_chromosome.cum_pnl = cum_pnl;
-  updated_chromosome.mean_return = mean_return;
-  updated_chromosome.variance = variance;
-  updated_chromosome.kelly = kelly;
-  updated_chromosome.num_of_trades = num_of_trades;
-  updated_chromosome.w_kelly = kelly * (num_of_trades as f32 / *total_trade_signals as f32);
-  updated_chromosome.losing_trades = losing_trades;
-  updated_chromosome.winning_trades = winning_trades;
-  updated_chromosome.percentage_winners = percentage_winners;
+  updated_chromosome.cum_pnl = stats.cum_pnl;
+  updated_chromosome.mean_return = stats.mean_return;
+  updated_chromosome.variance = stats.variance;
+  updated_chromosome.kelly = stats.kelly;
+  updated_chromosome.num_of_trades = stats.num_of_trades;
+  // Without days with signals there are no trades to weight kelly by
+  updated_chromosome.w_kelly = if days > 0 {
+    stats.kelly * (stats.num_of_trades as f32 / days as f32)
+  } else {
+    0.0
+  };
+  updated_chromosome.losing_trades = stats.losing_trades;
+  updated_chromosome.winning_trades = stats.winning_trades;
+  updated_chromosome.percentage_winners = stats.percentage_winners;
+  updated_chromosome.long = pnl_stats(&long_pnls);
+  updated_chromosome.short = pnl_stats(&short_pnls);
+  updated_chromosome.max_drawdown = max_drawdown;
+  let mut behaviour: Vec<(i64, i32)> = trades.iter().map(|trade| (trade.0 as i64, trade.1)).collect();
+  behaviour.sort();
+  updated_chromosome.behaviour = behaviour;
 
   // println!("xxx chromosome: {:?}", updated_chromosome);
   updated_chromosome
 }
 
+/// Calculates max drawdown
+///
+/// Max drawdown is the largest drop of cumulative pnl from its running peak,
+/// taken over the trade signals in time order.
+pub fn max_drawdown<'a, I: IntoIterator<Item = &'a TradeSignal>>(trade_signals: I) -> f32 {
+  let mut signals: Vec<&TradeSignal> = trade_signals.into_iter().collect();
+  signals.sort_by(|a, b| a.ts.partial_cmp(&b.ts).unwrap_or(std::cmp::Ordering::Equal));
+  pnl_drawdown(signals.iter().map(|s| s.pnl))
+}
+
+/// Calculates max drawdown of pnls in time order
+pub fn pnl_drawdown<I: IntoIterator<Item = f32>>(pnls: I) -> f32 {
+  let mut cum_pnl = 0.0;
+  let mut peak = 0.0;
+  let mut drawdown: f32 = 0.0;
+  for pnl in pnls {
+    cum_pnl += pnl;
+    if cum_pnl > peak {
+      peak = cum_pnl;
+    }
+    drawdown = drawdown.max(peak - cum_pnl);
+  }
+  drawdown
+}
+
+/// Calculates summary data for signaled trades
+pub fn leg_stats(signaled_trades: &Vec<TradeSignal>) -> LegStats {
+  pnl_stats(&pnls(signaled_trades))
+}
+
+/// Calculates summary data from the pnl of each trade
+pub fn pnl_stats(pnls: &[f32]) -> LegStats {
+  let mean_return = mean(pnls);
+  let variance = pnl_variance(pnls);
+  let num_of_trades = pnls.len() as i32;
+  let winning_trades = pnls.iter().filter(|pnl| **pnl > 0.0).count() as i32;
+  LegStats {
+    num_of_trades,
+    cum_pnl: pnls.iter().sum(),
+    mean_return,
+    variance,
+    kelly: calc::kelly(mean_return, variance),
+    winning_trades,
+    losing_trades: pnls.iter().filter(|pnl| **pnl < 0.0).count() as i32,
+    percentage_winners: percentage_winners(winning_trades, num_of_trades),
+  }
+}
+
+/// Pnl of each trade signal
+fn pnls(signaled_trades: &Vec<TradeSignal>) -> Vec<f32> {
+  signaled_trades.iter().map(|x| x.pnl).collect()
+}
+
+/// Mean of pnls, 0 without trades
+fn mean(pnls: &[f32]) -> f32 {
+  if pnls.is_empty() {
+    return 0.0;
+  }
+  let cum_pnl: f32 = pnls.iter().sum();
+  cum_pnl / pnls.len() as f32
+}
+
+/// Variance of pnls, 0 without trades
+fn pnl_variance(pnls: &[f32]) -> f32 {
+  if pnls.is_empty() {
+    return 0.0;
+  }
+  let mean = mean(pnls);
+  let sum_diffs: f32 = pnls.iter().map(|x| (x - mean).powi(2)).sum();
+  sum_diffs / pnls.len() as f32
+}
+
 /// Calculates winning trades
 pub fn winning_trades(signaled_trades: &Vec<TradeSignal>) -> i32 {
   let winning_trades: Vec<&TradeSignal> = signaled_trades
@@ -247,7 +392,7 @@ pub fn losing_trades(signaled_trades: &Vec<TradeSignal>) -> i32 {
 ///
 /// Percentage winners is calculated as winners over total trades
 pub fn percentage_winners(num_winners: i32, num_of_trades: i32) -> f32 {
-  if num_of_trades < 0 {
+  if num_of_trades <= 0 {
     return 0.0;
   }
   return num_winners as f32 / num_of_trades as f32;
@@ -255,8 +400,252 @@ pub fn percentage_winners(num_winners: i32, num_of_trades: i32) -> f32 {
 
 #[cfg(test)]
 mod tests {
+  use super::*;
+  use signals::GeneSignals;
+  use std::collections::HashMap;
+  use std::time::Instant;
+  use store::Quotes;
+  use timeline::Timeline;
+
+  // A chromosome of the first generation targeting SPY
+  fn chromosome() -> Chromosome {
+    forge::generate_chromosomes(
+      vec![vec![0, 0, 0, 0]],
+      1,
+      "SPY",
+      &vec!["AAPL".to_string()],
+      &forge::Grammar::default(),
+      &forge::Config::default(),
+      &mut forge::seeded_rng(1),
+    )
+    .remove(0)
+  }
+
+  fn trade_signal(strategies: Vec<&str>, signals: Vec<i32>) -> TradeSignal {
+    TradeSignal {
+      chromosome_id: Uuid::new_v4(),
+      ts: 1528745804.0,
+      strategies: strategies.iter().map(|s| s.to_string()).collect(),
+      signals,
+      target_ticker: "SPY".to_string(),
+      hard_signal: 0,
+      generation: 1,
+      ret: 0.0,
+      pnl: 0.0,
+    }
+  }
+
   #[test]
   fn it_works() {
     assert_eq!(2 + 2, 4);
   }
+
+  #[test]
+  fn test_hard_signal() {
+    let rule = Rule::parse("(hhv:AAPL:20 OR gapup:MSFT:3) AND NOT stdevf:SPY:30").unwrap();
+    let s = trade_signal(vec!["hhv:AAPL:20", "gapup:MSFT:3", "stdevf:SPY:30"], vec![0, 1, 0]);
+    assert_eq!(1, hard_signal(&rule, &s));
+    let s = trade_signal(vec!["hhv:AAPL:20", "gapup:MSFT:3", "stdevf:SPY:30"], vec![1, 1, 1]);
+    assert_eq!(0, hard_signal(&rule, &s));
+    let s = trade_signal(vec!["stdevf:SPY:30"], vec![0]);
+    assert_eq!(0, hard_signal(&rule, &s));
+  }
+
+  #[test]
+  fn test_hard_signal_legacy_chromosome() {
+    let rule = Rule::parse("llv:AAPL:2::gapup:GOOG:10").unwrap();
+    let s = trade_signal(vec!["llv:AAPL:2", "gapup:GOOG:10"], vec![1, 1]);
+    assert_eq!(1, hard_signal(&rule, &s));
+    let s = trade_signal(vec!["llv:AAPL:2"], vec![1]);
+    assert_eq!(0, hard_signal(&rule, &s));
+  }
+
+  #[test]
+  fn test_hard_signal_short() {
+    let strategies = vec!["hhv:AAPL:20", "gapup:MSFT:3"];
+    let rule = Rule::parse("SHORT (hhv:AAPL:20 AND gapup:MSFT:3)").unwrap();
+    assert_eq!(SHORT, hard_signal(&rule, &trade_signal(strategies.clone(), vec![1, 1])));
+    assert_eq!(FLAT, hard_signal(&rule, &trade_signal(strategies.clone(), vec![1, 0])));
+    // Genes can go short on their own
+    let rule = Rule::parse("hhv:AAPL:20 AND gapup:MSFT:3").unwrap();
+    assert_eq!(SHORT, hard_signal(&rule, &trade_signal(strategies.clone(), vec![-1, -1])));
+    assert_eq!(FLAT, hard_signal(&rule, &trade_signal(strategies.clone(), vec![-1, 1])));
+    let rule = Rule::parse("hhv:AAPL:20 OR gapup:MSFT:3").unwrap();
+    assert_eq!(SHORT, hard_signal(&rule, &trade_signal(strategies.clone(), vec![-1, 0])));
+    assert_eq!(FLAT, hard_signal(&rule, &trade_signal(strategies.clone(), vec![-1, 1])));
+    let rule = Rule::parse("NOT hhv:AAPL:20").unwrap();
+    assert_eq!(FLAT, hard_signal(&rule, &trade_signal(strategies.clone(), vec![-1, 0])));
+    assert_eq!(LONG, hard_signal(&rule, &trade_signal(strategies, vec![0, 0])));
+  }
+
+  #[test]
+  fn test_leg_stats() {
+    let mut signals = BTreeMap::new();
+    for (ts, (signal, ret)) in vec![(1, 0.02), (-1, -0.01), (-1, 0.03), (0, 0.05)].into_iter().enumerate() {
+      let mut s = trade_signal(vec!["hhv:AAPL:20"], vec![signal]);
+      s.ts = ts as f64;
+      s.ret = ret;
+      signals.insert(ts.to_string(), s);
+    }
+    let rule = Rule::parse("hhv:AAPL:20").unwrap();
+    let chromosome = chromosome();
+    let chromosome = Chromosome { chromosome: rule, ..chromosome };
+    calc_pnl(&mut signals, chromosome.clone());
+    let chromosome = update_chromosome(chromosome, signals);
+    assert_eq!(3, chromosome.num_of_trades);
+    assert_eq!(1, chromosome.long.num_of_trades);
+    assert_eq!(2, chromosome.short.num_of_trades);
+    assert!((chromosome.long.cum_pnl - 0.02).abs() < 1e-6);
+    assert!((chromosome.short.cum_pnl + 0.02).abs() < 1e-6);
+    assert_eq!(1, chromosome.short.winning_trades);
+    assert_eq!(1, chromosome.short.losing_trades);
+    assert_eq!(0.5, chromosome.short.percentage_winners);
+    // Pnl runs 0.02, 0.03, 0.0 then flat
+    assert!((chromosome.max_drawdown - 0.03).abs() < 1e-6);
+    assert_eq!(vec![(0, LONG), (1, SHORT), (2, SHORT)], chromosome.behaviour);
+  }
+
+  // Trade signals of a chromosome by timestamp, the way signals were kept
+  // before the bitsets. `generate_signals` should give the same signals
+  fn generate_trade_signals(chromosome: &Chromosome, store: &Store) -> Result<BTreeMap<String, TradeSignal>, Error> {
+    let mut trade_signals: BTreeMap<String, TradeSignal> = BTreeMap::new();
+    for strategy in strategies::expand_strategies(chromosome)? {
+      let quotes = match store.get(&strategy.ticker) {
+        Some(quotes) => quotes,
+        None => return Err(Error::MissingQuotes(strategy.ticker)),
+      };
+      let gene = GeneSignals::new(&strategy, quotes, &store.timeline)?;
+      for day in gene.days.ones() {
+        let ts = store.timeline.ts(day);
+        let signal = gene.signal(day).unwrap();
+        match trade_signals.get_mut(&ts.to_string()) {
+          Some(s) => {
+            s.strategies.push(strategy.strategy.clone());
+            s.signals.push(signal);
+          }
+          None => {
+            trade_signals.insert(ts.to_string(), init_trade_signal(&strategy, ts, &signal));
+          }
+        }
+      }
+    }
+    Ok(trade_signals)
+  }
+
+  // Logs how long each strategy takes to compute the signals of a gene on
+  // 5000 quotes. Run with `cargo test --release -- --ignored --nocapture`
+  #[test]
+  #[ignore]
+  fn time_gene_signals() {
+    let quotes = Quotes::new(&strategies::random_walk(5000));
+    let timeline = Timeline::new(vec![&quotes]);
+    let runs = 200;
+    let mut total = 0.0;
+    for strategy in strategies::REGISTRY {
+      let call = StrategyCall {
+        chromosome_id: Uuid::new_v4(),
+        strategy: format!("{}:AAPL:20", strategy.name()),
+        code: strategy.name().to_string(),
+        ticker: "AAPL".to_string(),
+        param: 20,
+        target_ticker: "SPY".to_string(),
+        generation: 1,
+      };
+      let started = Instant::now();
+      for _ in 0..runs {
+        GeneSignals::new(&call, &quotes, &timeline).unwrap();
+      }
+      let micros = started.elapsed().as_secs_f64() * 1e6 / runs as f64;
+      total += micros;
+      println!("{:>12} {:>8.1}us", strategy.name(), micros);
+    }
+    println!("{:>12} {:>8.1}us", "mean", total / strategies::REGISTRY.len() as f64);
+  }
+
+  #[test]
+  fn test_bitset_signals_match_trade_signals() {
+    let chromosome = chromosome();
+    let aapl = strategies::random_walk(300);
+    // MSFT starts later and skips days so its quotes don't line up with AAPL
+    let msft: Vec<Quote> = strategies::random_walk(300)
+      .into_iter()
+      .skip(40)
+      .enumerate()
+      .filter(|(i, _)| i % 7 != 3)
+      .map(|(_, q)| Quote { ticker: "MSFT".to_string(), close: q.open, open: q.close, ..q })
+      .collect();
+    let mut returns = BTreeMap::new();
+    for (i, quote) in aapl.iter().enumerate().filter(|(i, _)| i % 5 != 0) {
+      let ret = ((i * 37) % 11) as f32 / 100.0 - 0.05;
+      returns.insert(quote.ts.to_string(), Return { ts: quote.ts, ret });
+    }
+    let mut quotes_repo = HashMap::new();
+    quotes_repo.insert("AAPL".to_string(), aapl);
+    quotes_repo.insert("MSFT".to_string(), msft);
+    let store = Store::new(&quotes_repo, &returns);
+    let cache = SignalCache::new(10);
+    type Row = (f64, Vec<String>, Vec<i32>, i32, f32, f32);
+    let rows = |signals: Vec<TradeSignal>| -> Vec<Row> {
+      signals
+        .into_iter()
+        .map(|s| (s.ts, s.strategies, s.signals, s.hard_signal, s.ret, s.pnl))
+        .collect()
+    };
+    let rules = vec![
+      "hhv:AAPL:5 AND gapup:MSFT:2",
+      "(conupdays:AAPL:2 OR SHORT condowndays:MSFT:2) AND NOT abovema:AAPL:10",
+      "SHORT (stdeva:MSFT:10 OR stdevf:AAPL:5) OR belowma:MSFT:20 OR hhv:AAPL:5",
+      "NOT gapdown:AAPL:1 AND NOT gapdown:AAPL:1",
+      "llv:AAPL:5",
+      "trend:MSFT:10 OR trend:AAPL:5",
+    ];
+    for rule in rules {
+      let chromosome = Chromosome { chromosome: Rule::parse(rule).unwrap(), ..chromosome.clone() };
+      let mut expected = generate_trade_signals(&chromosome, &store).unwrap();
+      merge_returns(&mut expected, &returns);
+      calc_pnl(&mut expected, chromosome.clone());
+      let signals = generate_signals(&chromosome, &store, &cache).unwrap();
+      assert_eq!(
+        rows(expected.values().cloned().collect()),
+        rows(signals.rows(&store).collect()),
+        "{}",
+        rule
+      );
+      assert_eq!(
+        format!("{:?}", update_chromosome(chromosome.clone(), expected)),
+        format!("{:?}", signals.update_chromosome(chromosome.clone(), &store)),
+        "{}",
+        rule
+      );
+    }
+    assert_eq!((2, 12), (cache.stats().hits, cache.stats().misses));
+    let chromosome = Chromosome { chromosome: Rule::parse("hhv:GOOG:5").unwrap(), ..chromosome };
+    assert_eq!(
+      Err(Error::MissingQuotes("GOOG".to_string())),
+      generate_signals(&chromosome, &store, &cache).map(|_| ())
+    );
+  }
+
+  #[test]
+  fn test_summarise_without_days() {
+    let chromosome = chromosome();
+    let chromosome = summarise(chromosome, &[], 0);
+    assert_eq!(0, chromosome.num_of_trades);
+    assert_eq!(0.0, chromosome.w_kelly);
+  }
+
+  #[test]
+  fn test_max_drawdown() {
+    let signals: Vec<TradeSignal> = vec![(3, -0.04), (1, 0.05), (2, 0.02), (4, 0.01), (5, -0.03)]
+      .into_iter()
+      .map(|(ts, pnl)| TradeSignal {
+        ts: ts as f64,
+        pnl,
+        ..trade_signal(vec![], vec![])
+      })
+      .collect();
+    // Peaks at 0.07 on day 2 and falls to 0.01 by day 5
+    assert!((max_drawdown(&signals) - 0.06).abs() < 1e-6);
+    assert_eq!(0.0, max_drawdown(&vec![]));
+  }
 }
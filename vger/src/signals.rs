@@ -0,0 +1,261 @@
+//! Signals of chromosomes as bitsets on the timeline
+//!
+//! A gene's signals are three bitsets over the days of the timeline: the days
+//! it has a signal on, i.e. once its window is full, and the days that signal
+//! is long or short. A rule combines the bitsets of its genes with bitwise
+//! operations into the days the chromosome is long and short, and its pnl is
+//! the returns masked by those days.
+//!
+//! The days are evaluated like `hard_signal` evaluates a `TradeSignal`:
+//!
+//! ```text
+//! gene  long and short days of the gene
+//! NOT   long on the days with signals where the rule is flat
+//! SHORT swaps long and short
+//! AND   long where every rule is long, short where every rule is short
+//! OR    long where a rule is long and none is short, and the other way round
+//! ```
+//!
+//! The `INVALID` rule is flat on every day.
+use bitset::Bitset;
+use forge::{Chromosome, Rule};
+use std::sync::Arc;
+use store::{Quotes, Store};
+use strategies;
+use strategies::StrategyCall;
+use timeline::Timeline;
+use uuid::Uuid;
+use {summarise, Error, TradeSignal, FLAT, LONG, SHORT};
+
+/// Signals of a gene on the timeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneSignals {
+    /// Days the gene has a signal on
+    pub days: Bitset,
+    pub long: Bitset,
+    pub short: Bitset,
+}
+
+impl GeneSignals {
+    /// Computes the signals of a gene from the quotes of its ticker
+    pub fn new(
+        strategy: &StrategyCall,
+        quotes: &Quotes,
+        timeline: &Timeline,
+    ) -> Result<GeneSignals, Error> {
+        let mut signals = GeneSignals {
+            days: Bitset::new(timeline.len()),
+            long: Bitset::new(timeline.len()),
+            short: Bitset::new(timeline.len()),
+        };
+        match strategies::find(&strategy.code) {
+            Some(s) => s.call(strategy, quotes, &timeline.days(quotes), &mut signals),
+            None => return Err(Error::UnknownStrategy(strategy.code.clone())),
+        };
+        Ok(signals)
+    }
+
+    /// Sets the signal of a day, going long or short by its sign
+    pub fn insert(&mut self, day: usize, signal: i32) {
+        self.days.insert(day);
+        match signal.signum() {
+            LONG => self.long.insert(day),
+            SHORT => self.short.insert(day),
+            _ => (),
+        }
+    }
+
+    /// Signal on a day, if the gene has one
+    pub fn signal(&self, day: usize) -> Option<i32> {
+        if !self.days.contains(day) {
+            return None;
+        }
+        Some(side(&self.long, &self.short, day))
+    }
+
+    /// Bytes taken by the bitsets
+    pub fn size(&self) -> usize {
+        self.days.size() + self.long.size() + self.short.size()
+    }
+}
+
+/// Days a rule is long and short on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sides {
+    pub long: Bitset,
+    pub short: Bitset,
+}
+
+impl Sides {
+    fn flat(len: usize) -> Sides {
+        Sides {
+            long: Bitset::new(len),
+            short: Bitset::new(len),
+        }
+    }
+
+    /// `LONG`, `SHORT` or `FLAT` on a day
+    pub fn side(&self, day: usize) -> i32 {
+        side(&self.long, &self.short, day)
+    }
+
+    /// Days in the market
+    pub fn trades(&self) -> Bitset {
+        self.long.or(&self.short)
+    }
+}
+
+/// Signals of a chromosome
+pub struct Signals {
+    pub chromosome_id: Uuid,
+    pub target_ticker: String,
+    pub generation: i32,
+    /// Genes in the order of the rule with their signals
+    pub genes: Vec<(String, Arc<GeneSignals>)>,
+    /// Days at least one gene has a signal on
+    pub days: Bitset,
+    pub sides: Sides,
+}
+
+impl Signals {
+    /// Combines the signals of the genes of a chromosome
+    pub fn new(
+        chromosome: &Chromosome,
+        genes: Vec<(String, Arc<GeneSignals>)>,
+        timeline: &Timeline,
+    ) -> Signals {
+        let days = genes
+            .iter()
+            .fold(Bitset::new(timeline.len()), |days, gene| {
+                days.or(&gene.1.days)
+            });
+        let sides = evaluate(&chromosome.chromosome, &genes, &days);
+        Signals {
+            chromosome_id: chromosome.id,
+            target_ticker: chromosome.target_ticker.clone(),
+            generation: chromosome.generation,
+            genes,
+            days,
+            sides,
+        }
+    }
+
+    /// Day, hard signal and pnl of each trade in time order
+    ///
+    /// The pnl is the return masked by the days in the market and signed by
+    /// the side.
+    pub fn trades(&self, returns: &[f32]) -> Vec<(usize, i32, f32)> {
+        self.sides
+            .trades()
+            .ones()
+            .map(|day| {
+                let side = self.sides.side(day);
+                (day, side, returns[day] * side as f32)
+            })
+            .collect()
+    }
+
+    /// Updates chromosome with the summary of its trades
+    pub fn update_chromosome(&self, chromosome: Chromosome, store: &Store) -> Chromosome {
+        let trades: Vec<(f64, i32, f32)> = self
+            .trades(&store.returns)
+            .into_iter()
+            .map(|(day, side, pnl)| (store.timeline.ts(day), side, pnl))
+            .collect();
+        summarise(chromosome, &trades, self.days.count())
+    }
+
+    /// Trade signal of each day with signals in time order, i.e. to write
+    /// them out
+    pub fn rows<'a>(&'a self, store: &'a Store) -> impl Iterator<Item = TradeSignal> + 'a {
+        self.days.ones().map(move |day| {
+            let genes: Vec<&(String, Arc<GeneSignals>)> = self
+                .genes
+                .iter()
+                .filter(|gene| gene.1.days.contains(day))
+                .collect();
+            let hard_signal = self.sides.side(day);
+            TradeSignal {
+                chromosome_id: self.chromosome_id,
+                ts: store.timeline.ts(day),
+                strategies: genes.iter().map(|gene| gene.0.clone()).collect(),
+                signals: genes.iter().filter_map(|gene| gene.1.signal(day)).collect(),
+                target_ticker: self.target_ticker.clone(),
+                hard_signal,
+                generation: self.generation,
+                ret: store.returns[day],
+                pnl: store.returns[day] * hard_signal as f32,
+            }
+        })
+    }
+
+    /// Bytes taken by the bitsets of the chromosome, not counting its genes
+    /// which are shared
+    pub fn size(&self) -> usize {
+        self.days.size() + self.sides.long.size() + self.sides.short.size()
+    }
+}
+
+/// Evaluates a rule against the signals of its genes
+///
+/// `days` are the days with signals, which `NOT` is long on when the rule is
+/// flat.
+pub fn evaluate(rule: &Rule, genes: &[(String, Arc<GeneSignals>)], days: &Bitset) -> Sides {
+    match rule {
+        Rule::Gene(gene) => {
+            let name = gene.to_string();
+            match genes.iter().find(|g| g.0 == name) {
+                Some(g) => Sides {
+                    long: g.1.long.clone(),
+                    short: g.1.short.clone(),
+                },
+                None => Sides::flat(days.len()),
+            }
+        }
+        Rule::Not(r) => Sides {
+            long: days.and_not(&evaluate(r, genes, days).trades()),
+            short: Bitset::new(days.len()),
+        },
+        Rule::Short(r) => {
+            let sides = evaluate(r, genes, days);
+            Sides {
+                long: sides.short,
+                short: sides.long,
+            }
+        }
+        Rule::And(rules) => {
+            let mut sides = rules.iter().map(|r| evaluate(r, genes, days));
+            match sides.next() {
+                Some(first) => sides.fold(first, |acc, s| Sides {
+                    long: acc.long.and(&s.long),
+                    short: acc.short.and(&s.short),
+                }),
+                None => Sides::flat(days.len()),
+            }
+        }
+        Rule::Or(rules) => {
+            let any = rules.iter().map(|r| evaluate(r, genes, days)).fold(
+                Sides::flat(days.len()),
+                |acc, s| Sides {
+                    long: acc.long.or(&s.long),
+                    short: acc.short.or(&s.short),
+                },
+            );
+            Sides {
+                long: any.long.and_not(&any.short),
+                short: any.short.and_not(&any.long),
+            }
+        }
+        Rule::Invalid => Sides::flat(days.len()),
+    }
+}
+
+fn side(long: &Bitset, short: &Bitset, day: usize) -> i32 {
+    if long.contains(day) {
+        LONG
+    } else if short.contains(day) {
+        SHORT
+    } else {
+        FLAT
+    }
+}
@@ -0,0 +1,168 @@
+//! Quotes and returns shared by every chromosome
+//!
+//! The store is built once per run and shared by the worker threads behind
+//! an `Arc`, so evaluating a chromosome doesn't copy any quotes. Quotes are
+//! kept by column, which is how strategies read them: a rolling max of highs
+//! is a pass over one slice.
+use repo::schemas::Quote;
+use repo::schemas::Return;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::mem;
+use timeline::Timeline;
+
+/// Quotes of a ticker by column, in time order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quotes {
+    pub ts: Vec<f64>,
+    pub open: Vec<f32>,
+    pub high: Vec<f32>,
+    pub low: Vec<f32>,
+    pub close: Vec<f32>,
+    pub volume: Vec<f32>,
+}
+
+impl Quotes {
+    pub fn new(quotes: &[Quote]) -> Quotes {
+        Quotes {
+            ts: quotes.iter().map(|q| q.ts).collect(),
+            open: quotes.iter().map(|q| q.open).collect(),
+            high: quotes.iter().map(|q| q.high).collect(),
+            low: quotes.iter().map(|q| q.low).collect(),
+            close: quotes.iter().map(|q| q.close).collect(),
+            volume: quotes.iter().map(|q| q.volume).collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ts.is_empty()
+    }
+
+    /// Bytes taken by the columns
+    pub fn size(&self) -> usize {
+        self.len() * (mem::size_of::<f64>() + 5 * mem::size_of::<f32>())
+    }
+}
+
+/// Quotes of every ticker and the target returns on their timeline
+pub struct Store {
+    pub quotes: HashMap<String, Quotes>,
+    pub timeline: Timeline,
+    /// Target returns by day of the timeline
+    pub returns: Vec<f32>,
+}
+
+impl Store {
+    pub fn new(quotes: &HashMap<String, Vec<Quote>>, returns: &BTreeMap<String, Return>) -> Store {
+        let quotes: HashMap<String, Quotes> = quotes
+            .iter()
+            .map(|(ticker, quotes)| (ticker.clone(), Quotes::new(quotes)))
+            .collect();
+        let timeline = Timeline::new(quotes.values());
+        let returns = timeline.returns(returns);
+        Store {
+            quotes,
+            timeline,
+            returns,
+        }
+    }
+
+    /// Quotes of a ticker
+    pub fn get(&self, ticker: &str) -> Option<&Quotes> {
+        self.quotes.get(ticker)
+    }
+
+    /// Number of quotes of every ticker
+    pub fn num_of_quotes(&self) -> usize {
+        self.quotes.values().map(|q| q.len()).sum()
+    }
+
+    /// Bytes taken by the quotes, timeline and returns, leaving out the
+    /// overhead of the maps
+    pub fn size(&self) -> usize {
+        let quotes: usize = self.quotes.iter().map(|(ticker, q)| ticker.len() + q.size()).sum();
+        let timeline = self.timeline.len() * (mem::size_of::<f64>() + mem::size_of::<(u64, usize)>());
+        quotes + timeline + self.returns.len() * mem::size_of::<f32>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Instant;
+    use strategies;
+
+    #[test]
+    fn test_store() {
+        let aapl = strategies::random_walk(10);
+        let mut quotes = HashMap::new();
+        quotes.insert("AAPL".to_string(), aapl.clone());
+        quotes.insert("MSFT".to_string(), aapl[5..].to_vec());
+        let mut returns = BTreeMap::new();
+        returns.insert(aapl[7].ts.to_string(), Return { ts: aapl[7].ts, ret: 0.5 });
+        let store = Store::new(&quotes, &returns);
+        let columns = store.get("MSFT").unwrap();
+        assert_eq!(5, columns.len());
+        assert_eq!(aapl[6].close, columns.close[1]);
+        assert_eq!(aapl[9].ts, columns.ts[4]);
+        assert_eq!(10, store.timeline.len());
+        assert_eq!(15, store.num_of_quotes());
+        assert_eq!(0.5, store.returns[7]);
+        assert_eq!(10.0, store.returns.iter().sum::<f32>() * 20.0);
+        assert_eq!(15 * 28 + 8 + 10 * 24 + 10 * 4, store.size());
+    }
+
+    // Logs what each chromosome cost when its thread got a copy of the
+    // quotes and returns against sharing one store, on 100 tickers of 5000
+    // quotes. Run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn time_store_against_copies() {
+        let walk = strategies::random_walk(5000);
+        let quotes: HashMap<String, Vec<Quote>> = (0..100)
+            .map(|i| {
+                let ticker = format!("T{}", i);
+                let quotes = walk.iter().map(|q| Quote { ticker: ticker.clone(), ..q.clone() }).collect();
+                (ticker, quotes)
+            })
+            .collect();
+        let returns: BTreeMap<String, Return> = walk
+            .iter()
+            .map(|q| (q.ts.to_string(), Return { ts: q.ts, ret: 0.01 }))
+            .collect();
+        let runs = 20;
+        let started = Instant::now();
+        for _ in 0..runs {
+            let copies = (quotes.clone(), returns.clone());
+            assert_eq!(100, copies.0.len());
+        }
+        let copy_ms = started.elapsed().as_secs_f64() * 1e3 / runs as f64;
+        let quote_bytes: usize = quotes
+            .iter()
+            .map(|(ticker, quotes)| {
+                ticker.len() + quotes.iter().map(|q| mem::size_of_val(q) + q.ticker.len()).sum::<usize>()
+            })
+            .sum();
+        let return_bytes: usize = returns.keys().map(|ts| ts.len() + mem::size_of::<(String, Return)>()).sum();
+        let store = Arc::new(Store::new(&quotes, &returns));
+        let runs = 1_000_000;
+        let started = Instant::now();
+        for _ in 0..runs {
+            let shared = Arc::clone(&store);
+            assert_eq!(5000, shared.timeline.len());
+        }
+        let share_ns = started.elapsed().as_secs_f64() * 1e9 / runs as f64;
+        println!(
+            "copies: {:.1} MB in {:.1} ms a chromosome, store: {:.1} MB once, {:.1} ns a chromosome",
+            (quote_bytes + return_bytes) as f64 / 1e6,
+            copy_ms,
+            store.size() as f64 / 1e6,
+            share_ns
+        );
+    }
+}
@@ -1,25 +1,45 @@
+use forge::grammar::Domain;
+#[cfg(test)]
 use repo::schemas::Quote;
-use std::collections::BTreeMap;
+use signals::GeneSignals;
+use store::Quotes;
 use strategies;
-use strategies::Strategy;
-use TradeSignal;
+use strategies::{Strategy, StrategyCall};
 use calc;
 
+/// Closes below the moving average of the last `param` closes
+pub struct BelowMa;
+
+impl Strategy for BelowMa {
+    fn name(&self) -> &'static str {
+        "belowma"
+    }
+
+    fn domain(&self) -> Domain {
+        strategies::set(&[5, 10, 20, 50, 100, 200])
+    }
+
+    fn call(&self, strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+        call(strategy, quotes, days, signals)
+    }
+}
+
 /// Below Moving Average
 /// 
 /// 
-pub fn call(
-    strategy: Strategy,
-    trade_signals: &mut BTreeMap<String, TradeSignal>,
-    quotes: &Vec<Quote>,
-) {
-    let windows = strategies::make_window(quotes, strategy.param as usize);
-    for w in windows {
-        let signal = below_ma(&w);
-        strategies::insert_signal(trade_signals, &w, &strategy, &signal);
-    }
+pub fn call(strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+    let lookback = strategy.param as usize;
+    let mas = calc::rolling_mean(&quotes.close, lookback);
+    strategies::insert_signals(signals, days, lookback, |i| {
+        if quotes.close[i] < mas[i - lookback] {
+            1
+        } else {
+            0
+        }
+    });
 }
 
+#[cfg(test)]
 fn below_ma(window: &strategies::Window) -> i32 {
     let closes: Vec<f32> = window.window.iter().map(|quote| quote.close).collect();
     let ma = calc::average(closes);
@@ -97,3 +117,8 @@ fn test_below_ma() {
     let signal = below_ma(&second_window);
     assert_eq!(1, signal);
 }
+
+#[test]
+fn test_below_ma_matches_windows() {
+    strategies::assert_same_signals(&BelowMa, &|w, _| below_ma(w));
+}
@@ -1,25 +1,50 @@
+use calc;
+use forge::grammar::Domain;
+#[cfg(test)]
 use repo::schemas::Quote;
-use std::collections::BTreeMap;
+use signals::GeneSignals;
+use store::Quotes;
 use strategies;
-use strategies::Strategy;
-use TradeSignal;
+use strategies::{Strategy, StrategyCall};
+#[cfg(test)]
 use Window;
 
+/// `param` closes in a row lower than the close before
+pub struct ConDownDays;
+
+impl Strategy for ConDownDays {
+    fn name(&self) -> &'static str {
+        "condowndays"
+    }
+
+    fn domain(&self) -> Domain {
+        Domain::Int(1, 10)
+    }
+
+    fn call(&self, strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+        call(strategy, quotes, days, signals)
+    }
+}
+
 /// Consecutive down days
 /// 
 /// 
-pub fn call(
-    strategy: Strategy,
-    trade_signals: &mut BTreeMap<String, TradeSignal>,
-    quotes: &Vec<Quote>,
-) {
-    let windows = strategies::make_window(quotes, strategy.param as usize);
-    for w in windows {
-        let signal = con_down_days(&w, strategy.param);
-        strategies::insert_signal(trade_signals, &w, &strategy, &signal);
-    }
+pub fn call(strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+    let lookback = strategy.param as usize;
+    let down_days: Vec<bool> = (0..quotes.len())
+        .map(|i| i > 0 && quotes.close[i] < quotes.close[i - 1])
+        .collect();
+    let runs = calc::run_lengths(&down_days);
+    strategies::insert_signals(signals, days, lookback, |i| {
+        if runs[i] >= lookback {
+            1
+        } else {
+            0
+        }
+    });
 }
 
+#[cfg(test)]
 fn con_down_days(window: &Window, param: i32) -> i32 {
     let mut up_days: Vec<i32> = vec![];
     let quotes = window.flatten();
@@ -108,3 +133,8 @@ fn test_condowndays() {
     let signal = con_down_days(&second_window, 3);
     assert_eq!(1, signal);
 }
+
+#[test]
+fn test_condowndays_matches_windows() {
+    strategies::assert_same_signals(&ConDownDays, &&con_down_days);
+}
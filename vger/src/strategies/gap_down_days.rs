@@ -1,22 +1,47 @@
+use calc;
+use forge::grammar::Domain;
+#[cfg(test)]
 use repo::schemas::Quote;
-use std::collections::BTreeMap;
+use signals::GeneSignals;
+use store::Quotes;
 use strategies;
-use strategies::Strategy;
-use TradeSignal;
+use strategies::{Strategy, StrategyCall};
+#[cfg(test)]
 use Window;
 
-pub fn call(
-    strategy: Strategy,
-    trade_signals: &mut BTreeMap<String, TradeSignal>,
-    quotes: &Vec<Quote>,
-) {
-    let windows = strategies::make_window(quotes, strategy.param as usize);
-    for w in windows {
-        let signal = gap_down_days(&w, strategy.param);
-        strategies::insert_signal(trade_signals, &w, &strategy, &signal);
+/// `param` opens in a row below the close before
+pub struct GapDownDays;
+
+impl Strategy for GapDownDays {
+    fn name(&self) -> &'static str {
+        "gapdown"
+    }
+
+    fn domain(&self) -> Domain {
+        Domain::Int(1, 10)
     }
+
+    fn call(&self, strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+        call(strategy, quotes, days, signals)
+    }
+}
+
+pub fn call(strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+    let lookback = strategy.param as usize;
+    let gap_downs: Vec<bool> = (0..quotes.len())
+        .map(|i| i > 0 && quotes.open[i] < quotes.close[i - 1])
+        .collect();
+    let runs = calc::run_lengths(&gap_downs);
+    strategies::insert_signals(signals, days, lookback, |i| {
+        if runs[i] >= lookback {
+            1
+        } else {
+            0
+        }
+    });
 }
 
+#[cfg(test)]
 fn gap_down_days(window: &Window, param: i32) -> i32 {
     let mut gap_down_days: Vec<i32> = vec![];
     let quotes = window.flatten();
@@ -105,3 +130,8 @@ fn test_gapdowndays() {
     let signal = gap_down_days(&second_window, 3);
     assert_eq!(1, signal);
 }
+
+#[test]
+fn test_gapdowndays_matches_windows() {
+    strategies::assert_same_signals(&GapDownDays, &&gap_down_days);
+}
@@ -1,28 +1,53 @@
+use calc;
+use forge::grammar::Domain;
+#[cfg(test)]
 use repo::schemas::Quote;
-use std::collections::BTreeMap;
+use signals::GeneSignals;
+use store::Quotes;
 use strategies;
-use strategies::Strategy;
-use TradeSignal;
+use strategies::{Strategy, StrategyCall};
+#[cfg(test)]
 use Window;
 
+/// `param` opens in a row above the close before
+pub struct GapUpDays;
+
+impl Strategy for GapUpDays {
+    fn name(&self) -> &'static str {
+        "gapup"
+    }
+
+    fn domain(&self) -> Domain {
+        Domain::Int(1, 10)
+    }
+
+    fn call(&self, strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+        call(strategy, quotes, days, signals)
+    }
+}
+
 /// Gap up days
 /// 
 /// Calculates gap up days
 /// A gap up is identified when the Open is higher than the previous close
 /// The signal returns 1 when the number of consecutive up days equals param
 /// 
-pub fn call(
-    strategy: Strategy,
-    trade_signals: &mut BTreeMap<String, TradeSignal>,
-    quotes: &Vec<Quote>,
-) {
-    let windows = strategies::make_window(quotes, strategy.param as usize);
-    for w in windows {
-        let signal = gap_up_days(&w, strategy.param);
-        strategies::insert_signal(trade_signals, &w, &strategy, &signal);
-    }
+pub fn call(strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+    let lookback = strategy.param as usize;
+    let gap_ups: Vec<bool> = (0..quotes.len())
+        .map(|i| i > 0 && quotes.open[i] > quotes.close[i - 1])
+        .collect();
+    let runs = calc::run_lengths(&gap_ups);
+    strategies::insert_signals(signals, days, lookback, |i| {
+        if runs[i] >= lookback {
+            1
+        } else {
+            0
+        }
+    });
 }
 
+#[cfg(test)]
 fn gap_up_days(window: &Window, param: i32) -> i32 {
     let mut gap_up_days: Vec<i32> = vec![];
     let quotes = window.flatten();
@@ -111,3 +136,8 @@ fn test_gapupdays() {
     let signal = gap_up_days(&second_window, 3);
     assert_eq!(1, signal);
 }
+
+#[test]
+fn test_gapupdays_matches_windows() {
+    strategies::assert_same_signals(&GapUpDays, &&gap_up_days);
+}
@@ -1,27 +1,51 @@
+use calc;
+use forge::grammar::Domain;
+#[cfg(test)]
 use repo::schemas::Quote;
-use std::collections::BTreeMap;
+use signals::GeneSignals;
+use store::Quotes;
+#[cfg(test)]
 use strategies;
-use strategies::insert_signal;
-use strategies::Strategy;
-use TradeSignal;
+use strategies::insert_signals;
+use strategies::{Strategy, StrategyCall};
+#[cfg(test)]
 use Window;
 
-pub fn call(
-    strategy: Strategy,
-    trade_signals: &mut BTreeMap<String, TradeSignal>,
-    quotes: &Vec<Quote>,
-) {
-    let windows = strategies::make_window(quotes, strategy.param as usize);
-    for w in windows {
-        let signal = highest_high_value(&w);
-        insert_signal(trade_signals, &w, &strategy, &signal);
+/// Closes above the highest high of the last `param` periods
+pub struct HighestHighValue;
+
+impl Strategy for HighestHighValue {
+    fn name(&self) -> &'static str {
+        "hhv"
+    }
+
+    fn domain(&self) -> Domain {
+        Domain::Int(5, 500)
+    }
+
+    fn call(&self, strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+        call(strategy, quotes, days, signals)
     }
 }
 
+pub fn call(strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+    let lookback = strategy.param as usize;
+    let highest = calc::rolling_max(&quotes.high, lookback);
+    insert_signals(signals, days, lookback, |i| {
+        // Like in the windowed version the highest high is at least 0
+        if quotes.close[i] > highest[i - lookback].max(0.0) {
+            1
+        } else {
+            0
+        }
+    });
+}
+
 /// Calculate highest high in window
 ///
 /// Triggers a signal is close exceeds highest high in window period
 ///
+#[cfg(test)]
 fn highest_high_value(window: &Window) -> i32 {
     let high_values: Vec<f32> = window.window.iter().map(|quote| quote.high).collect();
     let highest_value = high_values.iter().fold(0_f32, |acc, x| acc.max(*x));
@@ -94,3 +118,8 @@ fn test_hhv() {
     let signal = highest_high_value(&first_window);
     assert_eq!(1, signal);
 }
+
+#[test]
+fn test_hhv_matches_windows() {
+    strategies::assert_same_signals(&HighestHighValue, &|w, _| highest_high_value(w));
+}
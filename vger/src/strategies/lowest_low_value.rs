@@ -1,25 +1,51 @@
+use calc;
+use forge::grammar::Domain;
+#[cfg(test)]
 use repo::schemas::Quote;
-use std::collections::BTreeMap;
-use strategies::insert_signal;
+use signals::GeneSignals;
+use store::Quotes;
+#[cfg(test)]
+use strategies;
+use strategies::insert_signals;
+#[cfg(test)]
 use strategies::make_window;
-use strategies::Strategy;
-use TradeSignal;
+use strategies::{Strategy, StrategyCall};
+#[cfg(test)]
 use Window;
 
-/// Lowest low value
-pub fn call(
-    strategy: Strategy,
-    trade_signals: &mut BTreeMap<String, TradeSignal>,
-    quotes: &Vec<Quote>,
-) {
-    let windows = make_window(quotes, strategy.param as usize);
-    for w in windows {
-        let signal = lowest_low_value(&w);
-        insert_signal(trade_signals, &w, &strategy, &signal);
+/// Closes below the lowest low of the last `param` periods
+pub struct LowestLowValue;
+
+impl Strategy for LowestLowValue {
+    fn name(&self) -> &'static str {
+        "llv"
+    }
+
+    fn domain(&self) -> Domain {
+        Domain::Int(5, 500)
+    }
+
+    fn call(&self, strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+        call(strategy, quotes, days, signals)
     }
 }
 
+/// Lowest low value
+pub fn call(strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+    let lookback = strategy.param as usize;
+    let lowest = calc::rolling_min(&quotes.low, lookback);
+    insert_signals(signals, days, lookback, |i| {
+        // Like in the windowed version the lowest low is at most 0
+        if quotes.close[i] < lowest[i - lookback].min(0.0) {
+            1
+        } else {
+            0
+        }
+    });
+}
+
 // Calculate lowest low in window
+#[cfg(test)]
 fn lowest_low_value(window: &Window) -> i32 {
     let low_values: Vec<f32> = window.window.iter().map(|quote| quote.low).collect();
     let lowest_value = low_values.iter().fold(0_f32, |acc, x| acc.min(*x));
@@ -92,3 +118,8 @@ fn test_llv() {
     let signal = lowest_low_value(&first_window);
     assert_eq!(0, signal);
 }
+
+#[test]
+fn test_llv_matches_windows() {
+    strategies::assert_same_signals(&LowestLowValue, &|w, _| lowest_low_value(w));
+}
@@ -1,12 +1,23 @@
 //! Trading strategies
 //!
-//! Strategies take the following argument
+//! Each strategy lives in its own module and implements `Strategy`, which
+//! names it, declares the domain of its parameter and generates its signals.
+//! Strategies are listed once in `REGISTRY`. Genes are dispatched to their
+//! strategy by name and the strategy productions of the grammar are generated
+//! from the registry, so adding a strategy only takes its module and an entry
+//! in the registry.
+use forge::grammar::{self, Domain, GrammarError, Production, Symbol};
 use forge::Chromosome;
-use init_trade_signal;
+use forge::Gene;
+use forge::{Grammar, Param};
 use repo::schemas::Quote;
-use std::collections::BTreeMap;
+use signals::GeneSignals;
+use store::Quotes;
+#[cfg(test)]
+use timeline::Timeline;
 use uuid::Uuid;
-use TradeSignal;
+use Error;
+#[cfg(test)]
 use Window;
 
 pub mod above_ma;
@@ -21,16 +32,98 @@ pub mod stddev_a;
 pub mod stddev_b;
 pub mod stddev_d;
 pub mod stddev_f;
+pub mod trend;
 /// consecutive up volume
 /// consecutive down volume
 /// cross above ma
 /// cross below ma
 
-/// A chromosome expands into a `Strategy` struct
+/// A trading strategy genes can use
+pub trait Strategy: Sync {
+    /// Code of the strategy in genes, i.e. `llv`
+    fn name(&self) -> &'static str;
+
+    /// Values the parameter of the strategy can take
+    fn domain(&self) -> Domain;
+
+    /// Generates the signals of a gene of the strategy from the quotes of its
+    /// ticker
+    ///
+    /// `days` are the days of the quotes on the timeline. The signals are
+    /// written straight into the bitsets of the gene.
+    fn call(&self, strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals);
+}
+
+/// Every strategy, in the order of their productions in the grammar
+pub static REGISTRY: &[&dyn Strategy] = &[
+    &highest_high_value::HighestHighValue,
+    &lowest_low_value::LowestLowValue,
+    &con_up_days::ConUpDays,
+    &con_down_days::ConDownDays,
+    &gap_up_days::GapUpDays,
+    &gap_down_days::GapDownDays,
+    &stddev_a::StdDevA,
+    &stddev_b::StdDevB,
+    &stddev_d::StdDevD,
+    &stddev_f::StdDevF,
+    &above_ma::AboveMa,
+    &below_ma::BelowMa,
+    &trend::Trend,
+];
+
+/// Looks up a strategy in the registry by its code
+pub fn find(name: &str) -> Option<&'static dyn Strategy> {
+    REGISTRY.iter().find(|s| s.name() == name).cloned()
+}
+
+/// Productions of the registered strategies, i.e. `llv:<ticker>:<int:5:500>`
+pub fn productions() -> Vec<Production> {
+    REGISTRY
+        .iter()
+        .map(|s| {
+            vec![
+                Symbol::Terminal(format!("{}:", s.name())),
+                Symbol::NonTerminal("ticker".to_string()),
+                Symbol::Terminal(":".to_string()),
+                Symbol::Domain(s.domain()),
+            ]
+        })
+        .collect()
+}
+
+/// Replaces the strategy productions of a grammar with the registered
+/// strategies
+pub fn define_strategies(grammar: &mut Grammar) -> Result<(), GrammarError> {
+    grammar.define(grammar::STRATEGIES, productions())
+}
+
+/// Checks that every strategy production of a grammar starts with the code
+/// of a registered strategy, i.e. `hhv:<ticker>:<int:5:500>`
+pub fn check_strategies(grammar: &Grammar) -> Result<(), GrammarError> {
+    for production in grammar.productions(grammar::STRATEGIES).unwrap_or(&[]) {
+        let rendered = grammar::render(production);
+        let code = match production.first() {
+            Some(Symbol::Terminal(t)) => t.split(':').next().unwrap_or(""),
+            _ => "",
+        };
+        if find(code).is_none() {
+            return Err(GrammarError::UnknownStrategy(rendered));
+        }
+    }
+    Ok(())
+}
+
+/// Domain of a set of integer parameters
+fn set(values: &[i32]) -> Domain {
+    Domain::Set(values.iter().map(|v| Param::Int(*v)).collect())
+}
+
+/// A gene expands into a `StrategyCall` with the strategy to run and the
+/// ticker and parameter to run it on
 #[derive(Debug, Clone)]
-pub struct Strategy {
+pub struct StrategyCall {
     pub chromosome_id: Uuid,
-    /// string derived from dna `llv:AAPL:2::gapupday:GOOG:10`
+    /// gene derived from dna `llv:AAPL:2`
     pub strategy: String,
     /// strategy code: `llv`
     pub code: String,
@@ -41,95 +134,81 @@ pub struct Strategy {
     pub generation: i32,
 }
 
-/// Gets the current quote and quotes from `n periods` ago
-#[derive(Debug, Clone)]
-pub struct Lag {
-    /// Quote `t^0 - n` ago
-    pub lag_quote: Quote,
-    /// Quote at `t^0`
-    pub current_quote: Quote,
-}
-
 /// Expands chromosome of strategies to a list of strategies
 ///
-/// ```
-/// llv:AAPL:2::gapupday:GOOG:10
+/// ```text
+/// (llv:AAPL:2 OR gapupday:GOOG:10) AND NOT hhv:MSFT:5
 ///
 /// Returns
 /// [
-///     Strategy {
+///     StrategyCall {
 ///         name: "llv",
 ///         ticker: "AAPL",
 ///         param: 2
 ///     },
-///     Strategy {
+///     StrategyCall {
 ///         name: "gapupday",
 ///         ticker: "GOOG",
 ///         param: 10
+///     },
+///     StrategyCall {
+///         name: "hhv",
+///         ticker: "MSFT",
+///         param: 5
 ///     }
 /// ]
 /// ```
-pub fn expand_strategies(chromosome: Chromosome) -> Vec<Strategy> {
-    let strategies: Vec<&str> = chromosome.chromosome.split("::").collect();
-    let expanded_strategies = strategies
+pub fn expand_strategies(chromosome: &Chromosome) -> Result<Vec<StrategyCall>, Error> {
+    chromosome
+        .chromosome
+        .genes()
         .into_iter()
-        .map(|s| expand_strategy(chromosome.clone(), s.to_string()))
-        .collect();
-    expanded_strategies
+        .map(|gene| expand_strategy(chromosome, gene))
+        .collect()
 }
 
-/// Expands chromosomes to Strategy
-pub fn expand_strategy(chromosome: Chromosome, strategy: String) -> Strategy {
-    let v: Vec<&str> = strategy.split(":").collect();
-    let strategy_name = strategy.clone();
+/// Expands gene to StrategyCall
+///
+/// Strategies take a single integer parameter
+pub fn expand_strategy(chromosome: &Chromosome, gene: &Gene) -> Result<StrategyCall, Error> {
+    let strategy_name = gene.to_string();
     debug!("{:?}", strategy_name);
-    Strategy {
+    let param = match gene.params.as_slice() {
+        [p] => p.as_int(),
+        _ => None,
+    };
+    let param = match param {
+        Some(p) if p > 0 => p,
+        _ => return Err(Error::InvalidGene(strategy_name)),
+    };
+    Ok(StrategyCall {
         chromosome_id: chromosome.id,
         strategy: strategy_name,
-        code: v[0].to_string(),
-        ticker: v[1].to_string(),
-        param: v[2].parse::<i32>().unwrap(),
+        code: gene.code.clone(),
+        ticker: gene.ticker.clone(),
+        param,
         target_ticker: chromosome.target_ticker.clone(),
         generation: chromosome.generation,
-    }
-}
-
-/// Inserts a new, empty signal if the signal does not exist
-fn insert_signal(
-    trade_signals: &mut BTreeMap<String, TradeSignal>,
-    window: &Window,
-    strategy: &Strategy,
-    signal: &i32,
-) {
-    let ts_string = window.current_quote.ts.to_string();
-    let trade_signal = match trade_signals.get(&ts_string) {
-        Some(s) => update_signal(s, strategy, signal),
-        None => init_trade_signal(strategy, &window, signal),
-    };
-    trade_signals.insert(ts_string, trade_signal);
+    })
 }
 
-/// Updates existing signal in btreemap
-fn update_signal(trade_signal: &TradeSignal, strategy: &Strategy, signal: &i32) -> TradeSignal {
-    let mut strategies = trade_signal.strategies.clone();
-    strategies.push(strategy.strategy.clone());
-    let mut signals = trade_signal.signals.clone();
-    signals.push(signal.clone());
-    TradeSignal {
-        chromosome_id: trade_signal.chromosome_id,
-        ts: trade_signal.ts,
-        strategies: strategies,
-        signals: signals,
-        target_ticker: trade_signal.target_ticker.clone(),
-        hard_signal: trade_signal.hard_signal,
-        generation: trade_signal.generation,
-        pnl: 0.0,
-        ret: 0.0,
+/// Inserts the signal of every quote with `lookback` quotes before it
+///
+/// `signal` is called with the index of the quote, which is on the day of
+/// the timeline at the same index of `days`.
+fn insert_signals<F: Fn(usize) -> i32>(signals: &mut GeneSignals, days: &[usize], lookback: usize, signal: F) {
+    for (i, day) in days.iter().enumerate().skip(lookback) {
+        signals.insert(*day, signal(i));
     }
 }
 
 /// Cast windows from list of quotes
 /// A window include quotes of length n prior to the current quote
+///
+/// Strategies compute their series in one pass with the rolling functions of
+/// `calc` instead. Windows are kept for the tests that check the series
+/// against the windows.
+#[cfg(test)]
 fn make_window(quotes: &Vec<Quote>, length: usize) -> Vec<Window> {
     let mut windows: Vec<Window> = vec![];
     for n in length..quotes.len() {
@@ -144,24 +223,62 @@ fn make_window(quotes: &Vec<Quote>, length: usize) -> Vec<Window> {
     windows
 }
 
-/// creates a vector of `Lags`
-/// 
-#[allow(dead_code)]
-fn build_lags(quotes: &Vec<Quote>, periods: usize) -> Vec<Lag> {
-    let mut lags: Vec<Lag> = vec![];
-    for n in periods..quotes.len() {
-        let lag_n = n - periods;
-        let lag_quote = &quotes[lag_n];
-        let new_lag = Lag {
-            lag_quote: lag_quote.clone(),
-            current_quote: quotes[n].clone(),
+/// Quotes of a random walk with gaps and repeated closes
+#[cfg(test)]
+pub fn random_walk(len: usize) -> Vec<Quote> {
+    let mut seed: u32 = 7;
+    let mut next = || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        ((seed >> 16) % 9) as f32 - 4.0
+    };
+    let mut close = 100.0;
+    (0..len)
+        .map(|i| {
+            let open = close + next() * 0.25;
+            close = open + next() * 0.5;
+            Quote {
+                ticker: "AAPL".to_string(),
+                ts: 1528745804.0 + i as f64 * 86400.0,
+                open,
+                high: open.max(close) + 1.0,
+                low: open.min(close) - 1.0,
+                close,
+                volume: 1000.0,
+            }
+        })
+        .collect()
+}
+
+/// Checks that a strategy gives the same signals as its windowed version
+///
+/// The quotes are a random walk so that ties get checked too.
+#[cfg(test)]
+fn assert_same_signals(strategy: &dyn Strategy, windowed: &dyn Fn(&Window, i32) -> i32) {
+    let quotes = random_walk(400);
+    for &param in &[1, 2, 3, 5, 10, 20, 60] {
+        let call = StrategyCall {
+            chromosome_id: Uuid::new_v4(),
+            strategy: format!("{}:AAPL:{}", strategy.name(), param),
+            code: strategy.name().to_string(),
+            ticker: "AAPL".to_string(),
+            param,
+            target_ticker: "SPY".to_string(),
+            generation: 1,
         };
-        lags.push(new_lag);
+        let columns = Quotes::new(&quotes);
+        let timeline = Timeline::new(vec![&columns]);
+        let gene = GeneSignals::new(&call, &columns, &timeline).unwrap();
+        let signals: Vec<(f64, Option<i32>)> = gene.days.ones().map(|day| (timeline.ts(day), gene.signal(day))).collect();
+        let expected: Vec<(f64, Option<i32>)> = make_window(&quotes, param as usize)
+            .iter()
+            .map(|w| (w.current_quote.ts, Some(windowed(w, param).signum())))
+            .collect();
+        assert_eq!(expected, signals, "{} with param {}", strategy.name(), param);
     }
-    lags
 }
 
 /// Takes diff of close
+#[cfg(test)]
 fn diff(quotes: &Vec<Quote>, lag: usize) -> Vec<f32> {
     let mut diffs: Vec<f32> = vec![];
     for n in lag..quotes.len() {
@@ -178,11 +295,15 @@ fn diff(quotes: &Vec<Quote>, lag: usize) -> Vec<f32> {
 
 #[cfg(test)]
 mod test {
+    use super::*;
+    use calc::std_dev;
+    use forge::{LegStats, Rule};
+
     #[test]
     fn test_expand_strategy() {
         let chromosome = Chromosome {
             id: Uuid::new_v4(),
-            chromosome: "llv:krakenUSD:2::hhv:coinbaseUSD:3".to_string(),
+            chromosome: Rule::parse("llv:krakenUSD:2::hhv:coinbaseUSD:3").unwrap(),
             target_ticker: "xlf".to_string(),
             dna: vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12],
             generation: 1,
@@ -197,9 +318,19 @@ mod test {
             winning_trades: 0,
             percentage_winners: 0.0,
             rank: 0,
+            valid: true,
+            long: LegStats::default(),
+            short: LegStats::default(),
+            max_drawdown: 0.0,
+            front: 0,
+            crowding_distance: 0.0,
+            island: 0,
+            behaviour: vec![],
+            parents: vec![],
+            penalty: 0.0,
         };
 
-        let expected = Strategy {
+        let expected = StrategyCall {
             chromosome_id: chromosome.id,
             strategy: "llv:krakenUSD:2".to_string(),
             code: String::from("llv"),
@@ -209,11 +340,61 @@ mod test {
             generation: chromosome.generation,
         };
 
-        let actual = expand_strategy(chromosome, "llv:krakenUSD:2".to_string());
+        let actual = expand_strategy(&chromosome, &"llv:krakenUSD:2".parse().unwrap()).unwrap();
 
         assert_eq!(expected.code, actual.code);
         assert_eq!(expected.ticker, actual.ticker);
         assert_eq!(expected.param, actual.param);
+
+        let strategies = expand_strategies(&chromosome).unwrap();
+        assert_eq!(2, strategies.len());
+        assert_eq!("hhv:coinbaseUSD:3", strategies[1].strategy);
+
+        let invalid = expand_strategy(&chromosome, &"llv:krakenUSD:2.5".parse().unwrap());
+        assert_eq!(Err(Error::InvalidGene("llv:krakenUSD:2.5".to_string())), invalid.map(|s| s.code));
+    }
+
+    #[test]
+    fn test_registry() {
+        let names: Vec<&str> = REGISTRY.iter().map(|s| s.name()).collect();
+        for name in &names {
+            assert_eq!(1, names.iter().filter(|n| n == &name).count());
+        }
+        assert_eq!(Some("abovema"), find("abovema").map(|s| s.name()));
+        assert!(find("nope").is_none());
+    }
+
+    #[test]
+    fn test_define_strategies() {
+        let mut grammar = Grammar::default();
+        define_strategies(&mut grammar).unwrap();
+        let stmnt: Vec<String> = grammar
+            .productions(grammar::STRATEGIES)
+            .unwrap()
+            .iter()
+            .map(|p| grammar::render(p))
+            .collect();
+        assert_eq!(REGISTRY.len(), stmnt.len());
+        assert_eq!("hhv:<ticker>:<int:5:500>", stmnt[0]);
+        assert_eq!("belowma:<ticker>:<set:5,10,20,50,100,200>", stmnt[11]);
+        // Every gene the grammar decodes to has a strategy
+        let tickers = vec!["AAPL".to_string()];
+        for i in 0..REGISTRY.len() as i32 {
+            let code = forge::decode_dna(&grammar, &vec![0, 0, i, 0, 0], &tickers, 0).unwrap();
+            let rule = Rule::parse(&code).unwrap();
+            assert!(find(&rule.genes()[0].code).is_some(), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_check_strategies() {
+        let grammar = Grammar::parse("<code> ::= <stmnt>\n<stmnt> ::= trend:<ticker>:<set:5,10> | hhv:<ticker>:<param>").unwrap();
+        assert_eq!(Ok(()), check_strategies(&grammar));
+        let grammar = Grammar::parse("<code> ::= <stmnt>\n<stmnt> ::= hhv:<ticker>:<param> | sma:<ticker>:<param>").unwrap();
+        assert_eq!(
+            Err(GrammarError::UnknownStrategy("sma:<ticker>:<param>".to_string())),
+            check_strategies(&grammar)
+        );
     }
 
     #[test]
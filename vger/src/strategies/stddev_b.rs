@@ -1,26 +1,52 @@
+use forge::grammar::Domain;
+#[cfg(test)]
 use repo::schemas::Quote;
-use std::collections::BTreeMap;
+use signals::GeneSignals;
+use store::Quotes;
 use strategies;
-use strategies::Strategy;
-use TradeSignal;
+use strategies::{Strategy, StrategyCall};
+#[cfg(test)]
 use Window;
 use calc;
 
+/// Close to close change between 1 and 2 standard deviations
+pub struct StdDevB;
+
+impl Strategy for StdDevB {
+    fn name(&self) -> &'static str {
+        "stdevb"
+    }
+
+    fn domain(&self) -> Domain {
+        strategies::set(&[5, 10, 20, 30, 60, 90, 120, 250])
+    }
+
+    fn call(&self, strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+        call(strategy, quotes, days, signals)
+    }
+}
+
 /// Standard deviation above 1 sigma and less than 2 sigmas
 ///
 ///
-pub fn call(
-    strategy: Strategy,
-    trade_signals: &mut BTreeMap<String, TradeSignal>,
-    quotes: &Vec<Quote>,
-) {
-    let windows = strategies::make_window(quotes, strategy.param as usize);
-    for w in windows {
-        let signal = generator(&w);
-        strategies::insert_signal(trade_signals, &w, &strategy, &signal);
-    }
+pub fn call(strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+    let lookback = strategy.param as usize;
+    // diffs[i - 1] is the change into quote i, so the window before quote i
+    // holds the lookback - 1 changes from diffs[i - lookback]
+    let diffs = calc::diff(&quotes.close, 1);
+    let std_devs = calc::rolling_std(&diffs, lookback - 1);
+    strategies::insert_signals(signals, days, lookback, |i| {
+        let std_dev = std_devs[i - lookback];
+        let current_diff = diffs[i - 1];
+        if current_diff < (std_dev * 2.0) && current_diff >= std_dev {
+            1
+        } else {
+            0
+        }
+    });
 }
 
+#[cfg(test)]
 fn generator(window: &Window) -> i32 {
     let close_diffs: Vec<f32> = strategies::diff(&window.window, 1);
     let std_dev = calc::std_dev(close_diffs);
@@ -109,3 +135,8 @@ fn test_std_dev_b() {
     let signal = generator(&third_window);
     assert_eq!(0, signal);
 }
+
+#[test]
+fn test_std_dev_b_matches_windows() {
+    strategies::assert_same_signals(&StdDevB, &|w, _| generator(w));
+}
@@ -0,0 +1,85 @@
+//! Trend
+//!
+//! Goes long when the close is above the moving average of length param and
+//! short when it is below
+use forge::grammar::Domain;
+#[cfg(test)]
+use repo::schemas::Quote;
+use signals::GeneSignals;
+use store::Quotes;
+use strategies;
+use strategies::{Strategy, StrategyCall};
+use calc;
+
+/// Follows the close around the moving average of the last `param` closes
+pub struct Trend;
+
+impl Strategy for Trend {
+    fn name(&self) -> &'static str {
+        "trend"
+    }
+
+    fn domain(&self) -> Domain {
+        strategies::set(&[5, 10, 20, 50, 100, 200])
+    }
+
+    fn call(&self, strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+        call(strategy, quotes, days, signals)
+    }
+}
+
+/// Trend
+///
+/// 1 above the moving average, -1 below it and 0 on it
+pub fn call(strategy: &StrategyCall, quotes: &Quotes, days: &[usize], signals: &mut GeneSignals) {
+    let lookback = strategy.param as usize;
+    let mas = calc::rolling_mean(&quotes.close, lookback);
+    strategies::insert_signals(signals, days, lookback, |i| {
+        let ma = mas[i - lookback];
+        if quotes.close[i] > ma {
+            1
+        } else if quotes.close[i] < ma {
+            -1
+        } else {
+            0
+        }
+    });
+}
+
+#[cfg(test)]
+fn trend(window: &strategies::Window) -> i32 {
+    let closes: Vec<f32> = window.window.iter().map(|quote| quote.close).collect();
+    let ma = calc::average(closes);
+    if window.current_quote.close > ma {
+        1
+    } else if window.current_quote.close < ma {
+        -1
+    } else {
+        0
+    }
+}
+
+#[test]
+fn test_trend() {
+    let quotes: Vec<Quote> = [99.0, 99.0, 101.0, 102.0, 100.0, 99.0, 100.0, 100.0, 100.0]
+        .iter()
+        .enumerate()
+        .map(|(i, close)| Quote {
+            ticker: "AAPL".to_string(),
+            ts: 1528745804.0 + i as f64,
+            open: 100.00,
+            high: 105.00,
+            low: 99.00,
+            close: *close,
+            volume: 1000.0,
+        })
+        .collect();
+    let windows = strategies::make_window(&quotes, 3);
+    let signals: Vec<i32> = windows.iter().map(trend).collect();
+    assert_eq!(vec![1, -1, -1, -1, 1, 1], signals);
+}
+
+#[test]
+fn test_trend_matches_windows() {
+    strategies::assert_same_signals(&Trend, &|w, _| trend(w));
+}
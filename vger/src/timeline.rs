@@ -0,0 +1,102 @@
+//! Global index of timestamps
+//!
+//! Every ticker's quotes and the target returns are placed on one timeline,
+//! the sorted timestamps of all the quotes. Signals and returns are then
+//! vectors indexed by day of the timeline, which lines up tickers without
+//! looking timestamps up by string.
+use repo::schemas::Return;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use store::Quotes;
+
+/// Sorted timestamps of every quote
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    ts: Vec<f64>,
+    /// Days by the bits of their timestamps
+    days: HashMap<u64, usize>,
+}
+
+impl Timeline {
+    /// Timeline of the quotes of every ticker
+    pub fn new<'a, I: IntoIterator<Item = &'a Quotes>>(quotes: I) -> Timeline {
+        let mut ts: Vec<f64> = quotes.into_iter().flat_map(|q| q.ts.iter().cloned()).collect();
+        ts.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+        ts.dedup();
+        let days = ts.iter().enumerate().map(|(day, t)| (t.to_bits(), day)).collect();
+        Timeline { ts, days }
+    }
+
+    /// Number of days
+    pub fn len(&self) -> usize {
+        self.ts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ts.is_empty()
+    }
+
+    /// Timestamp of a day
+    pub fn ts(&self, day: usize) -> f64 {
+        self.ts[day]
+    }
+
+    /// Day of a timestamp, if any quote has it
+    pub fn day(&self, ts: f64) -> Option<usize> {
+        self.days.get(&ts.to_bits()).cloned()
+    }
+
+    /// Days of a ticker's quotes, in the order of the quotes
+    pub fn days(&self, quotes: &Quotes) -> Vec<usize> {
+        quotes
+            .ts
+            .iter()
+            .map(|ts| self.day(*ts).expect("quote missing from the timeline"))
+            .collect()
+    }
+
+    /// Returns by day. Days without a return have a return of 0
+    pub fn returns(&self, returns: &BTreeMap<String, Return>) -> Vec<f32> {
+        let mut by_day = vec![0.0; self.len()];
+        for ret in returns.values() {
+            if let Some(day) = self.day(ret.ts) {
+                by_day[day] = ret.ret;
+            }
+        }
+        by_day
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use repo::schemas::Quote;
+
+    fn quote(ticker: &str, ts: f64) -> Quote {
+        Quote {
+            ticker: ticker.to_string(),
+            ts,
+            open: 1.0,
+            high: 1.0,
+            low: 1.0,
+            close: 1.0,
+            volume: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_timeline() {
+        let aapl = Quotes::new(&[quote("AAPL", 3.0), quote("AAPL", 1.0)]);
+        let msft = Quotes::new(&[quote("MSFT", 2.0), quote("MSFT", 3.0)]);
+        let timeline = Timeline::new(vec![&aapl, &msft]);
+        assert_eq!(3, timeline.len());
+        assert_eq!(vec![2, 0], timeline.days(&aapl));
+        assert_eq!(vec![1, 2], timeline.days(&msft));
+        assert_eq!(None, timeline.day(4.0));
+        let mut returns = BTreeMap::new();
+        for (ts, ret) in [(2.0, 0.5), (4.0, 0.25)] {
+            returns.insert(ts.to_string(), Return { ts, ret });
+        }
+        assert_eq!(vec![0.0, 0.5, 0.0], timeline.returns(&returns));
+    }
+}
@@ -8,7 +8,8 @@ extern crate log;
 extern crate repo;
 
 use forge::Chromosome;
-use std::collections::BTreeMap;
+use forge::LegStats;
+use forge::StopReason;
 // use std::fs;
 use std::fs::File;
 use std::io::{self, Write};
@@ -16,7 +17,10 @@ use vger::TradeSignal;
 
 /// Write chromosomes to disk
 ///
-/// Writes chromosomes to disk as a tab delimited csv
+/// Writes chromosomes to disk as a tab delimited csv. The summary data for
+/// the long leg and then the short leg follow the rank, then max drawdown,
+/// Pareto front, crowding distance and the island the chromosome lives on.
+/// Chromosomes with a front of 1 make up the Pareto front of the generation.
 ///
 /// # Usage
 /// ```
@@ -29,11 +33,27 @@ pub fn write_chromosomes(chromosomes: &Vec<Chromosome>, generation: i32, backtes
   io::stdout().flush().unwrap();
   let filename = format!("/tmp/{}_generation_{}.txt", backtest_id, generation);
   let mut f = File::create(filename).expect("Unable to create file");
+  write_chromosome_rows(&mut f, chromosomes, backtest_id);
+}
+
+/// Write the hall of fame to disk
+///
+/// Writes the best chromosomes of a run in the format of the generation
+/// files, with the fittest last.
+pub fn write_hall_of_fame(chromosomes: &[Chromosome], backtest_id: &String) {
+  debug!("writing hall of fame to disk");
+  let filename = format!("/tmp/{}_hall_of_fame.txt", backtest_id);
+  let mut f = File::create(filename).expect("Unable to create file");
+  write_chromosome_rows(&mut f, chromosomes, backtest_id);
+}
+
+/// Write a row per chromosome
+fn write_chromosome_rows(f: &mut File, chromosomes: &[Chromosome], backtest_id: &String) {
   for chromosome in chromosomes {
     let c = chromosome;
     write!(
       f,
-      "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+      "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
       backtest_id,
       c.id,
       c.target_ticker,
@@ -50,12 +70,46 @@ pub fn write_chromosomes(chromosomes: &Vec<Chromosome>, generation: i32, backtes
       c.winning_trades,
       c.losing_trades,
       c.percentage_winners,
-      c.rank
+      c.rank,
+      fmt_leg_stats(&c.long),
+      fmt_leg_stats(&c.short),
+      c.max_drawdown,
+      c.front,
+      c.crowding_distance,
+      c.island
     )
     .unwrap();
   }
 }
 
+/// Write why a run stopped
+///
+/// Writes the backtest id, the last generation of the run and the stop
+/// reason as one tab delimited line next to the generation files.
+pub fn write_stop_reason(reason: &StopReason, generation: i32, backtest_id: &String) {
+  debug!("writing stop reason to disk");
+  let filename = format!("/tmp/{}_stop_reason.txt", backtest_id);
+  let mut f = File::create(filename).expect("Unable to create file");
+  writeln!(f, "{}\t{}\t{}", backtest_id, generation, reason).expect("Unable to write data");
+}
+
+/// Format leg stats
+///
+/// Formats the summary data of a leg as tab delimited columns
+fn fmt_leg_stats(leg: &LegStats) -> String {
+  format!(
+    "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+    leg.num_of_trades,
+    leg.cum_pnl,
+    leg.mean_return,
+    leg.variance,
+    leg.kelly,
+    leg.winning_trades,
+    leg.losing_trades,
+    leg.percentage_winners
+  )
+}
+
 /// Format vector of String
 ///
 /// Formats the vector to be readable by postgresql as an array
@@ -89,15 +143,16 @@ fn fmt_vec_dna(dna: Vec<i32>) -> String {
 }
 
 /// Write signals to disk
-pub fn write_signals(
-  signals: &BTreeMap<String, TradeSignal>,
+///
+/// Signals are written one per line in the order they come in.
+pub fn write_signals<I: IntoIterator<Item = TradeSignal>>(
+  signals: I,
   chromosome: &Chromosome,
   backtest_id: String,
 ) {
   log_write_signals(chromosome);
   let (_filename, mut f) = create_file(chromosome);
-  for signal in signals {
-    let s = signal.1;
+  for s in signals {
     debug!("writing signal: {:?} to disk", &s);
     write!(
       f,